@@ -3,7 +3,7 @@ use std::rc::Rc;
 
 use glam::{Mat3, Mat4, Vec2, Vec3};
 
-use super::{Control, ControlId, ControlIdPartType, ControlSetBuilder, Globals, Size2D};
+use super::{Control, ControlId, ControlIdPartType, ControlSetBuilder, CubeFace, Globals, Size2D};
 
 pub struct Camera {
     target: Rc<Control>,
@@ -104,4 +104,29 @@ impl Camera {
 
         globals.update_compound_matrices();
     }
+
+    /// Sets the globals for rendering a cube face from the position of the camera,
+    /// with a 90° field of view looking through the face.
+    pub fn set_cube_face_globals(
+        &self,
+        globals: &mut Globals,
+        face: CubeFace,
+        canvas_size: Size2D,
+    ) {
+        self.set_globals(globals, canvas_size);
+        let eye = globals.camera_from_world.inverse().transform_point3(Vec3::ZERO);
+        let (direction, up) = face.view_direction_and_up();
+
+        globals.aspect_ratio = 1.0;
+        globals.field_of_view = PI / 2.0;
+        globals.projection_from_camera = Mat4::perspective_infinite_lh(
+            globals.field_of_view,
+            globals.aspect_ratio,
+            globals.z_near,
+        );
+        globals.camera_from_world = Mat4::look_to_lh(eye, direction, up);
+        globals.light_dir_camspace_norm =
+            Mat3::from_mat4(globals.camera_from_world) * globals.light_dir_worldspace_norm;
+        globals.update_compound_matrices();
+    }
 }
@@ -5,32 +5,44 @@ use std::sync::Arc;
 use anyhow::{bail, Result};
 
 use super::{
-    BitangImage, Camera, Compute, ComputePassContext, ControlId, ControlIdPartType, ControlSet,
-    ControlSetBuilder, Draw, FrameContext, GenerateMipLevels, Run, SIMULATION_STEP_SECONDS,
+    BitangImage, Camera, Compute, ComputePassContext, ControlId, ControlIdPartType,
+    ControlRepository, ControlSet, ControlSetBuilder, Draw, ErrorPattern, FrameContext,
+    GenerateMipLevels, GpuContext, PrefilterEnvironment, Run, Sort, StorageBuffer,
+    SIMULATION_STEP_SECONDS,
 };
 
 pub enum ChartStep {
     Draw(Draw),
     Compute(Compute),
     GenerateMipLevels(GenerateMipLevels),
+    Sort(Sort),
+    PrefilterEnvironment(PrefilterEnvironment),
+    ErrorPattern(ErrorPattern),
 }
 
 pub struct Chart {
     pub id: String,
+
+    /// The id of the chart this is an instance of
+    pub base_id: Option<String>,
     pub controls: Rc<ControlSet>,
     camera: Camera,
     images: Vec<Arc<BitangImage>>,
+    buffers: Vec<Rc<StorageBuffer>>,
     pub steps: Vec<ChartStep>,
 
     simulation_cursor: RefCell<SimulationCursor>,
 }
 
 impl Chart {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: &str,
+        base_id: Option<&str>,
         control_id: &ControlId,
         control_set_builder: ControlSetBuilder,
         images: Vec<Arc<BitangImage>>,
+        buffers: Vec<Rc<StorageBuffer>>,
         steps: Vec<ChartStep>,
         simulation_precalculation_time: f32,
     ) -> Self {
@@ -44,19 +56,44 @@ impl Chart {
                 ChartStep::Draw(draw) => draw.id.clone(),
                 ChartStep::Compute(compute) => compute.id.clone(),
                 ChartStep::GenerateMipLevels(genmips) => genmips._id.clone(),
+                ChartStep::Sort(sort) => sort.id.clone(),
+                ChartStep::PrefilterEnvironment(prefilter) => prefilter.id.clone(),
+                ChartStep::ErrorPattern(error_pattern) => error_pattern.id.clone(),
             })
             .collect::<Vec<String>>();
         let controls = Rc::new(control_set_builder.into_control_set(&chart_step_ids));
         Chart {
             id: id.to_string(),
+            base_id: base_id.map(str::to_string),
             camera: _camera,
             images,
+            buffers,
             steps,
             controls,
             simulation_cursor: RefCell::new(SimulationCursor::new(simulation_precalculation_time)),
         }
     }
 
+    /// Creates a chart that only draws an error pattern.
+    ///
+    /// Stands in for a chart that failed to load and has no previously loaded version.
+    /// Its controls are not added to the project's control repository.
+    pub fn new_placeholder(context: &GpuContext, id: &str, base_id: Option<&str>) -> Self {
+        let control_id = ControlId::default().add(ControlIdPartType::Chart, id);
+        let control_set_builder =
+            ControlSetBuilder::new(control_id.clone(), Rc::new(ControlRepository::default()));
+        Self::new(
+            id,
+            base_id,
+            &control_id,
+            control_set_builder,
+            vec![],
+            vec![],
+            vec![ChartStep::ErrorPattern(ErrorPattern::new(context, "error"))],
+            0.0,
+        )
+    }
+
     /// Reruns the initialization step and runs the simulation for the precalculation time.
     pub fn reset_simulation(&self, context: &mut ComputePassContext) -> Result<()> {
         self.initialize(context)?;
@@ -75,7 +112,7 @@ impl Chart {
         self.evaluate_splines(sim_time);
         for step in &self.steps {
             if let ChartStep::Compute(compute) = step {
-                if let Run::Init(_) = compute.run {
+                if let Run::Init = compute.run {
                     compute.execute(context)?;
                 }
             }
@@ -149,7 +186,17 @@ impl Chart {
                 pass: compute_pass,
                 globals: &mut context.globals,
             };
-            self.simulate(&mut compute_pass_context, false)?;
+
+            // Resized buffers lose their content, so the simulation starts over
+            let mut is_resized = false;
+            for buffer in &self.buffers {
+                is_resized |= buffer.update_size(&context.gpu_context);
+            }
+            if is_resized {
+                self.reset_simulation(&mut compute_pass_context)?;
+            } else {
+                self.simulate(&mut compute_pass_context, false)?;
+            }
         }
 
         // Render step
@@ -160,14 +207,42 @@ impl Chart {
         for step in &self.steps {
             match step {
                 ChartStep::Draw(draw) => {
+                    if draw.run_once.as_ref().is_some_and(|run_once| !run_once.needs_run()) {
+                        continue;
+                    }
                     draw.render(context, &self.camera)?;
                 }
-                ChartStep::Compute(_) => {
-                    // Compute only runs simulation or init, no need to do anything during render.
+                ChartStep::Compute(compute) => {
+                    // Init and simulation computes run in the simulation step
+                    let should_run = match &compute.run {
+                        Run::Frame => true,
+                        Run::Once(run_once) => run_once.needs_run(),
+                        Run::Init | Run::Simulate(_) => false,
+                    };
+                    if should_run {
+                        let compute_pass = context
+                            .command_encoder
+                            .begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                        let mut compute_pass_context = ComputePassContext {
+                            gpu_context: &context.gpu_context,
+                            pass: compute_pass,
+                            globals: &mut context.globals,
+                        };
+                        compute.execute(&mut compute_pass_context)?;
+                    }
                 }
                 ChartStep::GenerateMipLevels(genmips) => {
                     genmips.execute(context)?;
                 }
+                ChartStep::Sort(sort) => {
+                    sort.execute(context)?;
+                }
+                ChartStep::PrefilterEnvironment(prefilter) => {
+                    prefilter.execute(context)?;
+                }
+                ChartStep::ErrorPattern(error_pattern) => {
+                    error_pattern.execute(context)?;
+                }
             }
         }
         Ok(())
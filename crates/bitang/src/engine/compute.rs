@@ -2,12 +2,20 @@ use std::rc::Rc;
 
 use anyhow::Result;
 
-use super::{ComputeCall, ComputePassContext, DoubleBuffer, GpuContext, Shader};
+use super::{
+    ComputeCall, ComputePassContext, DispatchSize, GpuContext, RunOnce, Shader, StorageBuffer,
+};
 
 // TODO: rename to "stage" or similar
 pub enum Run {
-    Init(Rc<DoubleBuffer>),
-    Simulate(Rc<DoubleBuffer>),
+    Init,
+    Simulate(Rc<StorageBuffer>),
+
+    /// Runs every frame in the order of the chart steps
+    Frame,
+
+    /// Runs in the order of the chart steps when the chart is loaded or its images change
+    Once(RunOnce),
 }
 
 /// Represents a compute step in the chart sequence.
@@ -18,12 +26,14 @@ pub struct Compute {
 }
 
 impl Compute {
-    pub fn new(context: &GpuContext, id: &str, shader: Shader, run: Run) -> Result<Compute> {
-        let invocation_count = match &run {
-            Run::Init(buffer) => buffer.item_count,
-            Run::Simulate(buffer) => buffer.item_count,
-        };
-        let compute_call = ComputeCall::new(context, id, shader, invocation_count)?;
+    pub fn new(
+        context: &GpuContext,
+        id: &str,
+        shader: Shader,
+        run: Run,
+        dispatch_size: DispatchSize,
+    ) -> Result<Compute> {
+        let compute_call = ComputeCall::new(context, id, shader, dispatch_size)?;
         Ok(Compute {
             id: id.to_string(),
             run,
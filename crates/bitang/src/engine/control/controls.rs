@@ -20,6 +20,11 @@ use crate::loader::CHARTS_FOLDER;
 
 const CONTROLS_FILE_NAME: &str = "controls.ron";
 
+/// Controls of a chart instance are stored next to the controls of the chart in
+/// "controls.<instance id>.ron".
+const CONTROLS_FILE_PREFIX: &str = "controls.";
+const CONTROLS_FILE_EXTENSION: &str = ".ron";
+
 #[derive(Default)]
 pub struct UsedControlsNode {
     pub id_prefix: ControlId,
@@ -74,6 +79,18 @@ impl UsedControlsNode {
 pub struct ControlSet {
     pub used_controls: Vec<Rc<Control>>,
     pub root_node: RefCell<UsedControlsNode>,
+
+    /// The component counts of `used_controls` at the time the set was built.
+    used_component_counts: Vec<usize>,
+}
+
+impl ControlSet {
+    /// Marks the controls as used again after a failed load cycle reset their usage counts.
+    pub fn restore_component_usage_counts(&self) {
+        for (control, &count) in self.used_controls.iter().zip(&self.used_component_counts) {
+            control.used_component_count.set(max(control.used_component_count.get(), count));
+        }
+    }
 }
 
 /// Builder for `ControlSet`, used during project loading.
@@ -82,6 +99,9 @@ pub struct ControlSetBuilder {
     used_controls: RefCell<AHashSet<RcHashRef<Control>>>,
     used_control_list: RefCell<Vec<Rc<Control>>>,
     root_id: ControlId,
+
+    /// Root id of the chart this is an instance of. Controls fall back to the controls under it.
+    base_root_id: Option<ControlId>,
 }
 
 impl ControlSetBuilder {
@@ -91,6 +111,20 @@ impl ControlSetBuilder {
             control_repository,
             used_controls: RefCell::new(AHashSet::new()),
             used_control_list: RefCell::new(vec![]),
+            base_root_id: None,
+        }
+    }
+
+    /// Creates a builder for a chart instance whose controls fall back to the controls of the
+    /// base chart until they are overridden.
+    pub fn new_instance(
+        root_id: ControlId,
+        base_root_id: ControlId,
+        control_repository: Rc<ControlRepository>,
+    ) -> Self {
+        Self {
+            base_root_id: Some(base_root_id),
+            ..Self::new(root_id, control_repository)
         }
     }
 
@@ -105,9 +139,12 @@ impl ControlSetBuilder {
             root_node.insert(control.clone(), chart_step_ids);
             controls.push(control);
         }
+        let used_component_counts =
+            controls.iter().map(|control| control.used_component_count.get()).collect();
         ControlSet {
             used_controls: controls,
             root_node: RefCell::new(root_node),
+            used_component_counts,
         }
     }
 
@@ -146,7 +183,14 @@ impl ControlSetBuilder {
         component_count: usize,
         default: &[f32; 4],
     ) -> Rc<Control> {
-        let control = self.control_repository.get_control(id, default);
+        let control = match &self.base_root_id {
+            Some(base_root_id) => {
+                let mut base_id = base_root_id.clone();
+                base_id.parts.extend_from_slice(&id.parts[self.root_id.parts.len()..]);
+                self.control_repository.get_instance_control(id, &base_id, default)
+            }
+            None => self.control_repository.get_control(id, default),
+        };
         control.used_component_count.set(max(control.used_component_count.get(), component_count));
         if self.used_controls.borrow_mut().insert(RcHashRef(control.clone())) {
             self.used_control_list.borrow_mut().push(control.clone());
@@ -155,6 +199,7 @@ impl ControlSetBuilder {
     }
 }
 
+#[derive(Default)]
 pub struct ControlRepository {
     by_id: DashMap<ControlId, Rc<Control>>,
 }
@@ -183,14 +228,44 @@ impl ControlRepository {
         }
     }
 
+    /// Returns the control of a chart instance. New controls follow the control of the base chart.
+    fn get_instance_control(
+        &self,
+        id: &ControlId,
+        base_id: &ControlId,
+        default: &[f32; 4],
+    ) -> Rc<Control> {
+        let base_control = self.get_control(base_id, default);
+        let control = match self.by_id.entry(id.clone()) {
+            Occupied(x) => x.get().clone(),
+            Vacant(entry) => {
+                let control = Rc::new(Control::new(id.clone(), default));
+                control.is_overridden.set(false);
+                entry.insert(control.clone());
+                control
+            }
+        };
+        *control.fallback.borrow_mut() = Some(base_control);
+        control
+    }
+
     pub fn save_control_files(&self, project: &Project) -> Result<()> {
         for chart in project.charts_by_id.values() {
-            let path =
-                project.root_path.join(CHARTS_FOLDER).join(&chart.id).join(CONTROLS_FILE_NAME);
+            let path = match &chart.base_id {
+                Some(base_id) => project.root_path.join(CHARTS_FOLDER).join(base_id).join(format!(
+                    "{CONTROLS_FILE_PREFIX}{}{CONTROLS_FILE_EXTENSION}",
+                    chart.id
+                )),
+                None => {
+                    project.root_path.join(CHARTS_FOLDER).join(&chart.id).join(CONTROLS_FILE_NAME)
+                }
+            };
+            // Instance controls that follow the base chart are not saved
             let controls = self
                 .by_id
                 .iter()
                 .filter(|it| it.id.parts[0].part_type == Chart && it.id.parts[0].name == chart.id)
+                .filter(|it| it.is_overridden.get())
                 .map(|it| it.value().clone())
                 .collect();
             let serialized = SerializedControls { controls };
@@ -211,29 +286,53 @@ impl ControlRepository {
             let path = entry.path();
             if path.is_dir() {
                 let chart_id = path.file_name().unwrap().to_str().unwrap();
-                let controls_path =
-                    root_path.join(CHARTS_FOLDER).join(chart_id).join(CONTROLS_FILE_NAME);
-                if let Ok(ron) = std::fs::read_to_string(&controls_path) {
-                    info!("Loading {controls_path:?}.");
-                    let deserialized: DeserializedControls = ron::de::from_str(&ron)?;
-                    for mut control in deserialized.controls {
-                        control.id.parts.insert(
-                            0,
-                            ControlIdPart {
-                                part_type: Chart,
-                                name: chart_id.to_string(),
-                            },
-                        );
-                        by_id.insert(control.id.clone(), Rc::new(control));
-                    }
+                let controls_path = path.join(CONTROLS_FILE_NAME);
+                if controls_path.exists() {
+                    Self::load_control_file(&controls_path, chart_id, &by_id)?;
                 } else {
                     warn!("No controls file found at {controls_path:?}.");
                 }
+
+                // Controls of the instances of the chart
+                for entry in std::fs::read_dir(&path)? {
+                    let entry = entry?;
+                    let file_name = entry.file_name();
+                    let Some(instance_id) = file_name
+                        .to_str()
+                        .and_then(|name| name.strip_prefix(CONTROLS_FILE_PREFIX))
+                        .and_then(|name| name.strip_suffix(CONTROLS_FILE_EXTENSION))
+                    else {
+                        continue;
+                    };
+                    Self::load_control_file(&entry.path(), instance_id, &by_id)?;
+                }
             }
         }
         Ok(Self { by_id })
     }
 
+    fn load_control_file(
+        path: &Path,
+        chart_id: &str,
+        by_id: &DashMap<ControlId, Rc<Control>>,
+    ) -> Result<()> {
+        info!("Loading {path:?}.");
+        let ron = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read controls from {path:?}."))?;
+        let deserialized: DeserializedControls = ron::de::from_str(&ron)?;
+        for mut control in deserialized.controls {
+            control.id.parts.insert(
+                0,
+                ControlIdPart {
+                    part_type: Chart,
+                    name: chart_id.to_string(),
+                },
+            );
+            by_id.insert(control.id.clone(), Rc::new(control));
+        }
+        Ok(())
+    }
+
     pub fn reset_component_usage_counts(&self) {
         for it in self.by_id.iter() {
             it.value().used_component_count.set(0);
@@ -252,6 +351,18 @@ pub struct Control {
 
     #[serde(skip)]
     pub used_component_count: Cell<usize>,
+
+    /// The control of the base chart, if this control belongs to a chart instance
+    #[serde(skip)]
+    pub fallback: RefCell<Option<Rc<Control>>>,
+
+    /// False if the control follows its fallback control
+    #[serde(skip, default = "default_overridden")]
+    pub is_overridden: Cell<bool>,
+}
+
+fn default_overridden() -> Cell<bool> {
+    Cell::new(true)
 }
 
 fn serialize_control_id<S>(id: &ControlId, s: S) -> Result<S::Ok, S::Error>
@@ -279,7 +390,7 @@ where
     Ok(ControlId { parts })
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ControlComponent {
     pub value: f32,
     pub spline: Spline,
@@ -296,7 +407,19 @@ impl Control {
                 use_spline: false,
             })),
             used_component_count: Cell::new(0),
+            fallback: RefCell::new(None),
+            is_overridden: Cell::new(true),
+        }
+    }
+
+    /// Overriding starts from the current state of the fallback control.
+    pub fn set_overridden(&self, is_overridden: bool) {
+        if is_overridden && !self.is_overridden.get() {
+            if let Some(fallback) = &*self.fallback.borrow() {
+                *self.components.borrow_mut() = fallback.components.borrow().clone();
+            }
         }
+        self.is_overridden.set(is_overridden);
     }
 
     pub fn set(&self, value: &[f32; 4]) {
@@ -307,6 +430,20 @@ impl Control {
     }
 
     pub fn evaluate_splines(&self, time: f32) {
+        if !self.is_overridden.get() {
+            if let Some(fallback) = &*self.fallback.borrow() {
+                let fallback_components = fallback.components.borrow();
+                let mut components = self.components.borrow_mut();
+                for (component, fallback) in components.iter_mut().zip(fallback_components.iter()) {
+                    component.value = if fallback.use_spline {
+                        fallback.spline.get_value(time)
+                    } else {
+                        fallback.value
+                    };
+                }
+                return;
+            }
+        }
         let mut components = self.components.borrow_mut();
         for component in components.iter_mut() {
             if component.use_spline {
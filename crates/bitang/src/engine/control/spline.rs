@@ -3,12 +3,12 @@ use std::cmp::min;
 use serde::{Deserialize, Serialize};
 
 // Plain Catmull-Rom spline ffs
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Spline {
     pub points: Vec<SplinePoint>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct SplinePoint {
     pub time: f32,
     pub value: f32,
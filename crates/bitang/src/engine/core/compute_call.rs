@@ -1,7 +1,40 @@
-use anyhow::Result;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
 
 use super::context::{ComputePassContext, GpuContext};
+use super::image::BitangImage;
 use super::shader::Shader;
+use super::storage_buffer::{BufferSlot, StorageBuffer};
+
+/// The number of invocations of a compute shader in each dimension.
+pub enum DispatchSize {
+    /// One invocation per buffer item, evaluated at every dispatch
+    Buffer(Rc<StorageBuffer>),
+
+    /// One invocation per pixel of the image, evaluated at every dispatch
+    Image(Arc<BitangImage>),
+
+    Fixed([u32; 3]),
+
+    /// Workgroup counts read from a buffer as `DispatchIndirectArgs`, written by compute shaders
+    Indirect(Rc<StorageBuffer>),
+}
+
+impl DispatchSize {
+    fn get_invocation_count(&self) -> Result<[u32; 3]> {
+        match self {
+            DispatchSize::Buffer(buffer) => Ok([buffer.item_count().try_into()?, 1, 1]),
+            DispatchSize::Image(image) => {
+                let [width, height] = image.get_size()?;
+                Ok([width, height, 1])
+            }
+            DispatchSize::Fixed(size) => Ok(*size),
+            DispatchSize::Indirect(_) => bail!("Indirect dispatch size is only known on the GPU"),
+        }
+    }
+}
 
 /// Represents a compute shader with all necessary resources.
 pub struct ComputeCall {
@@ -9,23 +42,23 @@ pub struct ComputeCall {
     pub id: String,
     shader: Shader,
     pipeline: wgpu::ComputePipeline,
-    dispatch_count: u32,
+    dispatch_size: DispatchSize,
 }
 
 impl ComputeCall {
     /// Creates a new compute shader call.
     ///
-    /// `invocation_count` - The number of invocations. The compute shader will run _AT_LEAST_ this
+    /// `dispatch_size` - The number of invocations. The compute shader will run _AT_LEAST_ this
     /// many times. Note: the actual number of invocations may be higher since it's a multiple of the workgroup size.
     /// It's the responsibility of the actual compute shader code to handle this. For example: if you want to
-    /// calculate something for each item in a buffer which has 100 items, then `invocation_count` should be 100.
+    /// calculate something for each item in a buffer which has 100 items, then `dispatch_size` should be 100.
     /// If the the workgroup size is 64, the compute shader will run 128 times since that's the smallest multiple of 64
-    /// that is greater than or equal to 100.
+    /// that is greater than or equal to 100. The workgroup size is taken from the shader.
     pub fn new(
         context: &GpuContext,
         id: &str,
         shader: Shader,
-        invocation_count: usize,
+        dispatch_size: DispatchSize,
     ) -> Result<ComputeCall> {
         let pipeline_layout =
             context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -42,22 +75,26 @@ impl ComputeCall {
             cache: None,
         });
 
-        // TODO: use the actual workgroup size
-        const WORKGROUP_SIZE: usize = 64;
-        let dispatch_count =
-            ((invocation_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE).try_into()?;
         Ok(ComputeCall {
             id: id.to_string(),
             shader,
             pipeline,
-            dispatch_count,
+            dispatch_size,
         })
     }
 
     pub fn execute(&self, context: &mut ComputePassContext<'_>) -> Result<()> {
         context.pass.set_pipeline(&self.pipeline);
         self.shader.bind_to_compute_pass(context)?;
-        context.pass.dispatch_workgroups(self.dispatch_count, 1, 1);
+        if let DispatchSize::Indirect(buffer) = &self.dispatch_size {
+            let indirect_buffer = buffer.get_buffer(BufferSlot::Current);
+            context.pass.dispatch_workgroups_indirect(&indirect_buffer, 0);
+            return Ok(());
+        }
+        let invocation_count = self.dispatch_size.get_invocation_count()?;
+        let [x, y, z] = [0, 1, 2]
+            .map(|axis| invocation_count[axis].div_ceil(self.shader.workgroup_size[axis].max(1)));
+        context.pass.dispatch_workgroups(x, y, z);
         Ok(())
     }
 }
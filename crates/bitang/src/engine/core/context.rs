@@ -5,36 +5,65 @@ use smallvec::SmallVec;
 
 use super::globals::Globals;
 use super::image::BitangImage;
+use super::shader::ShaderBindGroup;
+use super::uniform_ring::UniformRing;
 use super::Size2D;
 
 pub struct GpuContext {
-    #[allow(dead_code)]
     pub adapter: wgpu::Adapter,
     pub queue: wgpu::Queue,
     pub device: wgpu::Device,
     pub final_render_target: Arc<BitangImage>,
+    pub uniform_ring: UniformRing,
 }
 
 impl GpuContext {
-    pub async fn _new_for_offscreen(final_render_target: Arc<BitangImage>) -> Result<Self> {
+    /// The features every device has, the project check assumes only these
+    pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::FLOAT32_FILTERABLE
+        .union(wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER)
+        .union(wgpu::Features::VERTEX_WRITABLE_STORAGE);
+
+    /// Returns the features to request from the device. Optional features enable
+    /// pixel formats, sample counts and polygon modes that not every GPU supports.
+    pub fn device_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        let optional_features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+            | wgpu::Features::DEPTH32FLOAT_STENCIL8
+            | wgpu::Features::RG11B10UFLOAT_RENDERABLE
+            | wgpu::Features::POLYGON_MODE_LINE
+            | wgpu::Features::POLYGON_MODE_POINT;
+        Self::REQUIRED_FEATURES | (adapter.features() & optional_features)
+    }
+
+    /// Returns the features of a texture format usable on the device: the adapter's own
+    /// features if the device allows them, otherwise the ones guaranteed by WebGPU.
+    pub fn format_features(&self, format: wgpu::TextureFormat) -> wgpu::TextureFormatFeatures {
+        let device_features = self.device.features();
+        if device_features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            self.adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(device_features)
+        }
+    }
+
+    pub async fn new_for_offscreen(final_render_target: Arc<BitangImage>) -> Result<Self> {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions::default())
             .await
             .context("No suitable adapter found")?;
         let device_descriptor = wgpu::DeviceDescriptor {
-            required_features: wgpu::Features::FLOAT32_FILTERABLE
-                | wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER
-                | wgpu::Features::VERTEX_WRITABLE_STORAGE,
+            required_features: Self::device_features(&adapter),
             ..Default::default()
         };
         let (device, queue) = adapter.request_device(&device_descriptor).await?;
+        let uniform_ring = UniformRing::new(&device);
 
         Ok(GpuContext {
             adapter,
             queue,
             device,
             final_render_target,
+            uniform_ring,
         })
     }
 }
@@ -53,18 +82,28 @@ pub struct FrameContext {
     pub command_encoder: wgpu::CommandEncoder,
     pub globals: Globals,
     pub screen_pass_draw_batch: RenderPassDrawBatch,
+
+    /// If set, passes render to this image instead of the final render target
+    pub screen_override: Option<Arc<BitangImage>>,
 }
 
 // A draw command that belong to a certain render pass
 pub struct RenderPassDrawCommand {
     pub pipeline: wgpu::RenderPipeline,
-    pub vertex_buffer: wgpu::Buffer,
+    pub vertex_buffer: Option<wgpu::Buffer>,
+
+    /// Per-instance vertex attributes, bound as the second vertex stream
+    pub instance_buffer: Option<wgpu::Buffer>,
+
+    /// If set, the vertex, index and instance counts are read from this buffer
+    pub indirect_buffer: Option<wgpu::Buffer>,
     pub index_buffer: Option<wgpu::Buffer>,
-    pub vertex_bind_group: wgpu::BindGroup,
-    pub fragment_bind_group: wgpu::BindGroup,
+    pub vertex_bind_group: ShaderBindGroup,
+    pub fragment_bind_group: ShaderBindGroup,
     pub vertex_count: u32,
     pub index_count: u32,
     pub instance_count: u32,
+    pub stencil_reference: u32,
 }
 
 // All data needed to render a render pass.
@@ -78,16 +117,36 @@ impl RenderPassDrawBatch {
     pub fn render(&self, render_pass: &mut wgpu::RenderPass) {
         for draw_command in &self.draw_commands {
             render_pass.set_pipeline(&draw_command.pipeline);
-            render_pass.set_vertex_buffer(0, draw_command.vertex_buffer.slice(..));
-            render_pass.set_bind_group(0, &draw_command.vertex_bind_group, &[]);
-            render_pass.set_bind_group(1, &draw_command.fragment_bind_group, &[]);
+            render_pass.set_stencil_reference(draw_command.stencil_reference);
+            if let Some(vertex_buffer) = &draw_command.vertex_buffer {
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            }
+            if let Some(instance_buffer) = &draw_command.instance_buffer {
+                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            }
+            render_pass.set_bind_group(
+                0,
+                &draw_command.vertex_bind_group.bind_group,
+                draw_command.vertex_bind_group.dynamic_offsets(),
+            );
+            render_pass.set_bind_group(
+                1,
+                &draw_command.fragment_bind_group.bind_group,
+                draw_command.fragment_bind_group.dynamic_offsets(),
+            );
             if let Some(index_buffer) = &draw_command.index_buffer {
                 render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                render_pass.draw_indexed(
-                    0..draw_command.index_count,
-                    0,
-                    0..draw_command.instance_count,
-                );
+                if let Some(indirect_buffer) = &draw_command.indirect_buffer {
+                    render_pass.draw_indexed_indirect(indirect_buffer, 0);
+                } else {
+                    render_pass.draw_indexed(
+                        0..draw_command.index_count,
+                        0,
+                        0..draw_command.instance_count,
+                    );
+                }
+            } else if let Some(indirect_buffer) = &draw_command.indirect_buffer {
+                render_pass.draw_indirect(indirect_buffer, 0);
             } else {
                 render_pass.draw(0..draw_command.vertex_count, 0..draw_command.instance_count);
             }
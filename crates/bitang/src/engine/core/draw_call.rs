@@ -1,4 +1,6 @@
-use anyhow::Result;
+use std::rc::Rc;
+
+use anyhow::{ensure, Result};
 use serde::Deserialize;
 use smallvec::SmallVec;
 use wgpu::CompareFunction;
@@ -6,9 +8,11 @@ use wgpu::CompareFunction;
 use super::context::{GpuContext, RenderPassContext};
 use super::mesh::Mesh;
 use super::shader::Shader;
+use super::storage_buffer::{BufferSlot, StorageBuffer};
 use super::{Vertex3, VERTEX_FORMAT};
 use crate::engine::core::context::RenderPassDrawCommand;
 use crate::engine::pass::FramebufferInfo;
+use crate::engine::Control;
 
 #[derive(Debug, Deserialize, Default, Clone)]
 pub enum BlendMode {
@@ -17,6 +21,377 @@ pub enum BlendMode {
     Alpha,
     Additive,
     Subtractive,
+
+    /// Explicit factors and operations for the color and alpha channels
+    Custom {
+        color: BlendComponent,
+        alpha: BlendComponent,
+    },
+}
+
+impl BlendMode {
+    pub fn wgpu_blend_state(&self) -> Result<wgpu::BlendState> {
+        let blend_state = match self {
+            BlendMode::None => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Max,
+                },
+            },
+            BlendMode::Subtractive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::ReverseSubtract,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Max,
+                },
+            },
+            BlendMode::Custom { color, alpha } => wgpu::BlendState {
+                color: color.wgpu_component()?,
+                alpha: alpha.wgpu_component()?,
+            },
+        };
+        Ok(blend_state)
+    }
+}
+
+/// Blending of a channel group: `src * src_factor <operation> dst * dst_factor`
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct BlendComponent {
+    pub src_factor: BlendFactor,
+    pub dst_factor: BlendFactor,
+
+    #[serde(default)]
+    pub operation: BlendOperation,
+}
+
+impl BlendComponent {
+    fn wgpu_component(&self) -> Result<wgpu::BlendComponent> {
+        if matches!(self.operation, BlendOperation::Min | BlendOperation::Max) {
+            ensure!(
+                matches!(self.src_factor, BlendFactor::One)
+                    && matches!(self.dst_factor, BlendFactor::One),
+                "Blend operation {:?} requires One for both factors",
+                self.operation
+            );
+        }
+        Ok(wgpu::BlendComponent {
+            src_factor: self.src_factor.wgpu_factor(),
+            dst_factor: self.dst_factor.wgpu_factor(),
+            operation: self.operation.wgpu_operation(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    Src,
+    OneMinusSrc,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    Dst,
+    OneMinusDst,
+    DstAlpha,
+    OneMinusDstAlpha,
+    SrcAlphaSaturated,
+}
+
+impl BlendFactor {
+    fn wgpu_factor(self) -> wgpu::BlendFactor {
+        match self {
+            BlendFactor::Zero => wgpu::BlendFactor::Zero,
+            BlendFactor::One => wgpu::BlendFactor::One,
+            BlendFactor::Src => wgpu::BlendFactor::Src,
+            BlendFactor::OneMinusSrc => wgpu::BlendFactor::OneMinusSrc,
+            BlendFactor::SrcAlpha => wgpu::BlendFactor::SrcAlpha,
+            BlendFactor::OneMinusSrcAlpha => wgpu::BlendFactor::OneMinusSrcAlpha,
+            BlendFactor::Dst => wgpu::BlendFactor::Dst,
+            BlendFactor::OneMinusDst => wgpu::BlendFactor::OneMinusDst,
+            BlendFactor::DstAlpha => wgpu::BlendFactor::DstAlpha,
+            BlendFactor::OneMinusDstAlpha => wgpu::BlendFactor::OneMinusDstAlpha,
+            BlendFactor::SrcAlphaSaturated => wgpu::BlendFactor::SrcAlphaSaturated,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum BlendOperation {
+    #[default]
+    Add,
+    Subtract,
+    ReverseSubtract,
+
+    /// Requires One for both factors
+    Min,
+
+    /// Requires One for both factors
+    Max,
+}
+
+impl BlendOperation {
+    fn wgpu_operation(self) -> wgpu::BlendOperation {
+        match self {
+            BlendOperation::Add => wgpu::BlendOperation::Add,
+            BlendOperation::Subtract => wgpu::BlendOperation::Subtract,
+            BlendOperation::ReverseSubtract => wgpu::BlendOperation::ReverseSubtract,
+            BlendOperation::Min => wgpu::BlendOperation::Min,
+            BlendOperation::Max => wgpu::BlendOperation::Max,
+        }
+    }
+}
+
+/// The color channels written to a color image.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct ColorWriteMask {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl Default for ColorWriteMask {
+    fn default() -> Self {
+        ColorWriteMask {
+            red: true,
+            green: true,
+            blue: true,
+            alpha: true,
+        }
+    }
+}
+
+impl ColorWriteMask {
+    fn wgpu_color_writes(self) -> wgpu::ColorWrites {
+        let mut color_writes = wgpu::ColorWrites::empty();
+        color_writes.set(wgpu::ColorWrites::RED, self.red);
+        color_writes.set(wgpu::ColorWrites::GREEN, self.green);
+        color_writes.set(wgpu::ColorWrites::BLUE, self.blue);
+        color_writes.set(wgpu::ColorWrites::ALPHA, self.alpha);
+        color_writes
+    }
+}
+
+/// Blending and write mask of a color image of the pass.
+#[derive(Debug, Default, Clone)]
+pub struct ColorTargetProps {
+    pub blend_mode: BlendMode,
+    pub write_mask: ColorWriteMask,
+}
+
+/// Depth bias added to the fragment depth, e.g. against shadow acne in shadow passes.
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+pub struct DepthBias {
+    pub constant: i32,
+    pub slope_scale: f32,
+
+    /// Maximum bias, 0 means no limit
+    pub clamp: f32,
+}
+
+impl DepthBias {
+    fn wgpu_depth_bias(self) -> wgpu::DepthBiasState {
+        wgpu::DepthBiasState {
+            constant: self.constant,
+            slope_scale: self.slope_scale,
+            clamp: self.clamp,
+        }
+    }
+
+    /// Fails if the bias is set for a topology that isn't made of triangles.
+    pub fn ensure_topology(self, draw_call_id: &str, topology: Topology) -> Result<()> {
+        let is_triangle_topology =
+            matches!(topology, Topology::TriangleList | Topology::TriangleStrip);
+        ensure!(
+            is_triangle_topology || !self.wgpu_depth_bias().is_enabled(),
+            "Draw call '{draw_call_id}' uses depth bias with {topology:?} topology"
+        );
+        Ok(())
+    }
+}
+
+/// The winding order of front-facing triangles
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum FrontFace {
+    #[default]
+    Ccw,
+    Cw,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum CullFace {
+    #[default]
+    Back,
+    Front,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum Topology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    LineList,
+    LineStrip,
+    PointList,
+}
+
+impl Topology {
+    fn wgpu_topology(self) -> wgpu::PrimitiveTopology {
+        match self {
+            Topology::TriangleList => wgpu::PrimitiveTopology::TriangleList,
+            Topology::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+            Topology::LineList => wgpu::PrimitiveTopology::LineList,
+            Topology::LineStrip => wgpu::PrimitiveTopology::LineStrip,
+            Topology::PointList => wgpu::PrimitiveTopology::PointList,
+        }
+    }
+}
+
+/// How triangles are rasterized. Line and point modes need an optional device feature.
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    Line,
+    Point,
+}
+
+/// Compares a new value with the stored value, `Less` passes if the new value is less
+/// than the stored value. The new value is the fragment depth for depth tests and the
+/// stencil reference for stencil tests.
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum Compare {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    #[default]
+    Always,
+}
+
+impl Compare {
+    fn wgpu_compare(self) -> CompareFunction {
+        match self {
+            Compare::Never => CompareFunction::Never,
+            Compare::Less => CompareFunction::Less,
+            Compare::Equal => CompareFunction::Equal,
+            Compare::LessEqual => CompareFunction::LessEqual,
+            Compare::Greater => CompareFunction::Greater,
+            Compare::NotEqual => CompareFunction::NotEqual,
+            Compare::GreaterEqual => CompareFunction::GreaterEqual,
+            Compare::Always => CompareFunction::Always,
+        }
+    }
+}
+
+/// The operation applied to the stored stencil value.
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum StencilOperation {
+    #[default]
+    Keep,
+    Zero,
+
+    /// Replaces the value with the stencil reference
+    Replace,
+    Invert,
+    IncrementClamp,
+    DecrementClamp,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl StencilOperation {
+    fn wgpu_operation(self) -> wgpu::StencilOperation {
+        match self {
+            StencilOperation::Keep => wgpu::StencilOperation::Keep,
+            StencilOperation::Zero => wgpu::StencilOperation::Zero,
+            StencilOperation::Replace => wgpu::StencilOperation::Replace,
+            StencilOperation::Invert => wgpu::StencilOperation::Invert,
+            StencilOperation::IncrementClamp => wgpu::StencilOperation::IncrementClamp,
+            StencilOperation::DecrementClamp => wgpu::StencilOperation::DecrementClamp,
+            StencilOperation::IncrementWrap => wgpu::StencilOperation::IncrementWrap,
+            StencilOperation::DecrementWrap => wgpu::StencilOperation::DecrementWrap,
+        }
+    }
+}
+
+/// Stencil test and write state of a draw call, the same for front and back faces.
+pub struct StencilProps {
+    pub compare: Compare,
+
+    /// Applied if both the stencil and depth tests pass
+    pub pass_op: StencilOperation,
+
+    /// Applied if the stencil test fails
+    pub fail_op: StencilOperation,
+
+    /// Applied if the stencil test passes but the depth test fails
+    pub depth_fail_op: StencilOperation,
+    pub read_mask: u32,
+    pub write_mask: u32,
+
+    pub reference: u32,
+
+    /// If set, overrides `reference` with the rounded control value
+    pub reference_control: Option<Rc<Control>>,
+}
+
+impl StencilProps {
+    fn wgpu_stencil_state(&self) -> wgpu::StencilState {
+        let face = wgpu::StencilFaceState {
+            compare: self.compare.wgpu_compare(),
+            fail_op: self.fail_op.wgpu_operation(),
+            depth_fail_op: self.depth_fail_op.wgpu_operation(),
+            pass_op: self.pass_op.wgpu_operation(),
+        };
+        wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: self.read_mask,
+            write_mask: self.write_mask,
+        }
+    }
+}
+
+/// Vertex buffer layout of the draw call pipeline.
+#[derive(Debug, Clone)]
+pub enum VertexLayout {
+    /// Mesh vertices, see `Vertex3`
+    Mesh,
+
+    /// Mesh vertices and per-instance attributes from a storage buffer
+    MeshWithInstances(InstanceLayout),
+
+    /// No vertex buffer, the vertex shader generates the geometry from `vertex_index`
+    Empty,
+}
+
+/// Per-instance vertex attributes of a storage buffer item.
+///
+/// Attribute `i` reads the `i`th vec4 of the item. The shader locations follow the
+/// mesh attributes, so the first instance attribute is at `@location(5)`.
+#[derive(Debug, Clone)]
+pub struct InstanceLayout {
+    pub item_size_in_vec4: usize,
+    pub attributes: Vec<wgpu::VertexFormat>,
 }
 
 pub struct DrawCall {
@@ -24,16 +399,26 @@ pub struct DrawCall {
     pub vertex_shader: Shader,
     pub fragment_shader: Shader,
     pub pipeline: wgpu::RenderPipeline,
+    stencil_reference: u32,
+    stencil_reference_control: Option<Rc<Control>>,
 }
 
 pub struct DrawCallProps {
     pub id: String,
     pub vertex_shader: Shader,
     pub fragment_shader: Shader,
-    pub depth_test: bool,
+    pub depth_compare: Compare,
     pub depth_write: bool,
-    pub blend_mode: BlendMode,
-    pub cull: bool,
+    pub depth_bias: DepthBias,
+
+    /// One for each color image of the pass
+    pub color_targets: Vec<ColorTargetProps>,
+    pub cull_face: Option<CullFace>,
+    pub front_face: FrontFace,
+    pub topology: Topology,
+    pub polygon_mode: PolygonMode,
+    pub vertex_layout: VertexLayout,
+    pub stencil: Option<StencilProps>,
 }
 
 impl DrawCall {
@@ -52,70 +437,132 @@ impl DrawCall {
                 push_constant_ranges: &[],
             });
 
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+        let mesh_buffer_layout = wgpu::VertexBufferLayout {
             array_stride: size_of::<Vertex3>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
             attributes: &VERTEX_FORMAT,
         };
-
-        let blend_state = match props.blend_mode {
-            BlendMode::None => wgpu::BlendState::REPLACE,
-            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
-            BlendMode::Additive => wgpu::BlendState {
-                color: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::Add,
-                },
-                alpha: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::One,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::Max,
-                },
-            },
-            BlendMode::Subtractive => wgpu::BlendState {
-                color: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::ReverseSubtract,
-                },
-                alpha: wgpu::BlendComponent {
-                    src_factor: wgpu::BlendFactor::One,
-                    dst_factor: wgpu::BlendFactor::One,
-                    operation: wgpu::BlendOperation::Max,
+        let instance_attributes = match &props.vertex_layout {
+            VertexLayout::MeshWithInstances(instance_layout) => instance_layout
+                .attributes
+                .iter()
+                .enumerate()
+                .map(|(index, format)| wgpu::VertexAttribute {
+                    format: *format,
+                    offset: (index * size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+                    shader_location: (VERTEX_FORMAT.len() + index) as u32,
+                })
+                .collect(),
+            VertexLayout::Mesh | VertexLayout::Empty => vec![],
+        };
+        let vertex_buffer_layouts = match &props.vertex_layout {
+            VertexLayout::Mesh => vec![mesh_buffer_layout],
+            VertexLayout::MeshWithInstances(instance_layout) => vec![
+                mesh_buffer_layout,
+                wgpu::VertexBufferLayout {
+                    array_stride: (instance_layout.item_size_in_vec4 * size_of::<[f32; 4]>())
+                        as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &instance_attributes,
                 },
-            },
+            ],
+            VertexLayout::Empty => vec![],
         };
 
+        ensure!(
+            props.color_targets.len() == framebuffer_info.color_buffer_formats.len(),
+            "Draw call '{}' has {} color targets, but the pass has {} color images",
+            props.id,
+            props.color_targets.len(),
+            framebuffer_info.color_buffer_formats.len()
+        );
         let mut fragment_targets = SmallVec::<[_; 8]>::new();
-        for color_buffer_format in &framebuffer_info.color_buffer_formats {
+        for (color_buffer_format, color_target) in
+            framebuffer_info.color_buffer_formats.iter().zip(&props.color_targets)
+        {
+            let format = color_buffer_format.wgpu_format();
+            let blend = match color_target.blend_mode {
+                BlendMode::None => None,
+                _ => {
+                    // Integer formats, and 32-bit floats on some GPUs, can't be blended
+                    ensure!(
+                        context
+                            .format_features(format)
+                            .flags
+                            .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE),
+                        "Draw call '{}' uses blending, but {format:?} images can't be blended",
+                        props.id
+                    );
+                    Some(color_target.blend_mode.wgpu_blend_state()?)
+                }
+            };
             fragment_targets.push(Some(wgpu::ColorTargetState {
-                format: color_buffer_format.wgpu_format(),
-                blend: Some(blend_state),
-                write_mask: wgpu::ColorWrites::ALL,
+                format,
+                blend,
+                write_mask: color_target.write_mask.wgpu_color_writes(),
             }));
         }
 
-        let depth_stencil = framebuffer_info.depth_buffer_format.map(|format| {
-            let depth_compare =
-                if props.depth_test { CompareFunction::LessEqual } else { CompareFunction::Always };
-            wgpu::DepthStencilState {
+        if props.stencil.is_some() {
+            framebuffer_info.ensure_stencil(&props.id)?;
+        }
+        let stencil = props.stencil.as_ref().map_or_else(
+            wgpu::StencilState::default,
+            StencilProps::wgpu_stencil_state,
+        );
+        props.depth_bias.ensure_topology(&props.id, props.topology)?;
+        let depth_bias = props.depth_bias.wgpu_depth_bias();
+        let depth_stencil =
+            framebuffer_info.depth_buffer_format.map(|format| wgpu::DepthStencilState {
                 format: format.wgpu_format(),
                 depth_write_enabled: props.depth_write,
-                depth_compare,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }
-        });
+                depth_compare: props.depth_compare.wgpu_compare(),
+                stencil,
+                bias: depth_bias,
+            });
 
-        let cull_mode = if props.cull { Some(wgpu::Face::Back) } else { None };
+        let polygon_mode = match props.polygon_mode {
+            PolygonMode::Fill => wgpu::PolygonMode::Fill,
+            PolygonMode::Line => wgpu::PolygonMode::Line,
+            PolygonMode::Point => wgpu::PolygonMode::Point,
+        };
+        let required_feature = match polygon_mode {
+            wgpu::PolygonMode::Fill => wgpu::Features::empty(),
+            wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        };
+        ensure!(
+            context.device.features().contains(required_feature),
+            "Draw call '{}' uses {:?} polygon mode, but the GPU doesn't support it",
+            props.id,
+            props.polygon_mode
+        );
+        let primitive = wgpu::PrimitiveState {
+            topology: props.topology.wgpu_topology(),
+            // Mesh indices are always 32-bit
+            strip_index_format: matches!(
+                props.topology,
+                Topology::TriangleStrip | Topology::LineStrip
+            )
+            .then_some(wgpu::IndexFormat::Uint32),
+            front_face: match props.front_face {
+                FrontFace::Ccw => wgpu::FrontFace::Ccw,
+                FrontFace::Cw => wgpu::FrontFace::Cw,
+            },
+            cull_mode: props.cull_face.map(|cull_face| match cull_face {
+                CullFace::Back => wgpu::Face::Back,
+                CullFace::Front => wgpu::Face::Front,
+            }),
+            polygon_mode,
+            ..wgpu::PrimitiveState::default()
+        };
         let pipeline = context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &props.vertex_shader.shader_module,
                 entry_point: Some(props.vertex_shader.entry_point.as_ref()),
-                buffers: &[vertex_buffer_layout],
+                buffers: &vertex_buffer_layouts,
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
@@ -124,12 +571,12 @@ impl DrawCall {
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 targets: &fragment_targets,
             }),
-            primitive: wgpu::PrimitiveState {
-                cull_mode,
-                ..wgpu::PrimitiveState::default()
-            },
+            primitive,
             depth_stencil,
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState {
+                count: framebuffer_info.sample_count,
+                ..wgpu::MultisampleState::default()
+            },
             multiview: None,
             cache: None,
         });
@@ -139,13 +586,35 @@ impl DrawCall {
             vertex_shader: props.vertex_shader,
             fragment_shader: props.fragment_shader,
             pipeline,
+            stencil_reference: props.stencil.as_ref().map_or(0, |stencil| stencil.reference),
+            stencil_reference_control: props.stencil.and_then(|stencil| stencil.reference_control),
         })
     }
 
-    pub fn render(&self, context: &mut RenderPassContext, mesh: &Mesh) -> Result<()> {
+    fn stencil_reference(&self) -> u32 {
+        match &self.stencil_reference_control {
+            Some(control) => control.as_float().round().max(0.0) as u32,
+            None => self.stencil_reference,
+        }
+    }
+
+    /// Draws a mesh. `instance_buffer` is required if the pipeline uses
+    /// `VertexLayout::MeshWithInstances`. If `indirect_buffer` is set, the counts are
+    /// read from it as `DrawIndexedIndirectArgs`, or `DrawIndirectArgs` for meshes without indices.
+    /// The instance count of the globals is then only bound to the shaders, it doesn't limit
+    /// the draw, because the count in the buffer is only known on the GPU.
+    pub fn render(
+        &self,
+        context: &mut RenderPassContext,
+        mesh: &Mesh,
+        instance_buffer: Option<wgpu::Buffer>,
+        indirect_buffer: Option<&StorageBuffer>,
+    ) -> Result<()> {
         context.pass_queue.draw_commands.push(RenderPassDrawCommand {
             pipeline: self.pipeline.clone(),
-            vertex_buffer: mesh.vertex_buffer.clone(),
+            vertex_buffer: Some(mesh.vertex_buffer.clone()),
+            instance_buffer,
+            indirect_buffer: indirect_buffer.map(|buffer| buffer.get_buffer(BufferSlot::Current)),
             index_buffer: mesh.index_buffer.clone(),
             vertex_bind_group: self
                 .vertex_shader
@@ -156,6 +625,37 @@ impl DrawCall {
             vertex_count: mesh.vertex_count,
             index_count: mesh.index_count,
             instance_count: context.globals.instance_count as u32,
+            stencil_reference: self.stencil_reference(),
+        });
+        Ok(())
+    }
+
+    /// Draws vertices without a vertex buffer. The pipeline must use `VertexLayout::Empty`.
+    ///
+    /// If `indirect_buffer` is set, the counts are read from it as `DrawIndirectArgs`. The
+    /// instance count of the globals is then only bound to the shaders, as in `render`.
+    pub fn render_without_mesh(
+        &self,
+        context: &mut RenderPassContext,
+        vertex_count: u32,
+        indirect_buffer: Option<&StorageBuffer>,
+    ) -> Result<()> {
+        context.pass_queue.draw_commands.push(RenderPassDrawCommand {
+            pipeline: self.pipeline.clone(),
+            vertex_buffer: None,
+            instance_buffer: None,
+            indirect_buffer: indirect_buffer.map(|buffer| buffer.get_buffer(BufferSlot::Current)),
+            index_buffer: None,
+            vertex_bind_group: self
+                .vertex_shader
+                .make_bind_group(context.gpu_context, context.globals)?,
+            fragment_bind_group: self
+                .fragment_shader
+                .make_bind_group(context.gpu_context, context.globals)?,
+            vertex_count,
+            index_count: 0,
+            instance_count: context.globals.instance_count as u32,
+            stencil_reference: self.stencil_reference(),
         });
         Ok(())
     }
@@ -13,6 +13,13 @@ pub enum GlobalType {
     /// Elapsed time relative to the current chart.
     ChartTime,
 
+    /// Beat position of the music at the current project time, counted from zero.
+    /// Zero if the project has no tempo.
+    Beat,
+
+    /// Bar position of the music at the current project time, counted from zero.
+    Bar,
+
     ProjectionFromModel,
     LightspaceFromWorld,
     LightProjectionFromModel,
@@ -59,6 +66,8 @@ pub struct Globals {
     pub pixel_size: Vec2,
     pub app_time: f32,
     pub chart_time: f32,
+    pub beat: f32,
+    pub bar: f32,
     pub instance_count: f32,
     pub aspect_ratio: f32,
     pub z_near: f32,
@@ -79,6 +88,8 @@ impl Globals {
         match global_type {
             GlobalType::AppTime => slice::from_ref(&self.app_time),
             GlobalType::ChartTime => slice::from_ref(&self.chart_time),
+            GlobalType::Beat => slice::from_ref(&self.beat),
+            GlobalType::Bar => slice::from_ref(&self.bar),
             GlobalType::ProjectionFromModel => self.projection_from_model.as_ref(),
             GlobalType::LightspaceFromWorld => self.lightspace_from_world.as_ref(),
             GlobalType::LightProjectionFromModel => self.light_projection_from_model.as_ref(),
@@ -1,6 +1,6 @@
 use std::sync::{Arc, RwLock};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use serde::Deserialize;
 use wgpu::Extent3d;
 
@@ -19,6 +19,26 @@ pub enum PixelFormat {
     // Intel only apparently supports this surface format, no RGBA_SRGB.
     Bgra8Srgb,
     Bgra8Unorm,
+
+    R8U,
+    R16F,
+    R32F,
+    Rg16F,
+    Rg32F,
+
+    /// 10 bits per color channel, 2 bits of alpha
+    Rgb10a2U,
+
+    /// Packed unsigned HDR color without alpha. Rendering to it needs an optional GPU feature.
+    Rg11b10F,
+
+    /// Integer values, only readable with `textureLoad`
+    R32Uint,
+
+    Depth24Stencil8,
+
+    /// Needs an optional GPU feature
+    Depth32FStencil8,
 }
 
 impl PixelFormat {
@@ -31,6 +51,16 @@ impl PixelFormat {
             PixelFormat::Rgba8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
             PixelFormat::Bgra8Srgb => wgpu::TextureFormat::Bgra8UnormSrgb,
             PixelFormat::Bgra8Unorm => wgpu::TextureFormat::Bgra8Unorm,
+            PixelFormat::R8U => wgpu::TextureFormat::R8Unorm,
+            PixelFormat::R16F => wgpu::TextureFormat::R16Float,
+            PixelFormat::R32F => wgpu::TextureFormat::R32Float,
+            PixelFormat::Rg16F => wgpu::TextureFormat::Rg16Float,
+            PixelFormat::Rg32F => wgpu::TextureFormat::Rg32Float,
+            PixelFormat::Rgb10a2U => wgpu::TextureFormat::Rgb10a2Unorm,
+            PixelFormat::Rg11b10F => wgpu::TextureFormat::Rg11b10Ufloat,
+            PixelFormat::R32Uint => wgpu::TextureFormat::R32Uint,
+            PixelFormat::Depth24Stencil8 => wgpu::TextureFormat::Depth24PlusStencil8,
+            PixelFormat::Depth32FStencil8 => wgpu::TextureFormat::Depth32FloatStencil8,
         }
     }
 
@@ -43,9 +73,59 @@ impl PixelFormat {
             wgpu::TextureFormat::Rgba8UnormSrgb => Ok(PixelFormat::Rgba8Srgb),
             wgpu::TextureFormat::Bgra8UnormSrgb => Ok(PixelFormat::Bgra8Srgb),
             wgpu::TextureFormat::Bgra8Unorm => Ok(PixelFormat::Bgra8Unorm),
+            wgpu::TextureFormat::R8Unorm => Ok(PixelFormat::R8U),
+            wgpu::TextureFormat::R16Float => Ok(PixelFormat::R16F),
+            wgpu::TextureFormat::R32Float => Ok(PixelFormat::R32F),
+            wgpu::TextureFormat::Rg16Float => Ok(PixelFormat::Rg16F),
+            wgpu::TextureFormat::Rg32Float => Ok(PixelFormat::Rg32F),
+            wgpu::TextureFormat::Rgb10a2Unorm => Ok(PixelFormat::Rgb10a2U),
+            wgpu::TextureFormat::Rg11b10Ufloat => Ok(PixelFormat::Rg11b10F),
+            wgpu::TextureFormat::R32Uint => Ok(PixelFormat::R32Uint),
+            wgpu::TextureFormat::Depth24PlusStencil8 => Ok(PixelFormat::Depth24Stencil8),
+            wgpu::TextureFormat::Depth32FloatStencil8 => Ok(PixelFormat::Depth32FStencil8),
             _ => bail!("Unsupported format: {:?}", format),
         }
     }
+
+    /// Returns the sample type of the format when bound as a texture. Depth-stencil formats
+    /// are sampled through their depth aspect.
+    pub fn sample_type(&self, device_features: wgpu::Features) -> wgpu::TextureSampleType {
+        self.wgpu_format()
+            .sample_type(Some(self.sampled_aspect()), Some(device_features))
+            .unwrap_or(wgpu::TextureSampleType::Float { filterable: false })
+    }
+
+    /// Returns the aspect of the image that's visible to shaders.
+    pub fn sampled_aspect(&self) -> wgpu::TextureAspect {
+        if self.wgpu_format().has_stencil_aspect() {
+            wgpu::TextureAspect::DepthOnly
+        } else {
+            wgpu::TextureAspect::All
+        }
+    }
+}
+
+/// The shape of a chart image
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageDimension {
+    #[default]
+    D2,
+
+    /// Six square faces in +X, -X, +Y, -Y, +Z, -Z order
+    Cube,
+
+    /// A 2D array with the given number of layers
+    Array(u32),
+}
+
+impl ImageDimension {
+    pub fn layer_count(&self) -> u32 {
+        match self {
+            ImageDimension::D2 => 1,
+            ImageDimension::Cube => 6,
+            ImageDimension::Array(layers) => *layers,
+        }
+    }
 }
 
 // TODO: add Size2D type.
@@ -61,6 +141,10 @@ struct AttachmentImage {
 
     // The underlying texture..
     texture: Option<wgpu::Texture>,
+
+    // The render target of a multisampled color image, resolved into `texture` at the end of
+    // each pass. Multisampled depth images have no resolve, `texture` is multisampled instead.
+    multisampled_texture: Option<wgpu::Texture>,
 }
 
 pub struct SwapchainImage {
@@ -84,6 +168,11 @@ pub struct BitangImage {
     pub pixel_format: PixelFormat,
     inner: ImageInner,
     has_mipmaps: bool,
+
+    /// MSAA sample count of the render target
+    pub sample_count: u32,
+
+    pub dimension: ImageDimension,
 }
 
 impl BitangImage {
@@ -92,15 +181,20 @@ impl BitangImage {
         pixel_format: PixelFormat,
         size_rule: ImageSizeRule,
         has_mipmaps: bool,
+        sample_count: u32,
+        dimension: ImageDimension,
     ) -> Arc<Self> {
         Arc::new(Self {
             id: id.to_owned(),
             inner: ImageInner::Attachment(RwLock::new(AttachmentImage {
                 size_rule,
                 texture: None,
+                multisampled_texture: None,
             })),
             pixel_format,
             has_mipmaps,
+            sample_count,
+            dimension,
         })
     }
 
@@ -110,6 +204,8 @@ impl BitangImage {
             inner: ImageInner::Swapchain(RwLock::new(None)),
             pixel_format,
             has_mipmaps: false,
+            sample_count: 1,
+            dimension: ImageDimension::D2,
         })
     }
 
@@ -164,6 +260,8 @@ impl BitangImage {
             pixel_format,
             inner: ImageInner::Immutable(texture),
             has_mipmaps: true,
+            sample_count: 1,
+            dimension: ImageDimension::D2,
         });
 
         if mip_level_count > 1 {
@@ -179,19 +277,32 @@ impl BitangImage {
 
     // Returns an image view that only has one mip level.
     pub fn view_as_render_target(&self) -> Result<wgpu::TextureView> {
+        self.view_layer_as_render_target(0)
+    }
+
+    /// Returns a view of level 0 of a single layer, e.g. a cube face.
+    pub fn view_layer_as_render_target(&self, layer: u32) -> Result<wgpu::TextureView> {
+        if layer >= self.dimension.layer_count() {
+            bail!("Image '{}' has no layer {layer}", self.id);
+        }
         let view: wgpu::TextureView = match &self.inner {
             ImageInner::Immutable(_) => {
                 bail!("Immutable image can't be used as a render target");
             }
             ImageInner::Attachment(attachment) => {
                 let attachment = attachment.read().unwrap();
-                let Some(texture) = &attachment.texture else {
+                let Some(texture) =
+                    attachment.multisampled_texture.as_ref().or(attachment.texture.as_ref())
+                else {
                     bail!("Attachment image not initialized");
                 };
                 texture.create_view(&wgpu::TextureViewDescriptor {
                     usage: Some(wgpu::TextureUsages::RENDER_ATTACHMENT),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
                     base_mip_level: 0,
                     mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
                     ..wgpu::TextureViewDescriptor::default()
                 })
             }
@@ -206,11 +317,36 @@ impl BitangImage {
         Ok(view)
     }
 
-    // Returns an image view for sampling purposes. It includes all mip levels.
-    pub fn view_as_sampler(&self) -> Result<wgpu::TextureView> {
+    /// Returns the view a multisampled color image is resolved into, None if there's no resolve.
+    pub fn view_as_resolve_target(&self) -> Result<Option<wgpu::TextureView>> {
+        let ImageInner::Attachment(attachment) = &self.inner else {
+            return Ok(None);
+        };
+        let attachment = attachment.read().unwrap();
+        if attachment.multisampled_texture.is_none() {
+            return Ok(None);
+        }
+        let Some(texture) = &attachment.texture else {
+            bail!("Attachment image not initialized");
+        };
+        Ok(Some(texture.create_view(&wgpu::TextureViewDescriptor {
+            usage: Some(wgpu::TextureUsages::RENDER_ATTACHMENT),
+            base_mip_level: 0,
+            mip_level_count: Some(1),
+            ..wgpu::TextureViewDescriptor::default()
+        })))
+    }
+
+    // Returns an image view for sampling purposes. It includes all mip levels and layers.
+    pub fn view_as_sampler(
+        &self,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Result<wgpu::TextureView> {
         let view: wgpu::TextureView = match &self.inner {
             ImageInner::Immutable(texture) => texture.create_view(&wgpu::TextureViewDescriptor {
                 usage: Some(wgpu::TextureUsages::TEXTURE_BINDING),
+                dimension: Some(view_dimension),
+                aspect: self.pixel_format.sampled_aspect(),
                 base_mip_level: 0,
                 mip_level_count: Some(texture.mip_level_count()),
                 ..wgpu::TextureViewDescriptor::default()
@@ -220,9 +356,12 @@ impl BitangImage {
                 let Some(texture) = &attachment.texture else {
                     bail!("Attachment image not initialized");
                 };
+                self.ensure_sampleable()?;
                 // texture.create_view(&wgpu::TextureViewDescriptor::default())
                 texture.create_view(&wgpu::TextureViewDescriptor {
                     usage: Some(wgpu::TextureUsages::TEXTURE_BINDING),
+                    dimension: Some(view_dimension),
+                    aspect: self.pixel_format.sampled_aspect(),
                     base_mip_level: 0,
                     mip_level_count: Some(texture.mip_level_count()),
                     ..wgpu::TextureViewDescriptor::default()
@@ -235,17 +374,45 @@ impl BitangImage {
         Ok(view)
     }
 
-    pub fn view_mip_level(&self, mip_level: u32) -> Result<wgpu::TextureView> {
+    /// Returns a view of level 0 of all layers for binding as a storage texture.
+    pub fn view_as_storage(
+        &self,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Result<wgpu::TextureView> {
+        let ImageInner::Attachment(attachment) = &self.inner else {
+            bail!("Only chart images can be used as storage textures");
+        };
+        let attachment = attachment.read().unwrap();
+        let Some(texture) = &attachment.texture else {
+            bail!("Attachment image not initialized");
+        };
+        if !texture.usage().contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            bail!(
+                "Image '{}' with format {:?} can't be used as a storage texture",
+                self.id,
+                self.pixel_format
+            );
+        }
+        Ok(texture.create_view(&wgpu::TextureViewDescriptor {
+            usage: Some(wgpu::TextureUsages::STORAGE_BINDING),
+            dimension: Some(view_dimension),
+            base_mip_level: 0,
+            mip_level_count: Some(1),
+            ..wgpu::TextureViewDescriptor::default()
+        }))
+    }
+
+    pub fn view_mip_level(&self, mip_level: u32, layer: u32) -> Result<wgpu::TextureView> {
         let view_descriptor = wgpu::TextureViewDescriptor {
             label: Some("mip"),
             format: None,
-            dimension: None,
+            dimension: Some(wgpu::TextureViewDimension::D2),
             usage: None,
             aspect: wgpu::TextureAspect::All,
             base_mip_level: mip_level,
             mip_level_count: Some(1),
-            base_array_layer: 0,
-            array_layer_count: None,
+            base_array_layer: layer,
+            array_layer_count: Some(1),
         };
         let view = match &self.inner {
             ImageInner::Immutable(texture) => texture.create_view(&view_descriptor),
@@ -263,12 +430,62 @@ impl BitangImage {
         Ok(view)
     }
 
+    /// Fails if the image is an attachment that no GPU can create, e.g. a cube image with
+    /// a size that isn't square.
+    pub fn ensure_valid_attachment(&self) -> Result<()> {
+        let ImageInner::Attachment(attachment) = &self.inner else {
+            return Ok(());
+        };
+        ensure!(
+            matches!(self.sample_count, 1 | 2 | 4 | 8 | 16),
+            "Image '{}' has invalid sample count {}",
+            self.id,
+            self.sample_count
+        );
+        match self.dimension {
+            ImageDimension::Cube => {
+                // At4k sizes are scaled the same way in both directions
+                let size_rule = attachment.read().unwrap().size_rule;
+                ensure!(
+                    matches!(size_rule, ImageSizeRule::Fixed(w, h) | ImageSizeRule::At4k(w, h) if w == h),
+                    "Cube image '{}' must have a square Fixed or At4k size",
+                    self.id
+                );
+            }
+            ImageDimension::Array(layers) => {
+                ensure!(layers > 0, "Array image '{}' has no layers", self.id);
+            }
+            ImageDimension::D2 => {}
+        }
+        ensure!(
+            self.sample_count == 1 || self.dimension.layer_count() == 1,
+            "Image '{}' with multiple layers can't be multisampled",
+            self.id
+        );
+        Ok(())
+    }
+
+    /// Fails if shaders can't sample the image. Multisampled depth images have no resolve.
+    pub fn ensure_sampleable(&self) -> Result<()> {
+        ensure!(
+            !self.is_multisampled_depth(),
+            "Multisampled depth image '{}' can't be sampled",
+            self.id
+        );
+        Ok(())
+    }
+
+    fn is_multisampled_depth(&self) -> bool {
+        self.sample_count > 1 && self.pixel_format.wgpu_format().is_depth_stencil_format()
+    }
+
     /// Enforce the size rule.
     pub fn enforce_size_rule(&self, context: &GpuContext, canvas_size: &Size2D) -> Result<()> {
         // Only attachments need to be resized.
         let ImageInner::Attachment(attachment) = &self.inner else {
             return Ok(());
         };
+        self.ensure_valid_attachment()?;
         let mut attachment = attachment.write().unwrap();
 
         // Calculate the size of the image.
@@ -290,7 +507,7 @@ impl BitangImage {
         let extent = Extent3d {
             width: size[0],
             height: size[1],
-            depth_or_array_layers: 1,
+            depth_or_array_layers: self.dimension.layer_count(),
         };
         // Check if the image is already the correct size.
         if let Some(texture) = &attachment.texture {
@@ -300,23 +517,74 @@ impl BitangImage {
         };
 
         // Create a new image with the correct size.
-        let mip_levels =
-            if self.has_mipmaps { extent.max_mips(wgpu::TextureDimension::D2) } else { 1 };
-        let usage = wgpu::TextureUsages::TEXTURE_BINDING
+        let format = self.pixel_format.wgpu_format();
+        if !context.device.features().contains(format.required_features()) {
+            bail!(
+                "Image '{}' with format {:?} is not supported by the GPU",
+                self.id,
+                self.pixel_format
+            );
+        }
+        let format_features = context.format_features(format);
+        if !format_features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT) {
+            bail!(
+                "Image '{}' with format {:?} can't be rendered to on this GPU",
+                self.id,
+                self.pixel_format
+            );
+        }
+        if !format_features.flags.sample_count_supported(self.sample_count) {
+            bail!(
+                "Image '{}' with format {:?} doesn't support {} samples",
+                self.id,
+                self.pixel_format,
+                self.sample_count
+            );
+        }
+        let is_multisampled = self.sample_count > 1;
+        let is_multisampled_depth = self.is_multisampled_depth();
+        let mip_levels = if self.has_mipmaps && !is_multisampled_depth {
+            extent.max_mips(wgpu::TextureDimension::D2)
+        } else {
+            1
+        };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING
             | wgpu::TextureUsages::RENDER_ATTACHMENT
             | wgpu::TextureUsages::COPY_SRC;
+        if format_features.allowed_usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            // Compute shaders can write to the image
+            usage |= wgpu::TextureUsages::STORAGE_BINDING;
+        }
+        let multisampled_descriptor = wgpu::TextureDescriptor {
+            label: Some(&self.id),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        };
+        if is_multisampled_depth {
+            attachment.texture = Some(context.device.create_texture(&multisampled_descriptor));
+            attachment.multisampled_texture = None;
+            return Ok(());
+        }
+
         let image = context.device.create_texture(&wgpu::TextureDescriptor {
             label: Some(&self.id),
             size: extent,
             mip_level_count: mip_levels,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: self.pixel_format.wgpu_format(),
+            format,
             usage,
             view_formats: &[],
         });
 
         attachment.texture = Some(image);
+        attachment.multisampled_texture =
+            is_multisampled.then(|| context.device.create_texture(&multisampled_descriptor));
         Ok(())
     }
 
@@ -344,6 +612,15 @@ impl BitangImage {
         }
     }
 
+    /// Returns the current texture of the image. Resizing an attachment replaces its texture.
+    pub fn get_texture(&self) -> Option<wgpu::Texture> {
+        match &self.inner {
+            ImageInner::Attachment(attachment) => attachment.read().unwrap().texture.clone(),
+            ImageInner::Immutable(texture) => Some(texture.clone()),
+            ImageInner::Swapchain(_) => None,
+        }
+    }
+
     pub fn is_swapchain(&self) -> bool {
         matches!(&self.inner, ImageInner::Swapchain(_))
     }
@@ -358,6 +635,11 @@ impl BitangImage {
         }
     }
 
+    /// Returns true if the image is created with a full mip chain.
+    pub fn has_mipmaps(&self) -> bool {
+        self.has_mipmaps
+    }
+
     pub fn mip_levels(&self) -> Result<u32> {
         match &self.inner {
             ImageInner::Attachment(attachment) => {
@@ -374,7 +656,14 @@ impl BitangImage {
         }
     }
 
-    pub fn _copy_attachment_to_buffer(
+    /// Returns the number of bytes a row of pixels takes when copied to a buffer.
+    /// Rows are padded to satisfy wgpu's copy alignment requirements.
+    pub fn padded_bytes_per_row(&self, width: u32) -> u32 {
+        let bytes_per_pixel = self.pixel_format.wgpu_format().block_copy_size(None).unwrap_or(4);
+        (width * bytes_per_pixel).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+    }
+
+    pub fn copy_attachment_to_buffer(
         &self,
         context: &mut FrameContext,
         buffer: &wgpu::Buffer,
@@ -398,7 +687,7 @@ impl BitangImage {
                 buffer,
                 layout: wgpu::TexelCopyBufferLayout {
                     offset: 0,
-                    bytes_per_row: Some(extent.width * 4),
+                    bytes_per_row: Some(self.padded_bytes_per_row(extent.width)),
                     rows_per_image: None,
                 },
             },
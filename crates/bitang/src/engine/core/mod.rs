@@ -1,12 +1,13 @@
 pub mod compute_call;
 pub mod context;
-pub mod double_buffer;
 pub mod draw_call;
 pub mod globals;
 pub mod image;
 pub mod mesh;
 pub mod mipmap_generator;
 pub mod shader;
+pub mod storage_buffer;
+pub mod uniform_ring;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
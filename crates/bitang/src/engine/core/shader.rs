@@ -1,14 +1,15 @@
 use std::mem::size_of;
+use std::num::NonZeroU64;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use smallvec::SmallVec;
 
 use super::context::{ComputePassContext, GpuContext};
-use super::double_buffer::DoubleBuffer;
 use super::globals::{GlobalType, Globals};
-use super::image::{BitangImage, PixelFormat};
+use super::image::{BitangImage, ImageDimension};
+use super::storage_buffer::{BufferSlot, StorageBuffer};
 use crate::engine::Control;
 
 const MAX_UNIFORMS_F32_COUNT: usize = 1024;
@@ -80,11 +81,11 @@ pub struct Shader {
     /// Descriptor bindings, e.g. samplers, buffers, etc.
     pub descriptor_resources: Vec<DescriptorResource>,
 
-    /// Storage for the uniform buffer values    
-    uniform_buffer: wgpu::Buffer,
-
     /// Layout of the single bind group containing all resources
     pub bind_group_layout: wgpu::BindGroupLayout,
+
+    /// The number of invocations in a workgroup of a compute shader
+    pub workgroup_size: [u32; 3],
 }
 
 impl Shader {
@@ -97,14 +98,8 @@ impl Shader {
         local_uniform_bindings: Vec<LocalUniformMapping>,
         uniform_buffer_size: usize,
         descriptor_resources: Vec<DescriptorResource>,
+        workgroup_size: [u32; 3],
     ) -> Shader {
-        let uniform_buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: uniform_buffer_size as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
         let mut entries = vec![];
 
         let visibility = match kind {
@@ -116,12 +111,13 @@ impl Shader {
         };
 
         if uniform_buffer_size > 0 {
+            // Uniform values live in the uniform ring, every draw binds its own slice of it
             entries.push(wgpu::BindGroupLayoutEntry {
                 binding: 0,
                 visibility,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
+                    has_dynamic_offset: true,
                     min_binding_size: None,
                 },
                 count: None,
@@ -131,15 +127,16 @@ impl Shader {
         for descriptor_resource in &descriptor_resources {
             // TODO: just the resource instead of BindGroupEntry
             let ty = match &descriptor_resource.source {
-                DescriptorSource::Image(image_descriptor) => {
-                    let sample_type = match image_descriptor.image.pixel_format {
-                        PixelFormat::Depth32F => wgpu::TextureSampleType::Depth,
-                        _ => wgpu::TextureSampleType::Float { filterable: true },
-                    };
-                    wgpu::BindingType::Texture {
-                        sample_type,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
+                DescriptorSource::Image(image_descriptor) => wgpu::BindingType::Texture {
+                    sample_type: image_descriptor.sample_type,
+                    view_dimension: image_descriptor.view_dimension,
+                    multisampled: false,
+                },
+                DescriptorSource::StorageImage(storage_image_descriptor) => {
+                    wgpu::BindingType::StorageTexture {
+                        access: storage_image_descriptor.access,
+                        format: storage_image_descriptor.image.pixel_format.wgpu_format(),
+                        view_dimension: storage_image_descriptor.view_dimension,
                     }
                 }
                 DescriptorSource::Sampler(sampler_descriptor) => {
@@ -149,13 +146,10 @@ impl Shader {
                     };
                     wgpu::BindingType::Sampler(filtering)
                 }
-                DescriptorSource::BufferCurrent(_) => wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                DescriptorSource::BufferNext(_) => wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                DescriptorSource::Buffer(buffer_descriptor) => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage {
+                        read_only: buffer_descriptor.read_only,
+                    },
                     has_dynamic_offset: false,
                     min_binding_size: None,
                 },
@@ -182,27 +176,32 @@ impl Shader {
             local_uniform_bindings,
             uniform_buffer_size,
             descriptor_resources,
-            uniform_buffer,
             bind_group_layout,
+            workgroup_size,
         }
     }
 
     pub fn bind_to_compute_pass(&self, context: &mut ComputePassContext<'_>) -> Result<()> {
-        let bind_group = self.make_bind_group(&context.gpu_context, &context.globals)?;
-        context.pass.set_bind_group(self.kind.get_descriptor_set_index(), &bind_group, &[]);
+        let bind_group = self.make_bind_group(context.gpu_context, context.globals)?;
+        context.pass.set_bind_group(
+            self.kind.get_descriptor_set_index(),
+            &bind_group.bind_group,
+            bind_group.dynamic_offsets(),
+        );
         Ok(())
     }
 
+    /// Creates a bind group for a single draw call or dispatch.
+    ///
+    /// Uniform values are written to a fresh slice of the uniform ring every time, so
+    /// the bind group captures the current globals and control values.
     pub fn make_bind_group(
         &self,
         context: &GpuContext,
         globals: &Globals,
         // TODO: no result
-    ) -> Result<wgpu::BindGroup> {
-        let mut texture_views = SmallVec::<[_; 64]>::new();
-        let mut entries = SmallVec::<[_; 64]>::new();
-
-        if self.uniform_buffer_size > 0 {
+    ) -> Result<ShaderBindGroup> {
+        let uniforms = if self.uniform_buffer_size > 0 {
             // Fill uniform array
             let mut uniform_values = [0.0f32; MAX_UNIFORMS_F32_COUNT];
             for global_mapping in &self.global_uniform_bindings {
@@ -219,15 +218,28 @@ impl Shader {
                 }
             }
             let f32_count = self.uniform_buffer_size / size_of::<f32>();
-            context.queue.write_buffer(
-                &self.uniform_buffer,
-                0,
+            Some(context.uniform_ring.write(
+                &context.device,
+                &context.queue,
                 bytemuck::cast_slice(&uniform_values[..f32_count]),
-            );
+            ))
+        } else {
+            None
+        };
+
+        let mut texture_views = SmallVec::<[_; 64]>::new();
+        let mut buffers = SmallVec::<[_; 16]>::new();
+        let mut entries = SmallVec::<[_; 64]>::new();
+
+        if let Some(uniforms) = &uniforms {
             // Uniforms are always at binding 0
             entries.push(wgpu::BindGroupEntry {
                 binding: 0,
-                resource: self.uniform_buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &uniforms.buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(self.uniform_buffer_size as u64),
+                }),
             });
         }
 
@@ -238,7 +250,15 @@ impl Shader {
                     // Just store texture view in an array.
                     // This is needed because we create texture views per frame.
                     // TODO: cache texture views
-                    let texture_view = image_descriptor.image.view_as_sampler()?;
+                    let texture_view =
+                        image_descriptor.image.view_as_sampler(image_descriptor.view_dimension)?;
+                    texture_views.push((descriptor_resource.binding, texture_view));
+                    continue;
+                }
+                DescriptorSource::StorageImage(storage_image_descriptor) => {
+                    let texture_view = storage_image_descriptor
+                        .image
+                        .view_as_storage(storage_image_descriptor.view_dimension)?;
                     texture_views.push((descriptor_resource.binding, texture_view));
                     continue;
                 }
@@ -246,14 +266,12 @@ impl Shader {
                     binding: descriptor_resource.binding,
                     resource: wgpu::BindingResource::Sampler(&sampler_descriptor.sampler),
                 },
-                DescriptorSource::BufferCurrent(buffer) => wgpu::BindGroupEntry {
-                    binding: descriptor_resource.binding,
-                    resource: buffer.get_current_buffer().as_entire_binding(),
-                },
-                DescriptorSource::BufferNext(buffer) => wgpu::BindGroupEntry {
-                    binding: descriptor_resource.binding,
-                    resource: buffer.get_next_buffer().as_entire_binding(),
-                },
+                DescriptorSource::Buffer(buffer_descriptor) => {
+                    // Buffers are recreated when resized, so they're also fetched per frame
+                    let buffer = buffer_descriptor.buffer.get_buffer(buffer_descriptor.slot);
+                    buffers.push((descriptor_resource.binding, buffer));
+                    continue;
+                }
             };
             entries.push(write_descriptor_set);
         }
@@ -265,12 +283,37 @@ impl Shader {
             });
         }
 
+        for buffer in &buffers {
+            entries.push(wgpu::BindGroupEntry {
+                binding: buffer.0,
+                resource: buffer.1.as_entire_binding(),
+            });
+        }
+
         let bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Bind Group"),
             layout: &self.bind_group_layout,
             entries: &entries,
         });
-        Ok(bind_group)
+        Ok(ShaderBindGroup {
+            bind_group,
+            uniform_offset: uniforms.as_ref().map(|uniforms| uniforms.offset),
+        })
+    }
+}
+
+/// A bind group created for a single draw call or dispatch.
+#[derive(Clone)]
+pub struct ShaderBindGroup {
+    pub bind_group: wgpu::BindGroup,
+
+    /// Offset of the uniform values in the uniform ring, if the shader has uniforms.
+    pub uniform_offset: Option<u32>,
+}
+
+impl ShaderBindGroup {
+    pub fn dynamic_offsets(&self) -> &[u32] {
+        self.uniform_offset.as_slice()
     }
 }
 
@@ -326,14 +369,117 @@ impl SamplerMode {
 #[derive(Clone)]
 pub struct ImageDescriptor {
     pub image: Arc<BitangImage>,
+
+    /// The texture type declared in the shader, e.g. `texture_cube`
+    pub view_dimension: wgpu::TextureViewDimension,
+
+    /// The sample type of the image format, including whether the GPU can filter it
+    pub sample_type: wgpu::TextureSampleType,
 }
 
 impl ImageDescriptor {
-    pub fn new(image: Arc<BitangImage>) -> Result<Self> {
-        Ok(Self { image })
+    /// Checks that the image matches the texture declared in the shader. `is_filtered` is set if
+    /// the shader samples the texture with a filtering sampler.
+    pub fn new(
+        image: Arc<BitangImage>,
+        view_dimension: wgpu::TextureViewDimension,
+        declared_sample_type: wgpu::TextureSampleType,
+        is_filtered: bool,
+        device_features: wgpu::Features,
+    ) -> Result<Self> {
+        let layer_count = image.dimension.layer_count();
+        let is_compatible = match view_dimension {
+            wgpu::TextureViewDimension::D2 => layer_count == 1,
+            wgpu::TextureViewDimension::D2Array => true,
+            wgpu::TextureViewDimension::Cube => image.dimension == ImageDimension::Cube,
+            wgpu::TextureViewDimension::CubeArray => layer_count.is_multiple_of(6),
+            _ => false,
+        };
+        ensure!(
+            is_compatible,
+            "Image '{}' of dimension {:?} can't be bound as a {view_dimension:?} texture",
+            image.id,
+            image.dimension
+        );
+
+        let sample_type = image.pixel_format.sample_type(device_features);
+        let is_matching_sample_type = matches!(
+            (declared_sample_type, sample_type),
+            (
+                wgpu::TextureSampleType::Float { .. },
+                wgpu::TextureSampleType::Float { .. }
+            ) | (
+                wgpu::TextureSampleType::Depth,
+                wgpu::TextureSampleType::Depth
+            ) | (wgpu::TextureSampleType::Uint, wgpu::TextureSampleType::Uint)
+                | (wgpu::TextureSampleType::Sint, wgpu::TextureSampleType::Sint)
+        );
+        ensure!(
+            is_matching_sample_type,
+            "Image '{}' with format {:?} has sample type {sample_type:?}, but the shader declares {declared_sample_type:?}",
+            image.id,
+            image.pixel_format
+        );
+        ensure!(
+            !is_filtered || sample_type != wgpu::TextureSampleType::Float { filterable: false },
+            "Image '{}' with format {:?} can't be filtered, but the shader samples it with a filtering sampler",
+            image.id,
+            image.pixel_format
+        );
+        Ok(Self {
+            image,
+            view_dimension,
+            sample_type,
+        })
     }
 }
 
+/// An image bound as a storage texture. Only level 0 of the image is accessible.
+#[derive(Clone)]
+pub struct StorageImageDescriptor {
+    pub image: Arc<BitangImage>,
+    pub access: wgpu::StorageTextureAccess,
+
+    /// The texture type declared in the shader, `D2` or `D2Array`
+    pub view_dimension: wgpu::TextureViewDimension,
+}
+
+impl StorageImageDescriptor {
+    /// Checks that the image matches the storage texture declared in the shader. Cube images
+    /// are bound as 2D arrays.
+    pub fn new(
+        image: Arc<BitangImage>,
+        view_dimension: wgpu::TextureViewDimension,
+        access: wgpu::StorageTextureAccess,
+    ) -> Result<Self> {
+        let is_compatible = match view_dimension {
+            wgpu::TextureViewDimension::D2 => image.dimension.layer_count() == 1,
+            wgpu::TextureViewDimension::D2Array => true,
+            _ => false,
+        };
+        ensure!(
+            is_compatible,
+            "Image '{}' of dimension {:?} can't be bound as a {view_dimension:?} storage texture",
+            image.id,
+            image.dimension
+        );
+        Ok(Self {
+            image,
+            access,
+            view_dimension,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct BufferDescriptor {
+    pub buffer: Rc<StorageBuffer>,
+    pub slot: BufferSlot,
+
+    /// Must match the access mode declared in the shader
+    pub read_only: bool,
+}
+
 #[derive(Clone)]
 pub struct SamplerDescriptor {
     pub mode: SamplerMode,
@@ -361,9 +507,9 @@ impl SamplerDescriptor {
 #[derive(Clone)]
 pub enum DescriptorSource {
     Image(ImageDescriptor),
+    StorageImage(StorageImageDescriptor),
     Sampler(SamplerDescriptor),
-    BufferCurrent(Rc<DoubleBuffer>),
-    BufferNext(Rc<DoubleBuffer>),
+    Buffer(BufferDescriptor),
 }
 
 pub struct DescriptorResource {
@@ -0,0 +1,153 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use anyhow::{ensure, Context, Result};
+
+use super::context::GpuContext;
+use crate::engine::Control;
+
+/// Which buffer of a double buffer to bind. A single buffer is both current and next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BufferSlot {
+    Current,
+    Next,
+}
+
+/// The argument layout of a buffer of indirect draw or dispatch arguments
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndirectArgsKind {
+    Draw,
+    DrawIndexed,
+    Dispatch,
+}
+
+/// Fails unless the buffer holds indirect arguments of the expected kind.
+pub fn ensure_indirect_args(
+    buffer_id: &str,
+    indirect_args: Option<IndirectArgsKind>,
+    expected: IndirectArgsKind,
+) -> Result<()> {
+    let kind = indirect_args
+        .with_context(|| format!("Buffer '{buffer_id}' doesn't hold indirect arguments"))?;
+    ensure!(
+        kind == expected,
+        "Buffer '{buffer_id}' has {kind:?} arguments, but {expected:?} arguments are needed"
+    );
+    Ok(())
+}
+
+pub struct StorageBufferProps {
+    pub id: String,
+    pub item_size_in_vec4: usize,
+    pub item_count: usize,
+
+    /// Ping-pong buffers for simulations
+    pub is_double: bool,
+
+    /// Overrides `item_count` if set
+    pub item_count_control: Option<Rc<Control>>,
+
+    /// Copied to the start of the buffer, the rest is zeroed
+    pub initial_content: Vec<[f32; 4]>,
+
+    /// Set if the buffer holds indirect draw or dispatch arguments
+    pub indirect_args: Option<IndirectArgsKind>,
+}
+
+/// A storage buffer of vec4 items, or a pair of them that is swapped every simulation step.
+pub struct StorageBuffer {
+    pub id: String,
+    pub item_size_in_vec4: usize,
+    pub indirect_args: Option<IndirectArgsKind>,
+    item_count: Cell<usize>,
+    item_count_control: Option<Rc<Control>>,
+    initial_content: Vec<[f32; 4]>,
+    buffers: RefCell<Vec<wgpu::Buffer>>,
+    current_index: Cell<usize>,
+}
+
+impl StorageBuffer {
+    pub fn new(context: &GpuContext, props: StorageBufferProps) -> Self {
+        let buffer_count = if props.is_double { 2 } else { 1 };
+        let buffer = StorageBuffer {
+            id: props.id,
+            item_size_in_vec4: props.item_size_in_vec4,
+            indirect_args: props.indirect_args,
+            item_count: Cell::new(props.item_count.max(1)),
+            item_count_control: props.item_count_control,
+            initial_content: props.initial_content,
+            buffers: RefCell::new(Vec::with_capacity(buffer_count)),
+            current_index: Cell::new(0),
+        };
+        buffer.create_buffers(context, buffer_count);
+        buffer
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.item_count.get()
+    }
+
+    /// Recreates the buffers if the item count control changed.
+    ///
+    /// Returns true if the buffers were recreated. Their content is reset to the initial content.
+    pub fn update_size(&self, context: &GpuContext) -> bool {
+        let Some(control) = &self.item_count_control else {
+            return false;
+        };
+        let item_count = (control.as_float().round() as usize).max(1);
+        if item_count == self.item_count.get() {
+            return false;
+        }
+        self.item_count.set(item_count);
+        let buffer_count = self.buffers.borrow().len();
+        self.create_buffers(context, buffer_count);
+        true
+    }
+
+    fn create_buffers(&self, context: &GpuContext, buffer_count: usize) {
+        let item_count = self.item_count.get();
+        let size = (item_count * self.item_size_in_vec4 * size_of::<[f32; 4]>()) as u64;
+        let initial_content_len =
+            self.initial_content.len().min(item_count * self.item_size_in_vec4);
+        let initial_content = &self.initial_content[..initial_content_len];
+        let buffers = (0..buffer_count)
+            .map(|_| {
+                let buffer = context.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&self.id),
+                    size,
+                    usage: wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::VERTEX
+                        | wgpu::BufferUsages::INDIRECT
+                        | wgpu::BufferUsages::COPY_SRC
+                        | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                if !initial_content.is_empty() {
+                    context.queue.write_buffer(&buffer, 0, bytemuck::cast_slice(initial_content));
+                }
+                buffer
+            })
+            .collect();
+        *self.buffers.borrow_mut() = buffers;
+        self.current_index.set(0);
+    }
+
+    pub fn step(&self) {
+        let buffer_count = self.buffers.borrow().len();
+        self.current_index.set((self.current_index.get() + 1) % buffer_count);
+    }
+
+    pub fn get_buffer(&self, slot: BufferSlot) -> wgpu::Buffer {
+        let buffers = self.buffers.borrow();
+        let index = match slot {
+            BufferSlot::Current => self.current_index.get(),
+            BufferSlot::Next => (self.current_index.get() + 1) % buffers.len(),
+        };
+        buffers[index].clone()
+    }
+
+    /// Returns both buffers of a double buffer, or the single buffer.
+    pub fn get_buffers(&self) -> Vec<wgpu::Buffer> {
+        self.buffers.borrow().clone()
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Mutex;
+
+/// Initial size of the ring buffer in bytes. It grows if a frame needs more.
+const INITIAL_CAPACITY: u64 = 1 << 20;
+
+/// A slice of the uniform ring holding the uniform values of a single draw call or dispatch.
+pub struct UniformAllocation {
+    pub buffer: wgpu::Buffer,
+    pub offset: u32,
+}
+
+struct UniformRingInner {
+    buffer: wgpu::Buffer,
+    cursor: u64,
+}
+
+/// Uniform storage shared by all shaders.
+///
+/// Every draw call and dispatch writes its uniform values into its own slice of the ring
+/// and binds it using a dynamic offset. This way objects sharing the same shader don't
+/// overwrite each other's uniforms before the command buffer is submitted.
+///
+/// Queue writes are applied before the next submission, and submissions execute in order,
+/// so the cursor can be rewound at the start of every frame.
+pub struct UniformRing {
+    alignment: u64,
+    inner: Mutex<UniformRingInner>,
+}
+
+impl UniformRing {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        Self {
+            alignment,
+            inner: Mutex::new(UniformRingInner {
+                buffer: Self::create_buffer(device, INITIAL_CAPACITY),
+                cursor: 0,
+            }),
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Uniform ring"),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Rewinds the ring. Call this before recording the commands of a new submission.
+    pub fn start_frame(&self) {
+        self.inner.lock().unwrap().cursor = 0;
+    }
+
+    /// Copies uniform values into the next free slice of the ring.
+    pub fn write(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &[u8],
+    ) -> UniformAllocation {
+        let mut inner = self.inner.lock().unwrap();
+        let size = data.len() as u64;
+        if inner.cursor + size > inner.buffer.size() {
+            // Bind groups recorded earlier in this frame keep the old buffer alive,
+            // so it's safe to continue in a new, larger one.
+            let capacity = (inner.buffer.size() * 2).max(size.next_multiple_of(self.alignment));
+            inner.buffer = Self::create_buffer(device, capacity);
+            inner.cursor = 0;
+        }
+        let offset = inner.cursor;
+        queue.write_buffer(&inner.buffer, offset, data);
+        inner.cursor = (offset + size).next_multiple_of(self.alignment);
+        UniformAllocation {
+            buffer: inner.buffer.clone(),
+            offset: offset as u32,
+        }
+    }
+}
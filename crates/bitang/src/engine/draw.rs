@@ -3,12 +3,16 @@ use std::rc::Rc;
 use anyhow::{ensure, Result};
 use glam::{Mat3, Mat4, Vec2, Vec3};
 
-use super::{Camera, Control, FrameContext, Globals, Pass, RenderObject, RenderPassContext, Scene};
+use super::{
+    Camera, Control, FrameContext, Globals, Pass, ProceduralObject, RenderObject,
+    RenderPassContext, RunOnce, Scene,
+};
 use crate::engine::RenderPassDrawBatch;
 
 pub enum DrawItem {
     Object(Rc<RenderObject>),
     Scene(Rc<Scene>),
+    Procedural(Rc<ProceduralObject>),
 }
 
 /// Represents a draw step in the chart sequence.
@@ -18,6 +22,9 @@ pub struct Draw {
     pub items: Vec<DrawItem>,
     pub light_dir_worldspace: Rc<Control>,
     pub shadow_map_size: Rc<Control>,
+
+    /// If set, the draw only runs when the chart is loaded or its images change
+    pub run_once: Option<RunOnce>,
 }
 
 impl Draw {
@@ -27,6 +34,7 @@ impl Draw {
         items: Vec<DrawItem>,
         light_dir_worldspace: Rc<Control>,
         shadow_map_size: Rc<Control>,
+        run_once: Option<RunOnce>,
     ) -> Result<Draw> {
         Ok(Draw {
             id: id.to_string(),
@@ -34,6 +42,7 @@ impl Draw {
             items,
             light_dir_worldspace,
             shadow_map_size,
+            run_once,
         })
     }
 
@@ -42,6 +51,7 @@ impl Draw {
             match object {
                 DrawItem::Object(object) => object.render(context, pass_index)?,
                 DrawItem::Scene(scene) => scene.render(context, pass_index)?,
+                DrawItem::Procedural(object) => object.render(context, pass_index)?,
             }
         }
         Ok(())
@@ -94,41 +104,55 @@ impl Draw {
             // TODO: remove canvas_size
             let viewport_size = pass.get_viewport_size(frame_context)?;
 
-            // Set globals unspecific to pass
-            self.set_common_globals(&mut frame_context.globals);
-
-            // Set pass-specific globals
-            if pass.id == "shadow" {
-                self.set_globals_for_shadow_map_rendering(&mut frame_context.globals);
-            } else {
-                camera.set_globals(&mut frame_context.globals, viewport_size);
+            // Cube passes are rendered once per face
+            for (layer, cube_face) in pass.target_layers() {
+                // Set globals unspecific to pass
+                self.set_common_globals(&mut frame_context.globals);
+
+                // Set pass-specific globals
+                if pass.id == "shadow" {
+                    self.set_globals_for_shadow_map_rendering(&mut frame_context.globals);
+                } else if let Some(cube_face) = cube_face {
+                    camera.set_cube_face_globals(
+                        &mut frame_context.globals,
+                        cube_face,
+                        viewport_size,
+                    );
+                } else {
+                    camera.set_globals(&mut frame_context.globals, viewport_size);
+                }
+
+                if pass.is_screen_pass() && frame_context.screen_override.is_none() {
+                    self.render_screen_pass(pass_index, frame_context)?;
+                    continue;
+                }
+
+                let mut draw_batch = RenderPassDrawBatch::default();
+                let mut render_pass_context = RenderPassContext {
+                    gpu_context: &frame_context.gpu_context,
+                    globals: &mut frame_context.globals,
+                    pass_queue: &mut draw_batch,
+                };
+                self.render_items(&mut render_pass_context, pass_index)?;
+
+                let mut render_pass = pass.make_render_pass(
+                    &mut frame_context.command_encoder,
+                    &frame_context.gpu_context.final_render_target,
+                    frame_context.screen_override.as_ref(),
+                    layer,
+                )?;
+
+                render_pass.set_viewport(
+                    0.0,
+                    0.0,
+                    viewport_size[0] as f32,
+                    viewport_size[1] as f32,
+                    0.0,
+                    1.0,
+                );
+
+                draw_batch.render(&mut render_pass);
             }
-
-            if pass.is_screen_pass() {
-                self.render_screen_pass(pass_index, frame_context)?;
-                continue;
-            }
-
-            let mut draw_batch = RenderPassDrawBatch::default();
-            let mut render_pass_context = RenderPassContext {
-                gpu_context: &frame_context.gpu_context,
-                globals: &mut frame_context.globals,
-                pass_queue: &mut draw_batch,
-            };
-            self.render_items(&mut render_pass_context, pass_index)?;
-
-            let mut render_pass = pass.make_render_pass(&mut frame_context.command_encoder)?;
-
-            render_pass.set_viewport(
-                0.0,
-                0.0,
-                viewport_size[0] as f32,
-                viewport_size[1] as f32,
-                0.0,
-                1.0,
-            );
-
-            draw_batch.render(&mut render_pass);
         }
 
         Ok(())
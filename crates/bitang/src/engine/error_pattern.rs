@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::{FrameContext, GpuContext, RenderPassDrawBatch};
+use crate::engine::core::context::RenderPassDrawCommand;
+use crate::engine::core::shader::ShaderBindGroup;
+
+const ERROR_PATTERN_SHADER_SOURCE: &str = include_str!("error_pattern.wgsl");
+
+/// Fills the screen with a striped pattern, so the cut of a chart that failed to load
+/// stands out during playback.
+pub struct ErrorPattern {
+    pub id: String,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ErrorPattern {
+    pub fn new(context: &GpuContext, id: &str) -> Self {
+        let shader_module = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("error pattern"),
+            source: wgpu::ShaderSource::Wgsl(ERROR_PATTERN_SHADER_SOURCE.into()),
+        });
+
+        // Draw commands always bind a vertex and a fragment bind group
+        let bind_group_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("error pattern"),
+                entries: &[],
+            });
+        let pipeline_layout =
+            context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("error pattern"),
+                bind_group_layouts: &[&bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("error pattern"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(
+                    context.final_render_target.pixel_format.wgpu_format().into(),
+                )],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("error pattern"),
+            layout: &bind_group_layout,
+            entries: &[],
+        });
+
+        Self {
+            id: id.to_string(),
+            pipeline,
+            bind_group,
+        }
+    }
+
+    /// Draws the pattern to the screen, or to the image that replaces it.
+    pub fn execute(&self, context: &mut FrameContext) -> Result<()> {
+        let bind_group = ShaderBindGroup {
+            bind_group: self.bind_group.clone(),
+            uniform_offset: None,
+        };
+        let draw_command = RenderPassDrawCommand {
+            pipeline: self.pipeline.clone(),
+            vertex_buffer: None,
+            instance_buffer: None,
+            indirect_buffer: None,
+            index_buffer: None,
+            vertex_bind_group: bind_group.clone(),
+            fragment_bind_group: bind_group,
+            vertex_count: 3,
+            index_count: 0,
+            instance_count: 1,
+            stencil_reference: 0,
+        };
+
+        let target = match &context.screen_override {
+            Some(screen_override) => Arc::clone(screen_override),
+            None => Arc::clone(&context.gpu_context.final_render_target),
+        };
+        if target.is_swapchain() {
+            // The screen is drawn later by the UI
+            context.screen_pass_draw_batch.draw_commands.push(draw_command);
+            return Ok(());
+        }
+
+        let mut draw_batch = RenderPassDrawBatch::default();
+        draw_batch.draw_commands.push(draw_command);
+        let view = target.view_as_render_target()?;
+        let mut render_pass =
+            context.command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("error pattern"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        draw_batch.render(&mut render_pass);
+        Ok(())
+    }
+}
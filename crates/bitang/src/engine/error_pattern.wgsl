@@ -0,0 +1,25 @@
+// Diagonal magenta and black stripes drawn by the placeholder of a chart that failed to load.
+
+const STRIPE_WIDTH: f32 = 32.0;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+};
+
+// Draws one large triangle over the clip space, see blit.wgsl
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let stripe = u32(floor((in.position.x + in.position.y) / STRIPE_WIDTH)) & 1u;
+    if (stripe == 0u) {
+        return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+}
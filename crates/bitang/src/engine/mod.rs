@@ -4,27 +4,42 @@ mod compute;
 mod control;
 mod core;
 mod draw;
+mod error_pattern;
 mod generate_mip_levels;
 mod material;
 mod pass;
+mod prefilter_environment;
+mod procedural_object;
 mod project;
 mod render_object;
+mod run_once;
 mod scene;
+mod sort;
+mod tempo_map;
+mod transition;
 
-pub use core::compute_call::ComputeCall;
+pub use core::compute_call::{ComputeCall, DispatchSize};
 pub use core::context::{
     ComputePassContext, FrameContext, GpuContext, RenderPassContext, RenderPassDrawBatch, Viewport,
 };
-pub use core::double_buffer::DoubleBuffer;
-pub use core::draw_call::{BlendMode, DrawCall, DrawCallProps};
+pub use core::draw_call::{
+    BlendMode, ColorTargetProps, ColorWriteMask, Compare, CullFace, DepthBias, DrawCall,
+    DrawCallProps, FrontFace, InstanceLayout, PolygonMode, StencilOperation, StencilProps,
+    Topology, VertexLayout,
+};
 pub use core::globals::{GlobalType, Globals};
-pub use core::image::{BitangImage, ImageSizeRule, PixelFormat};
+pub use core::image::{BitangImage, ImageDimension, ImageSizeRule, PixelFormat};
 pub use core::mesh::Mesh;
 pub use core::mipmap_generator::MipmapGenerator;
 pub use core::shader::{
-    DescriptorResource, DescriptorSource, GlobalUniformMapping, ImageDescriptor,
+    BufferDescriptor, DescriptorResource, DescriptorSource, GlobalUniformMapping, ImageDescriptor,
     LocalUniformMapping, SamplerDescriptor, SamplerMode, Shader, ShaderKind,
+    StorageImageDescriptor,
+};
+pub use core::storage_buffer::{
+    ensure_indirect_args, BufferSlot, IndirectArgsKind, StorageBuffer, StorageBufferProps,
 };
+pub use core::uniform_ring::UniformRing;
 pub use core::{Size2D, Vertex3};
 
 pub use camera::Camera;
@@ -36,12 +51,19 @@ pub use control::controls::{
 pub use control::spline::SplinePoint;
 pub use control::{ControlId, ControlIdPartType};
 pub use draw::{Draw, DrawItem};
+pub use error_pattern::ErrorPattern;
 pub use generate_mip_levels::GenerateMipLevels;
 pub use material::Material;
-pub use pass::{FramebufferInfo, Pass};
+pub use pass::{CubeFace, FramebufferInfo, Pass, RenderLayer, StencilLoad};
+pub use prefilter_environment::{PrefilterEnvironment, PrefilterOutput};
+pub use procedural_object::ProceduralObject;
 pub use project::{Cut, Project};
-pub use render_object::RenderObject;
+pub use render_object::{InstanceBuffer, RenderObject};
+pub use run_once::RunOnce;
 pub use scene::Scene;
+pub use sort::{ensure_sort_key_field, Sort, SortKey, SortOrder};
+pub use tempo_map::TempoMap;
+pub use transition::{Transition, TransitionKind};
 
 /// How many times the simulation is updated per second.
 /// Weird number on purpose so issues are easier to spot.
@@ -1,15 +1,121 @@
 use std::sync::Arc;
 
 use anyhow::{bail, ensure, Result};
+use glam::Vec3;
+use serde::Deserialize;
 use smallvec::SmallVec;
 
-use super::{BitangImage, FrameContext, PixelFormat, Size2D};
+use super::{BitangImage, FrameContext, ImageDimension, PixelFormat, Size2D};
+
+/// A face of a cube image, in layer order
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    pub fn layer(self) -> u32 {
+        self as u32
+    }
+
+    /// Returns the view direction and up vector of a camera looking through the face.
+    pub fn view_direction_and_up(self) -> (Vec3, Vec3) {
+        match self {
+            CubeFace::PositiveX => (Vec3::X, Vec3::Y),
+            CubeFace::NegativeX => (Vec3::NEG_X, Vec3::Y),
+            CubeFace::PositiveY => (Vec3::Y, Vec3::NEG_Z),
+            CubeFace::NegativeY => (Vec3::NEG_Y, Vec3::Z),
+            CubeFace::PositiveZ => (Vec3::Z, Vec3::Y),
+            CubeFace::NegativeZ => (Vec3::NEG_Z, Vec3::Y),
+        }
+    }
+}
+
+/// The layer of array and cube images a pass renders to.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub enum RenderLayer {
+    /// A layer of an array image. Layer 0 is the only layer of 2D images.
+    Layer(u32),
+
+    /// A face of a cube image
+    Face(CubeFace),
+
+    /// Renders the pass once for each face of cube images, with the camera looking
+    /// through the face
+    AllFaces,
+}
+
+impl Default for RenderLayer {
+    fn default() -> Self {
+        RenderLayer::Layer(0)
+    }
+}
+
+/// How a pass initializes the stencil of its depth image.
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+pub enum StencilLoad {
+    /// Clears the stencil to 0 if the pass has a clear color, otherwise keeps it
+    #[default]
+    FollowClearColor,
+
+    /// Clears the stencil to the value
+    Clear(u32),
+
+    /// Keeps the stencil written by previous passes
+    Load,
+}
 
 // TODO: this might not be needed at all
 #[derive(Clone, Debug)]
 pub struct FramebufferInfo {
     pub color_buffer_formats: Vec<PixelFormat>,
     pub depth_buffer_format: Option<PixelFormat>,
+
+    /// MSAA sample count of all attachments
+    pub sample_count: u32,
+}
+
+impl FramebufferInfo {
+    pub fn has_stencil(&self) -> bool {
+        self.depth_buffer_format.is_some_and(|format| format.wgpu_format().has_stencil_aspect())
+    }
+
+    /// Fails if a draw call declares more color targets than the pass has color images.
+    pub fn ensure_color_target_count(
+        &self,
+        draw_call_id: &str,
+        color_target_count: usize,
+    ) -> Result<()> {
+        let color_image_count = self.color_buffer_formats.len();
+        ensure!(
+            color_target_count <= color_image_count,
+            "Material pass '{draw_call_id}' has {color_target_count} color targets, but the pass has {color_image_count} color images"
+        );
+        Ok(())
+    }
+
+    /// Fails if a draw call uses stencil, but the depth image of the pass has none.
+    pub fn ensure_stencil(&self, draw_call_id: &str) -> Result<()> {
+        ensure!(
+            self.has_stencil(),
+            "Draw call '{draw_call_id}' uses stencil, but the depth image of the pass has no stencil"
+        );
+        Ok(())
+    }
 }
 
 pub struct Pass {
@@ -17,6 +123,8 @@ pub struct Pass {
     pub color_buffers: Vec<Arc<BitangImage>>,
     pub depth_buffer: Option<Arc<BitangImage>>,
     pub clear_color: Option<[f32; 4]>,
+    pub render_layer: RenderLayer,
+    pub stencil_load: StencilLoad,
     pub framebuffer_info: FramebufferInfo,
 }
 
@@ -26,20 +134,49 @@ impl Pass {
         color_buffers: Vec<Arc<BitangImage>>,
         depth_buffer: Option<Arc<BitangImage>>,
         clear_color: Option<[f32; 4]>,
+        render_layer: RenderLayer,
+        stencil_load: StencilLoad,
     ) -> Result<Self> {
-        let color_buffer_formats =
-            color_buffers.iter().map(|image| image.pixel_format).collect::<Vec<_>>();
-        let depth_buffer_format = depth_buffer.as_ref().map(|image| image.pixel_format);
+        let sample_count =
+            color_buffers.iter().chain(&depth_buffer).next().map_or(1, |image| image.sample_count);
         let framebuffer_info = FramebufferInfo {
-            color_buffer_formats,
-            depth_buffer_format,
+            color_buffer_formats: color_buffers.iter().map(|image| image.pixel_format).collect(),
+            depth_buffer_format: depth_buffer.as_ref().map(|image| image.pixel_format),
+            sample_count,
         };
+        if !matches!(stencil_load, StencilLoad::FollowClearColor) {
+            ensure!(
+                framebuffer_info.has_stencil(),
+                "Pass '{id}' sets a stencil load, but its depth image has no stencil"
+            );
+        }
+        for image in color_buffers.iter().chain(&depth_buffer) {
+            ensure!(
+                image.sample_count == sample_count,
+                "Image '{}' in Pass '{id}' has a different sample count than other images",
+                image.id,
+            );
+            match render_layer {
+                RenderLayer::Layer(layer) => ensure!(
+                    layer < image.dimension.layer_count(),
+                    "Image '{}' in Pass '{id}' has no layer {layer}",
+                    image.id,
+                ),
+                RenderLayer::Face(_) | RenderLayer::AllFaces => ensure!(
+                    image.dimension == ImageDimension::Cube,
+                    "Image '{}' in Pass '{id}' is not a cube image",
+                    image.id,
+                ),
+            }
+        }
 
         Ok(Pass {
             id: id.to_string(),
             depth_buffer,
             color_buffers,
             clear_color,
+            render_layer,
+            stencil_load,
             framebuffer_info,
         })
     }
@@ -50,27 +187,56 @@ impl Pass {
             && self.color_buffers.iter().all(|image| image.is_swapchain())
     }
 
+    /// Returns the image layers the pass renders to, along with the cube face for each.
+    pub fn target_layers(&self) -> SmallVec<[(u32, Option<CubeFace>); 6]> {
+        match self.render_layer {
+            RenderLayer::Layer(layer) => SmallVec::from_slice(&[(layer, None)]),
+            RenderLayer::Face(face) => SmallVec::from_slice(&[(face.layer(), Some(face))]),
+            RenderLayer::AllFaces => {
+                CubeFace::ALL.iter().map(|face| (face.layer(), Some(*face))).collect()
+            }
+        }
+    }
+
+    /// Begins a render pass to a layer of the images. If `screen_override` is set, it
+    /// replaces `final_render_target`.
     pub fn make_render_pass<'pass, 'frame>(
         &'pass self,
         command_encoder: &'pass mut wgpu::CommandEncoder,
+        final_render_target: &Arc<BitangImage>,
+        screen_override: Option<&Arc<BitangImage>>,
+        layer: u32,
     ) -> Result<wgpu::RenderPass<'pass>> {
         // Collect attachment texture views
         let color_attachment_views: SmallVec<[_; 64]> = self
             .color_buffers
             .iter()
-            .map(|image| image.view_as_render_target())
+            .map(|image| match screen_override {
+                Some(screen_override) if Arc::ptr_eq(image, final_render_target) => {
+                    screen_override.view_as_render_target()
+                }
+                _ => image.view_layer_as_render_target(layer),
+            })
+            .collect::<Result<_>>()?;
+        let resolve_target_views: SmallVec<[_; 64]> = self
+            .color_buffers
+            .iter()
+            .map(|image| image.view_as_resolve_target())
             .collect::<Result<_>>()?;
 
         let depth_buffer_view = self
             .depth_buffer
             .as_ref()
-            .map(|depth_image| depth_image.view_as_render_target())
+            .map(|depth_image| depth_image.view_layer_as_render_target(layer))
             .transpose()?;
 
         // Collect attachments
         let mut color_attachments = SmallVec::<[_; 64]>::new();
         for i in 0..color_attachment_views.len() {
-            color_attachments.push(Some(self.make_color_attachment(&color_attachment_views[i])));
+            color_attachments.push(Some(self.make_color_attachment(
+                &color_attachment_views[i],
+                resolve_target_views[i].as_ref(),
+            )));
         }
         let depth_stencil_attachment =
             depth_buffer_view.as_ref().map(|view| self.make_depth_attachment(view));
@@ -89,6 +255,7 @@ impl Pass {
     fn make_color_attachment<'a>(
         &self,
         texture_view: &'a wgpu::TextureView,
+        resolve_target: Option<&'a wgpu::TextureView>,
     ) -> wgpu::RenderPassColorAttachment<'a> {
         let load = match &self.clear_color {
             Some(clear_color) => wgpu::LoadOp::Clear(wgpu::Color {
@@ -102,7 +269,7 @@ impl Pass {
 
         wgpu::RenderPassColorAttachment {
             view: texture_view,
-            resolve_target: None,
+            resolve_target,
             ops: wgpu::Operations {
                 load,
                 store: wgpu::StoreOp::Store,
@@ -118,6 +285,15 @@ impl Pass {
             Some(_) => wgpu::LoadOp::Clear(1.0),
             None => wgpu::LoadOp::Load,
         };
+        let stencil_load = match self.stencil_load {
+            StencilLoad::FollowClearColor if self.clear_color.is_some() => wgpu::LoadOp::Clear(0),
+            StencilLoad::FollowClearColor | StencilLoad::Load => wgpu::LoadOp::Load,
+            StencilLoad::Clear(value) => wgpu::LoadOp::Clear(value),
+        };
+        let has_stencil = self
+            .framebuffer_info
+            .depth_buffer_format
+            .is_some_and(|format| format.wgpu_format().has_stencil_aspect());
 
         wgpu::RenderPassDepthStencilAttachment {
             view: texture_view,
@@ -125,7 +301,10 @@ impl Pass {
                 load,
                 store: wgpu::StoreOp::Store,
             }),
-            stencil_ops: None,
+            stencil_ops: has_stencil.then_some(wgpu::Operations {
+                load: stencil_load,
+                store: wgpu::StoreOp::Store,
+            }),
         }
     }
 
@@ -0,0 +1,285 @@
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
+use anyhow::{ensure, Result};
+
+use super::{BitangImage, FrameContext, GpuContext, ImageDimension, RunOnce};
+
+const PREFILTER_SHADER_SOURCE: &str = include_str!("prefilter_environment.wgsl");
+
+/// Size of `PrefilterUniforms` in prefilter_environment.wgsl
+const UNIFORMS_BYTE_SIZE: u64 = 8;
+
+const SAMPLE_COUNT: u32 = 1024;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterUniforms {
+    roughness: f32,
+    sample_count: u32,
+}
+
+#[derive(Clone, Copy)]
+pub enum PrefilterOutput {
+    /// Prefiltered specular reflections, mip level n is for roughness n / (levels - 1)
+    Specular,
+
+    /// Diffuse irradiance
+    Irradiance,
+
+    /// Split-sum BRDF lookup table with n_dot_v along x and roughness along y
+    BrdfLut,
+}
+
+impl PrefilterOutput {
+    /// Fails if the image can't hold the output.
+    pub fn ensure_image(self, image: &BitangImage) -> Result<()> {
+        ensure!(
+            image.dimension == ImageDimension::D2 && image.sample_count == 1,
+            "Prefiltered image '{}' must be a 2D image without multisampling",
+            image.id
+        );
+        if let PrefilterOutput::Specular = self {
+            ensure!(
+                image.has_mipmaps(),
+                "Prefiltered specular image '{}' must have mipmaps",
+                image.id
+            );
+        }
+        Ok(())
+    }
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            PrefilterOutput::Specular => "fs_specular",
+            PrefilterOutput::Irradiance => "fs_irradiance",
+            PrefilterOutput::BrdfLut => "fs_brdf_lut",
+        }
+    }
+}
+
+struct PrefilterTarget {
+    image: Arc<BitangImage>,
+    output: PrefilterOutput,
+    pipeline: wgpu::RenderPipeline,
+}
+
+/// Precalculates image-based lighting maps from an equirectangular environment map.
+///
+/// The outputs are equirectangular chart images using the same mapping as the source,
+/// except the BRDF lookup table which doesn't depend on the source.
+pub struct PrefilterEnvironment {
+    pub id: String,
+    source: Arc<BitangImage>,
+    targets: Vec<PrefilterTarget>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    run_once: RunOnce,
+}
+
+impl PrefilterEnvironment {
+    /// Fails if the environment map isn't a 2D image.
+    pub fn ensure_source(source: &BitangImage) -> Result<()> {
+        ensure!(
+            source.dimension == ImageDimension::D2,
+            "Environment map '{}' must be a 2D image",
+            source.id
+        );
+        Ok(())
+    }
+
+    pub fn new(
+        context: &GpuContext,
+        id: &str,
+        source: Arc<BitangImage>,
+        outputs: Vec<(Arc<BitangImage>, PrefilterOutput)>,
+        run_once: RunOnce,
+    ) -> Result<Self> {
+        Self::ensure_source(&source)?;
+        for (image, output) in &outputs {
+            output.ensure_image(image)?;
+        }
+
+        let shader_module = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("prefilter environment"),
+            source: wgpu::ShaderSource::Wgsl(PREFILTER_SHADER_SOURCE.into()),
+        });
+        let bind_group_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("prefilter environment"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(UNIFORMS_BYTE_SIZE),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let pipeline_layout =
+            context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("prefilter environment"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let targets = outputs
+            .into_iter()
+            .map(|(image, output)| {
+                let pipeline =
+                    context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(output.entry_point()),
+                        layout: Some(&pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: &shader_module,
+                            entry_point: Some("vs_main"),
+                            compilation_options: Default::default(),
+                            buffers: &[],
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module: &shader_module,
+                            entry_point: Some(output.entry_point()),
+                            compilation_options: Default::default(),
+                            targets: &[Some(image.pixel_format.wgpu_format().into())],
+                        }),
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: None,
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview: None,
+                        cache: None,
+                    });
+                PrefilterTarget {
+                    image,
+                    output,
+                    pipeline,
+                }
+            })
+            .collect();
+
+        // Same addressing as the envmap sampler of materials
+        let sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("prefilter environment"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            id: id.to_string(),
+            source,
+            targets,
+            bind_group_layout,
+            sampler,
+            run_once,
+        })
+    }
+
+    pub fn execute(&self, context: &mut FrameContext) -> Result<()> {
+        if !self.run_once.needs_run() {
+            return Ok(());
+        }
+        let gpu_context = &context.gpu_context;
+
+        // One uniform block for every rendered mip level, in order
+        let mut levels = vec![];
+        for target in &self.targets {
+            let mip_levels = target.image.mip_levels()?;
+            for mip_level in 0..mip_levels {
+                let roughness = match target.output {
+                    // A 1x1 image has a single level even with mipmaps
+                    PrefilterOutput::Specular => mip_level as f32 / (mip_levels - 1).max(1) as f32,
+                    PrefilterOutput::Irradiance | PrefilterOutput::BrdfLut => 0.0,
+                };
+                levels.push((target, mip_level, roughness));
+            }
+        }
+        let alignment = gpu_context.device.limits().min_uniform_buffer_offset_alignment as usize;
+        let stride = (UNIFORMS_BYTE_SIZE as usize).next_multiple_of(alignment);
+        let mut uniform_data = vec![0u8; levels.len() * stride];
+        for (index, (_, _, roughness)) in levels.iter().enumerate() {
+            let uniforms = PrefilterUniforms {
+                roughness: *roughness,
+                sample_count: SAMPLE_COUNT,
+            };
+            uniform_data[index * stride..][..UNIFORMS_BYTE_SIZE as usize]
+                .copy_from_slice(bytemuck::bytes_of(&uniforms));
+        }
+        let uniforms =
+            gpu_context.uniform_ring.write(&gpu_context.device, &gpu_context.queue, &uniform_data);
+
+        let source_view = self.source.view_as_sampler(wgpu::TextureViewDimension::D2)?;
+        let bind_group = gpu_context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("prefilter environment"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &uniforms.buffer,
+                        offset: 0,
+                        size: NonZeroU64::new(UNIFORMS_BYTE_SIZE),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        for (index, (target, mip_level, _)) in levels.iter().enumerate() {
+            let view = target.image.view_mip_level(*mip_level, 0)?;
+            let mut render_pass =
+                context.command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("prefilter environment"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+            render_pass.set_pipeline(&target.pipeline);
+            render_pass.set_bind_group(
+                0,
+                &bind_group,
+                &[uniforms.offset + (index * stride) as u32],
+            );
+            render_pass.draw(0..3, 0..1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+// Image-based lighting precalculation from an equirectangular environment map.
+// fs_specular prefilters the map for the roughness of the current mip level,
+// fs_irradiance integrates the diffuse irradiance and fs_brdf_lut calculates the
+// split-sum BRDF lookup table. Uses the equirectangular mapping of the demo shaders.
+
+const PI: f32 = 3.14159265359;
+
+struct PrefilterUniforms {
+    // Roughness of the prefiltered specular level
+    roughness: f32,
+
+    // Number of samples per pixel
+    sample_count: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: PrefilterUniforms;
+@group(0) @binding(1) var source: texture_2d<f32>;
+@group(0) @binding(2) var source_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn direction_to_uv(direction_wn: vec3<f32>) -> vec2<f32> {
+    let phi = atan2(direction_wn.z, direction_wn.x);
+    let theta = acos(clamp(direction_wn.y, -1.0, 1.0));
+    return vec2<f32>(phi / (2.0 * PI) + 0.25, theta / PI);
+}
+
+fn uv_to_direction(uv: vec2<f32>) -> vec3<f32> {
+    let phi = (uv.x - 0.25) * 2.0 * PI;
+    let theta = uv.y * PI;
+    return vec3<f32>(sin(theta) * cos(phi), cos(theta), sin(theta) * sin(phi));
+}
+
+fn radical_inverse(bits_in: u32) -> f32 {
+    var bits = (bits_in << 16u) | (bits_in >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), radical_inverse(index));
+}
+
+// Returns a tangent space to world space transformation around `normal_wn`
+fn tangent_frame(normal_wn: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(normal_wn.y) > 0.999) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, normal_wn));
+    let bitangent = cross(normal_wn, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal_wn);
+}
+
+// GGX importance sampled half vector in tangent space
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Mip level of the source for a sample covering `solid_angle`, filters out the noise of
+// sparse sampling
+fn source_lod(solid_angle: f32) -> f32 {
+    let size = vec2<f32>(textureDimensions(source));
+    let texel_solid_angle = 4.0 * PI / (size.x * size.y);
+    return max(0.5 * log2(solid_angle / texel_solid_angle) + 1.0, 0.0);
+}
+
+fn sample_source(direction_wn: vec3<f32>, lod: f32) -> vec3<f32> {
+    return textureSampleLevel(source, source_sampler, direction_to_uv(direction_wn), lod).rgb;
+}
+
+@fragment
+fn fs_specular(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = uv_to_direction(in.uv);
+    if (uniforms.roughness <= 0.0) {
+        return vec4<f32>(sample_source(normal, 0.0), 1.0);
+    }
+
+    // The view direction is assumed to be the normal
+    let frame = tangent_frame(normal);
+    var color = vec3<f32>(0.0);
+    var total_weight = 0.0;
+    for (var i = 0u; i < uniforms.sample_count; i++) {
+        let half_vector = frame * importance_sample_ggx(hammersley(i, uniforms.sample_count), uniforms.roughness);
+        let light = normalize(2.0 * dot(normal, half_vector) * half_vector - normal);
+        let n_dot_l = dot(normal, light);
+        if (n_dot_l > 0.0) {
+            let n_dot_h = max(dot(normal, half_vector), 0.0);
+            let pdf = distribution_ggx(n_dot_h, uniforms.roughness) / 4.0 + 0.0001;
+            let solid_angle = 1.0 / (f32(uniforms.sample_count) * pdf);
+            color += sample_source(light, source_lod(solid_angle)) * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(total_weight, 0.0001), 1.0);
+}
+
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = uv_to_direction(in.uv);
+    let frame = tangent_frame(normal);
+
+    // Cosine weighted hemisphere sampling, the weights cancel out with the pdf
+    var irradiance = vec3<f32>(0.0);
+    for (var i = 0u; i < uniforms.sample_count; i++) {
+        let xi = hammersley(i, uniforms.sample_count);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let light = frame * vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+        let pdf = cos_theta / PI + 0.0001;
+        let solid_angle = 1.0 / (f32(uniforms.sample_count) * pdf);
+        irradiance += sample_source(light, source_lod(solid_angle));
+    }
+    return vec4<f32>(irradiance / f32(uniforms.sample_count), 1.0);
+}
+
+// x is n_dot_v and y is roughness, sampled like `brdf_lut` in the demo shaders
+@fragment
+fn fs_brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.uv.x, 0.0001);
+    let roughness = in.uv.y;
+    let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < uniforms.sample_count; i++) {
+        let half_vector = importance_sample_ggx(hammersley(i, uniforms.sample_count), roughness);
+        let light = normalize(2.0 * dot(view, half_vector) * half_vector - view);
+        let n_dot_l = max(light.z, 0.0);
+        let n_dot_h = max(half_vector.z, 0.0);
+        let v_dot_h = max(dot(view, half_vector), 0.0);
+        if (n_dot_l > 0.0) {
+            let geometry = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let visibility = geometry * v_dot_h / (n_dot_h * n_dot_v + 0.0001);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    let count = f32(uniforms.sample_count);
+    return vec4<f32>(scale / count, bias / count, 0.0, 1.0);
+}
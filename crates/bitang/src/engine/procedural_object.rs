@@ -0,0 +1,42 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::{Material, RenderPassContext, StorageBuffer};
+
+/// Draws vertices without a mesh. The vertex shader generates the geometry
+/// from `vertex_index` and `instance_index`.
+pub struct ProceduralObject {
+    pub _id: String,
+    pub material: Arc<Material>,
+    pub vertex_count: u32,
+    pub instance_count: u32,
+
+    /// Draw arguments written by compute shaders, overrides the vertex and instance counts
+    pub indirect_buffer: Option<Rc<StorageBuffer>>,
+}
+
+impl ProceduralObject {
+    pub fn render(
+        &self,
+        context: &mut RenderPassContext,
+        material_pass_index: usize,
+    ) -> Result<()> {
+        let Some(material_pass) = self.material.get_pass(material_pass_index) else {
+            return Ok(());
+        };
+
+        let saved_globals = *context.globals;
+        context.globals.instance_count = self.instance_count as f32;
+
+        let result = material_pass.render_without_mesh(
+            context,
+            self.vertex_count,
+            self.indirect_buffer.as_deref(),
+        );
+        *context.globals = saved_globals;
+
+        result
+    }
+}
@@ -3,7 +3,13 @@ use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use super::Chart;
+use anyhow::Result;
+use itertools::Itertools;
+
+use super::{
+    BitangImage, Chart, ControlSet, FrameContext, Globals, GpuContext, ImageDimension,
+    ImageSizeRule, TempoMap, Transition,
+};
 
 pub struct Project {
     pub root_path: Arc<PathBuf>,
@@ -11,6 +17,15 @@ pub struct Project {
     pub charts: Vec<Rc<Chart>>,
     pub cuts: Vec<Cut>,
     pub length: f32,
+
+    /// Project level controls, e.g. transition progress
+    pub controls: Rc<ControlSet>,
+
+    /// Beats and bars of the music, if the project has a tempo
+    pub tempo_map: Option<TempoMap>,
+
+    /// The previous and the next cut are rendered to these images during a transition
+    transition_images: [Arc<BitangImage>; 2],
 }
 
 pub struct Cut {
@@ -18,13 +33,35 @@ pub struct Cut {
     pub start_time: f32,
     pub end_time: f32,
     pub offset: f32,
+
+    /// Set if the chart or the transition failed to load. `chart` is then either the last
+    /// successfully loaded version of the chart or a placeholder, and a failed transition
+    /// is replaced by a hard cut.
+    pub load_error: Option<Rc<String>>,
+
+    /// Blends the previous cut into this one at the start of the cut
+    pub transition: Option<Transition>,
+}
+
+impl Cut {
+    pub fn contains(&self, time: f32) -> bool {
+        self.start_time <= time && time <= self.end_time
+    }
+
+    pub fn render(&self, context: &mut FrameContext, time: f32) -> Result<()> {
+        context.globals.chart_time = time - self.start_time + self.offset;
+        self.chart.render(context)
+    }
 }
 
 impl Project {
     pub fn new(
+        context: &GpuContext,
         root_path: &Arc<PathBuf>,
         charts_by_id: HashMap<String, Rc<Chart>>,
         cuts: Vec<Cut>,
+        controls: ControlSet,
+        tempo_map: Option<TempoMap>,
     ) -> Self {
         let mut charts = vec![];
         let mut charts_inserted = HashSet::new();
@@ -40,12 +77,114 @@ impl Project {
             .max_by(|a, b| a.partial_cmp(b).unwrap())
             .unwrap_or(1.0);
 
+        let pixel_format = context.final_render_target.pixel_format;
+        let transition_images = ["__transition_from", "__transition_to"].map(|id| {
+            BitangImage::new_attachment(
+                id,
+                pixel_format,
+                ImageSizeRule::CanvasRelative(1.0),
+                false,
+                1,
+                ImageDimension::D2,
+            )
+        });
+
         Self {
             root_path: Arc::clone(root_path),
             charts_by_id,
             charts,
             cuts,
             length,
+            controls: Rc::new(controls),
+            tempo_map,
+            transition_images,
         }
     }
+
+    /// Renders the cuts at the given project time.
+    ///
+    /// During a transition, the previous and the next cut are rendered to intermediate
+    /// images and blended. Overlapping cuts without a transition are drawn over each other.
+    pub fn render(&self, context: &mut FrameContext, time: f32) -> Result<()> {
+        self.set_tempo_globals(&mut context.globals, time);
+        for control in &self.controls.used_controls {
+            control.evaluate_splines(time);
+        }
+
+        let active_cuts = self.cuts.iter().filter(|cut| cut.contains(time)).collect_vec();
+        let transition = active_cuts.iter().find_map(|&cut| {
+            let transition = cut.transition.as_ref()?;
+            (time < cut.start_time + transition.duration).then_some((cut, transition))
+        });
+        let Some((to_cut, transition)) = transition else {
+            for cut in active_cuts {
+                cut.render(context, time)?;
+            }
+            return Ok(());
+        };
+
+        // Transition from the most recent other cut, or from black if there's none
+        let from_cut = active_cuts
+            .iter()
+            .filter(|&&cut| !std::ptr::eq(cut, to_cut))
+            .max_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        let [from_image, to_image] = &self.transition_images;
+        Self::render_to_image(context, from_image, |context| match from_cut {
+            Some(cut) => cut.render(context, time),
+            None => Ok(()),
+        })?;
+        Self::render_to_image(context, to_image, |context| to_cut.render(context, time))?;
+        transition.render(context, from_image, to_image, time - to_cut.start_time)
+    }
+
+    /// Sets the beat and bar globals for the given project time.
+    pub fn set_tempo_globals(&self, globals: &mut Globals, time: f32) {
+        let (beat, bar) = match &self.tempo_map {
+            Some(tempo_map) => (tempo_map.beat_at(time), tempo_map.bar_at(time)),
+            None => (0.0, 0.0),
+        };
+        globals.beat = beat;
+        globals.bar = bar;
+    }
+
+    /// Clears the image and renders to it instead of the final render target.
+    fn render_to_image(
+        context: &mut FrameContext,
+        image: &Arc<BitangImage>,
+        render: impl FnOnce(&mut FrameContext) -> Result<()>,
+    ) -> Result<()> {
+        image.enforce_size_rule(&context.gpu_context, &context.screen_size)?;
+        {
+            let view = image.view_as_render_target()?;
+            context.command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("clear"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        }
+        context.screen_override = Some(Arc::clone(image));
+        let result = render(context);
+        context.screen_override = None;
+        result
+    }
+
+    /// Returns the load errors of the cuts, without duplicates of charts used by several cuts.
+    pub fn load_errors(&self) -> Vec<&str> {
+        let mut errors_reported = HashSet::new();
+        self.cuts
+            .iter()
+            .filter_map(|cut| cut.load_error.as_deref())
+            .map(String::as_str)
+            .filter(|err| errors_reported.insert(*err))
+            .collect()
+    }
 }
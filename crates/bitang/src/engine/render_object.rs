@@ -4,7 +4,7 @@ use std::sync::Arc;
 use anyhow::Result;
 use glam::{EulerRot, Mat4};
 
-use super::{Control, Material, Mesh, RenderPassContext};
+use super::{BufferSlot, Control, Material, Mesh, RenderPassContext, StorageBuffer};
 
 pub struct RenderObject {
     pub _id: String,
@@ -13,6 +13,18 @@ pub struct RenderObject {
     pub position: Rc<Control>,
     pub rotation: Rc<Control>,
     pub instances: Rc<Control>,
+
+    /// If set, there's one instance per buffer item instead of `instances`
+    pub instance_buffer: Option<InstanceBuffer>,
+
+    /// Draw arguments written by compute shaders, overrides the instance count
+    pub indirect_buffer: Option<Rc<StorageBuffer>>,
+}
+
+/// A storage buffer bound as per-instance vertex data
+pub struct InstanceBuffer {
+    pub buffer: Rc<StorageBuffer>,
+    pub slot: BufferSlot,
 }
 
 impl RenderObject {
@@ -28,9 +40,23 @@ impl RenderObject {
         let saved_globals = *context.globals;
         self.apply_transformations(context);
 
-        context.globals.instance_count = self.instances.as_float().round();
+        let instance_buffer = match &self.instance_buffer {
+            Some(instance_buffer) => {
+                context.globals.instance_count = instance_buffer.buffer.item_count() as f32;
+                Some(instance_buffer.buffer.get_buffer(instance_buffer.slot))
+            }
+            None => {
+                context.globals.instance_count = self.instances.as_float().round();
+                None
+            }
+        };
 
-        let result = material_pass.render(context, &self.mesh);
+        let result = material_pass.render(
+            context,
+            &self.mesh,
+            instance_buffer,
+            self.indirect_buffer.as_deref(),
+        );
         *context.globals = saved_globals;
 
         result
@@ -0,0 +1,52 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::{BitangImage, StorageBuffer};
+
+/// Decides when a step that runs once, e.g. to precompute a lookup texture, has to run.
+///
+/// The step runs after it's loaded, and again when one of its images gets a new texture or
+/// one of its buffers is recreated, e.g. when a canvas-relative image is resized, since the
+/// new resource has no content yet. Controls are not inputs: changing a control the step
+/// reads doesn't rerun it.
+pub struct RunOnce {
+    /// The images the step reads and writes
+    images: Vec<Arc<BitangImage>>,
+
+    /// The storage buffers the step reads and writes
+    buffers: Vec<Rc<StorageBuffer>>,
+
+    /// The textures and buffers at the last run
+    last_resources: RefCell<Option<RunOnceResources>>,
+}
+
+#[derive(PartialEq)]
+struct RunOnceResources {
+    textures: Vec<Option<wgpu::Texture>>,
+    buffers: Vec<wgpu::Buffer>,
+}
+
+impl RunOnce {
+    pub fn new(images: Vec<Arc<BitangImage>>, buffers: Vec<Rc<StorageBuffer>>) -> Self {
+        Self {
+            images,
+            buffers,
+            last_resources: RefCell::new(None),
+        }
+    }
+
+    /// Returns true if the step has to run now. The step is considered done afterwards.
+    pub fn needs_run(&self) -> bool {
+        let resources = RunOnceResources {
+            textures: self.images.iter().map(|image| image.get_texture()).collect(),
+            buffers: self.buffers.iter().flat_map(|buffer| buffer.get_buffers()).collect(),
+        };
+        let mut last_resources = self.last_resources.borrow_mut();
+        if last_resources.as_ref() == Some(&resources) {
+            return false;
+        }
+        *last_resources = Some(resources);
+        true
+    }
+}
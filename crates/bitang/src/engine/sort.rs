@@ -0,0 +1,316 @@
+use std::cell::RefCell;
+use std::num::NonZeroU64;
+use std::rc::Rc;
+
+use anyhow::{ensure, Result};
+
+use super::{BufferSlot, FrameContext, GpuContext, StorageBuffer};
+
+const SORT_SHADER_SOURCE: &str = include_str!("sort.wgsl");
+
+/// `WORKGROUP_SIZE` in sort.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Size of `SortUniforms` in sort.wgsl
+const UNIFORMS_BYTE_SIZE: u64 = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortUniforms {
+    item_count: u32,
+    key_count: u32,
+    item_size: u32,
+    key_stride: u32,
+    key_offset: u32,
+    key_sign: f32,
+    block_size: u32,
+    compare_distance: u32,
+}
+
+pub enum SortKey {
+    /// A float of the sorted items, counted from the start of the item
+    Field(usize),
+
+    /// The first float of the items of another buffer, e.g. view depth written by a compute step
+    Buffer(Rc<StorageBuffer>),
+}
+
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Fails if a key field is outside the items of the sorted buffer.
+pub fn ensure_sort_key_field(
+    offset: usize,
+    buffer_id: &str,
+    item_size_in_vec4: usize,
+) -> Result<()> {
+    ensure!(
+        offset < item_size_in_vec4 * 4,
+        "Sort key offset {offset} is outside the items of buffer '{buffer_id}'"
+    );
+    Ok(())
+}
+
+/// The work buffers of the sort, recreated when the sorted buffer is resized.
+struct SortBuffers {
+    item_count: usize,
+    sort_keys: wgpu::Buffer,
+    sorted_items: wgpu::Buffer,
+}
+
+/// Sorts the items of a storage buffer on the GPU using a bitonic sort.
+///
+/// Both buffers of a double buffer are reordered the same way, so their items still
+/// correspond to each other.
+pub struct Sort {
+    pub id: String,
+    buffer: Rc<StorageBuffer>,
+    key: SortKey,
+    order: SortOrder,
+    bind_group_layout: wgpu::BindGroupLayout,
+    keys_pipeline: wgpu::ComputePipeline,
+    sort_step_pipeline: wgpu::ComputePipeline,
+    gather_pipeline: wgpu::ComputePipeline,
+    sort_buffers: RefCell<Option<SortBuffers>>,
+}
+
+impl Sort {
+    pub fn new(
+        context: &GpuContext,
+        id: &str,
+        buffer: Rc<StorageBuffer>,
+        key: SortKey,
+        order: SortOrder,
+    ) -> Result<Self> {
+        if let SortKey::Field(offset) = key {
+            ensure_sort_key_field(offset, &buffer.id, buffer.item_size_in_vec4)?;
+        }
+
+        let shader_module = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sort"),
+            source: wgpu::ShaderSource::Wgsl(SORT_SHADER_SOURCE.into()),
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("sort"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(UNIFORMS_BYTE_SIZE),
+                        },
+                        count: None,
+                    },
+                    storage_entry(1, true),
+                    storage_entry(2, false),
+                    storage_entry(3, true),
+                    storage_entry(4, false),
+                ],
+            });
+        let pipeline_layout =
+            context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("sort"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_pipeline = |entry_point| {
+            context.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        Ok(Self {
+            id: id.to_string(),
+            keys_pipeline: create_pipeline("cs_keys"),
+            sort_step_pipeline: create_pipeline("cs_sort_step"),
+            gather_pipeline: create_pipeline("cs_gather"),
+            buffer,
+            key,
+            order,
+            bind_group_layout,
+            sort_buffers: RefCell::new(None),
+        })
+    }
+
+    pub fn execute(&self, context: &mut FrameContext) -> Result<()> {
+        let gpu_context = &context.gpu_context;
+        let item_count = self.buffer.item_count();
+        let item_size = self.buffer.item_size_in_vec4;
+        let key_count = item_count.next_power_of_two();
+        let max_workgroups = gpu_context.device.limits().max_compute_workgroups_per_dimension;
+        let key_workgroups = key_count.div_ceil(WORKGROUP_SIZE as usize) as u32;
+        let gather_workgroups = (item_count * item_size).div_ceil(WORKGROUP_SIZE as usize) as u32;
+        ensure!(
+            key_workgroups.max(gather_workgroups) <= max_workgroups,
+            "Buffer '{}' is too large to sort",
+            self.buffer.id
+        );
+
+        let (key_source, key_stride, key_offset) = match &self.key {
+            SortKey::Field(offset) => (
+                self.buffer.get_buffer(BufferSlot::Current),
+                item_size * 4,
+                *offset,
+            ),
+            SortKey::Buffer(key_buffer) => (
+                key_buffer.get_buffer(BufferSlot::Current),
+                key_buffer.item_size_in_vec4 * 4,
+                0,
+            ),
+        };
+        let key_sign = match self.order {
+            SortOrder::Ascending => 1.0,
+            SortOrder::Descending => -1.0,
+        };
+
+        // The first uniforms are used by the key and gather passes, the rest by the
+        // bitonic stages in order.
+        let mut stages = vec![(0, 0)];
+        let mut block_size = 2;
+        while block_size <= key_count {
+            let mut compare_distance = block_size / 2;
+            while compare_distance > 0 {
+                stages.push((block_size, compare_distance));
+                compare_distance /= 2;
+            }
+            block_size *= 2;
+        }
+        let alignment = gpu_context.device.limits().min_uniform_buffer_offset_alignment as usize;
+        let stride = (UNIFORMS_BYTE_SIZE as usize).next_multiple_of(alignment);
+        let mut uniform_data = vec![0u8; stages.len() * stride];
+        for (index, (block_size, compare_distance)) in stages.iter().enumerate() {
+            let uniforms = SortUniforms {
+                item_count: item_count as u32,
+                key_count: key_count as u32,
+                item_size: item_size as u32,
+                key_stride: key_stride as u32,
+                key_offset: key_offset as u32,
+                key_sign,
+                block_size: *block_size as u32,
+                compare_distance: *compare_distance as u32,
+            };
+            uniform_data[index * stride..][..UNIFORMS_BYTE_SIZE as usize]
+                .copy_from_slice(bytemuck::bytes_of(&uniforms));
+        }
+        let uniforms =
+            gpu_context.uniform_ring.write(&gpu_context.device, &gpu_context.queue, &uniform_data);
+
+        let mut sort_buffers = self.sort_buffers.borrow_mut();
+        if sort_buffers.as_ref().is_none_or(|buffers| buffers.item_count != item_count) {
+            *sort_buffers = Some(self.create_sort_buffers(gpu_context, item_count, key_count));
+        }
+        let Some(sort_buffers) = sort_buffers.as_ref() else {
+            unreachable!("Sort buffers should be created");
+        };
+
+        for (index, items) in self.buffer.get_buffers().iter().enumerate() {
+            let bind_group = gpu_context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("sort"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &uniforms.buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(UNIFORMS_BYTE_SIZE),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: key_source.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: sort_buffers.sort_keys.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: items.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: sort_buffers.sorted_items.as_entire_binding(),
+                    },
+                ],
+            });
+
+            {
+                let mut pass = context
+                    .command_encoder
+                    .begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                if index == 0 {
+                    // The order is the same for both buffers of a double buffer
+                    pass.set_pipeline(&self.keys_pipeline);
+                    pass.set_bind_group(0, &bind_group, &[uniforms.offset]);
+                    pass.dispatch_workgroups(key_workgroups, 1, 1);
+
+                    pass.set_pipeline(&self.sort_step_pipeline);
+                    for stage_index in 1..stages.len() {
+                        let offset = uniforms.offset + (stage_index * stride) as u32;
+                        pass.set_bind_group(0, &bind_group, &[offset]);
+                        pass.dispatch_workgroups(key_workgroups, 1, 1);
+                    }
+                }
+                pass.set_pipeline(&self.gather_pipeline);
+                pass.set_bind_group(0, &bind_group, &[uniforms.offset]);
+                pass.dispatch_workgroups(gather_workgroups, 1, 1);
+            }
+
+            context.command_encoder.copy_buffer_to_buffer(
+                &sort_buffers.sorted_items,
+                0,
+                items,
+                0,
+                sort_buffers.sorted_items.size(),
+            );
+        }
+        Ok(())
+    }
+
+    fn create_sort_buffers(
+        &self,
+        context: &GpuContext,
+        item_count: usize,
+        key_count: usize,
+    ) -> SortBuffers {
+        let sort_keys = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sort keys"),
+            size: (key_count * size_of::<[u32; 2]>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let sorted_items = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sorted items"),
+            size: (item_count * self.buffer.item_size_in_vec4 * size_of::<[f32; 4]>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        SortBuffers {
+            item_count,
+            sort_keys,
+            sorted_items,
+        }
+    }
+}
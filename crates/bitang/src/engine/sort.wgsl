@@ -0,0 +1,88 @@
+// Bitonic sort of buffer items by a float key.
+// cs_keys collects the keys, cs_sort_step runs once per bitonic stage and
+// cs_gather writes the items in sorted order.
+
+const WORKGROUP_SIZE: u32 = 64u;
+
+// Sorts after every valid key
+const PADDING_KEY: f32 = 3.4028235e38;
+
+struct SortUniforms {
+    // Number of items in the buffer
+    item_count: u32,
+
+    // Number of keys, the smallest power of two not less than item_count
+    key_count: u32,
+
+    // Size of a buffer item in vec4s
+    item_size: u32,
+
+    // Distance between the keys of consecutive items in key_source, in floats
+    key_stride: u32,
+
+    // Position of the key in a key_source item, in floats
+    key_offset: u32,
+
+    // 1 for ascending, -1 for descending order
+    key_sign: f32,
+
+    // Bitonic block size and compare distance of the current stage
+    block_size: u32,
+    compare_distance: u32,
+}
+
+struct SortKey {
+    key: f32,
+    index: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: SortUniforms;
+@group(0) @binding(1) var<storage, read> key_source: array<f32>;
+@group(0) @binding(2) var<storage, read_write> sort_keys: array<SortKey>;
+@group(0) @binding(3) var<storage, read> items_in: array<vec4<f32>>;
+@group(0) @binding(4) var<storage, read_write> items_out: array<vec4<f32>>;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_keys(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= uniforms.key_count) {
+        return;
+    }
+    var key = PADDING_KEY;
+    if (index < uniforms.item_count) {
+        let key_index = index * uniforms.key_stride + uniforms.key_offset;
+        key = 0.0;
+        if (key_index < arrayLength(&key_source)) {
+            key = key_source[key_index] * uniforms.key_sign;
+        }
+    }
+    sort_keys[index] = SortKey(key, index);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_sort_step(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    let other = index ^ uniforms.compare_distance;
+    if (index >= uniforms.key_count || other <= index) {
+        return;
+    }
+    let a = sort_keys[index];
+    let b = sort_keys[other];
+    let ascending = (index & uniforms.block_size) == 0u;
+    if ((a.key > b.key) == ascending && a.key != b.key) {
+        sort_keys[index] = b;
+        sort_keys[other] = a;
+    }
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_gather(@builtin(global_invocation_id) id: vec3<u32>) {
+    let vec4_index = id.x;
+    if (vec4_index >= uniforms.item_count * uniforms.item_size) {
+        return;
+    }
+    let item_index = vec4_index / uniforms.item_size;
+    let component = vec4_index % uniforms.item_size;
+    let source_index = sort_keys[item_index].index;
+    items_out[vec4_index] = items_in[source_index * uniforms.item_size + component];
+}
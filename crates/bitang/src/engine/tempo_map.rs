@@ -0,0 +1,82 @@
+use anyhow::{ensure, Result};
+
+/// A part of the tempo map with constant tempo.
+struct TempoSegment {
+    start_time: f32,
+    start_beat: f32,
+    beats_per_second: f32,
+}
+
+/// Maps time in seconds to beats and bars of the music.
+///
+/// Beats are counted from zero at the start of the project. The time signature is constant.
+pub struct TempoMap {
+    /// Sorted by time, the first segment starts at zero
+    segments: Vec<TempoSegment>,
+    pub beats_per_bar: u32,
+}
+
+impl TempoMap {
+    pub fn new(bpm: f32, beats_per_bar: u32) -> Result<Self> {
+        ensure!(bpm > 0.0, "BPM must be positive, got {bpm}");
+        ensure!(beats_per_bar > 0, "Bars must have at least one beat");
+        Ok(Self {
+            segments: vec![TempoSegment {
+                start_time: 0.0,
+                start_beat: 0.0,
+                beats_per_second: bpm / 60.0,
+            }],
+            beats_per_bar,
+        })
+    }
+
+    /// Changes the tempo from the given beat. Changes must be added in order.
+    pub fn add_tempo_change(&mut self, beat: f32, bpm: f32) -> Result<()> {
+        ensure!(bpm > 0.0, "BPM must be positive, got {bpm}");
+        // Unwrap is safe: there's always at least one segment
+        let last = self.segments.last().unwrap();
+        ensure!(
+            beat > last.start_beat,
+            "Tempo changes must be in order, beat {beat} is not after beat {}",
+            last.start_beat
+        );
+        let start_time = self.time_at_beat(beat);
+        self.segments.push(TempoSegment {
+            start_time,
+            start_beat: beat,
+            beats_per_second: bpm / 60.0,
+        });
+        Ok(())
+    }
+
+    /// Returns the beat position at the given time. The fraction is the position within the beat.
+    pub fn beat_at(&self, time: f32) -> f32 {
+        let segment = self.find_segment(|segment| segment.start_time <= time);
+        segment.start_beat + (time - segment.start_time) * segment.beats_per_second
+    }
+
+    /// Returns the bar position at the given time. The fraction is the position within the bar.
+    pub fn bar_at(&self, time: f32) -> f32 {
+        self.beat_at(time) / self.beats_per_bar as f32
+    }
+
+    pub fn time_at_beat(&self, beat: f32) -> f32 {
+        let segment = self.find_segment(|segment| segment.start_beat <= beat);
+        segment.start_time + (beat - segment.start_beat) / segment.beats_per_second
+    }
+
+    /// Formats the time as one-based "bar:beat", the way it can be written in the project file.
+    pub fn format_bars_beats(&self, time: f32) -> String {
+        let beat = self.beat_at(time);
+        let bar = (beat / self.beats_per_bar as f32).floor();
+        let beat_in_bar = beat - bar * self.beats_per_bar as f32;
+        format!("{}:{:.2}", bar as i64 + 1, beat_in_bar + 1.0)
+    }
+
+    /// Returns the last segment that starts before the position, or the first one for
+    /// negative positions.
+    fn find_segment(&self, starts_before: impl Fn(&TempoSegment) -> bool) -> &TempoSegment {
+        let index = self.segments.partition_point(starts_before);
+        &self.segments[index.saturating_sub(1)]
+    }
+}
@@ -0,0 +1,288 @@
+use std::num::NonZeroU64;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::{BitangImage, Control, FrameContext, GpuContext, RenderPassDrawBatch};
+use crate::engine::core::context::RenderPassDrawCommand;
+use crate::engine::core::shader::ShaderBindGroup;
+
+const TRANSITION_PRELUDE: &str = include_str!("transition.wgsl");
+
+const CROSSFADE_SOURCE: &str = "
+fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+    return mix(sample_from(uv), sample_to(uv), progress);
+}
+";
+
+const WIPE_SOURCE: &str = "
+const WIPE_SOFTNESS: f32 = 0.02;
+
+fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32> {
+    let edge = progress * (1.0 + WIPE_SOFTNESS) - WIPE_SOFTNESS;
+    return mix(sample_from(uv), sample_to(uv), smoothstep(uv.x - WIPE_SOFTNESS, uv.x, edge));
+}
+";
+
+/// Size of `TransitionUniforms` in transition.wgsl
+const UNIFORMS_BYTE_SIZE: u64 = 16;
+
+pub enum TransitionKind {
+    Crossfade,
+
+    /// Wipes from left to right
+    Wipe,
+
+    /// WGSL source of a custom `transition` function
+    Shader(String),
+}
+
+impl TransitionKind {
+    /// Returns the full WGSL source of the transition shader.
+    pub fn shader_source(&self) -> String {
+        let source = match self {
+            TransitionKind::Crossfade => CROSSFADE_SOURCE,
+            TransitionKind::Wipe => WIPE_SOURCE,
+            TransitionKind::Shader(source) => source,
+        };
+        format!("{TRANSITION_PRELUDE}\n{source}")
+    }
+}
+
+/// Blends the image of the previous cut into the image of the next one.
+pub struct Transition {
+    pub duration: f32,
+
+    /// Overrides the linear progress if it uses a spline. Evaluated at project time.
+    pub progress: Rc<Control>,
+
+    pipeline: wgpu::RenderPipeline,
+    vertex_bind_group: wgpu::BindGroup,
+    fragment_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl Transition {
+    /// Creates a transition. The shader source must be validated by the caller.
+    pub fn new(
+        context: &GpuContext,
+        duration: f32,
+        kind: &TransitionKind,
+        progress: Rc<Control>,
+    ) -> Self {
+        let shader_module = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("transition"),
+            source: wgpu::ShaderSource::Wgsl(kind.shader_source().into()),
+        });
+
+        let vertex_bind_group_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("transition vertex"),
+                entries: &[],
+            });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let fragment_bind_group_layout =
+            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("transition fragment"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(UNIFORMS_BYTE_SIZE),
+                        },
+                        count: None,
+                    },
+                    texture_entry(1),
+                    texture_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let pipeline_layout =
+            context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("transition"),
+                bind_group_layouts: &[&vertex_bind_group_layout, &fragment_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("transition"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(
+                    context.final_render_target.pixel_format.wgpu_format().into(),
+                )],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let vertex_bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("transition vertex"),
+            layout: &vertex_bind_group_layout,
+            entries: &[],
+        });
+
+        let sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("transition"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            duration,
+            progress,
+            pipeline,
+            vertex_bind_group,
+            fragment_bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Returns the progress of the transition between 0 and 1.
+    pub fn get_progress(&self, time_since_start: f32) -> f32 {
+        let component = &self.progress.components.borrow()[0];
+        if component.use_spline {
+            return component.value;
+        }
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        (time_since_start / self.duration).clamp(0.0, 1.0)
+    }
+
+    /// Blends the two images into the final render target.
+    pub fn render(
+        &self,
+        context: &mut FrameContext,
+        from_image: &BitangImage,
+        to_image: &BitangImage,
+        time_since_start: f32,
+    ) -> Result<()> {
+        let gpu_context = &context.gpu_context;
+        let uniforms = [
+            self.get_progress(time_since_start),
+            context.screen_size[0] as f32 / context.screen_size[1] as f32,
+            time_since_start,
+            0.0,
+        ];
+        let uniforms = gpu_context.uniform_ring.write(
+            &gpu_context.device,
+            &gpu_context.queue,
+            bytemuck::cast_slice(&uniforms),
+        );
+        let from_view = from_image.view_as_sampler(wgpu::TextureViewDimension::D2)?;
+        let to_view = to_image.view_as_sampler(wgpu::TextureViewDimension::D2)?;
+        let fragment_bind_group =
+            gpu_context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("transition fragment"),
+                layout: &self.fragment_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &uniforms.buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(UNIFORMS_BYTE_SIZE),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&from_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&to_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+
+        let draw_command = RenderPassDrawCommand {
+            pipeline: self.pipeline.clone(),
+            vertex_buffer: None,
+            instance_buffer: None,
+            indirect_buffer: None,
+            index_buffer: None,
+            vertex_bind_group: ShaderBindGroup {
+                bind_group: self.vertex_bind_group.clone(),
+                uniform_offset: None,
+            },
+            fragment_bind_group: ShaderBindGroup {
+                bind_group: fragment_bind_group,
+                uniform_offset: Some(uniforms.offset),
+            },
+            vertex_count: 3,
+            index_count: 0,
+            instance_count: 1,
+            stencil_reference: 0,
+        };
+
+        let final_render_target = Arc::clone(&gpu_context.final_render_target);
+        if final_render_target.is_swapchain() {
+            // The screen is drawn later by the UI
+            context.screen_pass_draw_batch.draw_commands.push(draw_command);
+            return Ok(());
+        }
+
+        let mut draw_batch = RenderPassDrawBatch::default();
+        draw_batch.draw_commands.push(draw_command);
+        let view = final_render_target.view_as_render_target()?;
+        let mut render_pass =
+            context.command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("transition"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        draw_batch.render(&mut render_pass);
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+// Common code of transition shaders.
+//
+// A transition shader defines this function:
+//     fn transition(uv: vec2<f32>, progress: f32) -> vec4<f32>
+// `progress` goes from 0 to 1 during the transition. Use `sample_from` and `sample_to`
+// to read the image of the previous and the next cut.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+// Draws one large triangle over the clip space, see blit.wgsl
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var result: VertexOutput;
+    let x = i32(vertex_index) / 2;
+    let y = i32(vertex_index) & 1;
+    let tc = vec2<f32>(
+        f32(x) * 2.0,
+        f32(y) * 2.0
+    );
+    result.position = vec4<f32>(
+        tc.x * 2.0 - 1.0,
+        1.0 - tc.y * 2.0,
+        0.0, 1.0
+    );
+    result.tex_coords = tc;
+    return result;
+}
+
+struct TransitionUniforms {
+    progress: f32,
+    aspect_ratio: f32,
+    time: f32,
+    _padding: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> transition_uniforms: TransitionUniforms;
+@group(1) @binding(1)
+var from_image: texture_2d<f32>;
+@group(1) @binding(2)
+var to_image: texture_2d<f32>;
+@group(1) @binding(3)
+var image_sampler: sampler;
+
+fn sample_from(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(from_image, image_sampler, uv, 0.0);
+}
+
+fn sample_to(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(to_image, image_sampler, uv, 0.0);
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return transition(vertex.tex_coords, transition_uniforms.progress);
+}
@@ -0,0 +1,292 @@
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde::Deserialize;
+
+use crate::engine::{self, ControlIdPartType};
+use crate::file::chart_file::ChartContext;
+use crate::loader::gltf_loader::load_points;
+use crate::loader::project_checker::ChartCheckContext;
+
+// The variant names are the chart file format
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Deserialize, Clone)]
+pub enum Buffer {
+    /// A pair of buffers that is swapped every simulation step
+    DoubleBuffer(BufferProps),
+
+    /// A single buffer, e.g. for lookup data or frame computes
+    Buffer(BufferProps),
+
+    /// Arguments of indirect draws and dispatches. Compute shaders overwrite them as u32s.
+    IndirectArgs(IndirectArgsProps),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BufferProps {
+    pub id: String,
+    item_size_in_vec4: usize,
+
+    /// Defaults to the number of items in the initial content
+    #[serde(default)]
+    item_count: Option<usize>,
+
+    /// Name of a chart value that sets the item count. The buffer is recreated when it changes.
+    #[serde(default)]
+    item_count_control: Option<String>,
+
+    #[serde(default)]
+    init: Option<BufferInit>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct IndirectArgsProps {
+    pub id: String,
+
+    /// The initial arguments
+    args: IndirectArgs,
+}
+
+/// Two vec4s fit the largest indirect argument layout
+const INDIRECT_ARGS_ITEM_SIZE_IN_VEC4: usize = 2;
+
+/// The layouts of `wgpu::util::DrawIndirectArgs`, `DrawIndexedIndirectArgs`
+/// and `DispatchIndirectArgs`.
+#[derive(Debug, Deserialize, Clone)]
+pub enum IndirectArgs {
+    /// For procedural draws and meshes without indices
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+    },
+
+    /// For meshes
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+    },
+
+    /// Workgroup counts in x, y and z
+    Dispatch(u32, u32, u32),
+}
+
+impl IndirectArgs {
+    fn kind(&self) -> engine::IndirectArgsKind {
+        match self {
+            IndirectArgs::Draw { .. } => engine::IndirectArgsKind::Draw,
+            IndirectArgs::DrawIndexed { .. } => engine::IndirectArgsKind::DrawIndexed,
+            IndirectArgs::Dispatch(..) => engine::IndirectArgsKind::Dispatch,
+        }
+    }
+}
+
+/// The initial content of a buffer. Items shorter than the item size are padded with zeros.
+#[derive(Debug, Deserialize, Clone)]
+pub enum BufferInit {
+    /// Little-endian 32-bit floats
+    Binary(String),
+
+    /// One item per line, values separated by commas
+    Csv(String),
+
+    /// Vertex positions of the mesh of a node in a glTF file as (x, y, z, 1), transformed
+    /// to world space. The node can be anywhere in the node hierarchy of the default scene.
+    Gltf { file: String, node: String },
+}
+
+impl Buffer {
+    pub fn id(&self) -> &String {
+        match self {
+            Buffer::DoubleBuffer(props) | Buffer::Buffer(props) => &props.id,
+            Buffer::IndirectArgs(props) => &props.id,
+        }
+    }
+
+    pub fn item_size_in_vec4(&self) -> usize {
+        match self {
+            Buffer::DoubleBuffer(props) | Buffer::Buffer(props) => props.item_size_in_vec4,
+            Buffer::IndirectArgs(_) => INDIRECT_ARGS_ITEM_SIZE_IN_VEC4,
+        }
+    }
+
+    pub fn indirect_args(&self) -> Option<engine::IndirectArgsKind> {
+        match self {
+            Buffer::DoubleBuffer(_) | Buffer::Buffer(_) => None,
+            Buffer::IndirectArgs(props) => Some(props.args.kind()),
+        }
+    }
+
+    pub async fn load(&self, chart_context: &ChartContext) -> Result<engine::StorageBuffer> {
+        match self {
+            Buffer::DoubleBuffer(props) => props.load(chart_context, true).await,
+            Buffer::Buffer(props) => props.load(chart_context, false).await,
+            Buffer::IndirectArgs(props) => Ok(props.load(chart_context)),
+        }
+    }
+
+    pub fn check(&self, check_context: &ChartCheckContext) {
+        match self {
+            Buffer::DoubleBuffer(props) | Buffer::Buffer(props) => props.check(check_context),
+            Buffer::IndirectArgs(_) => {}
+        }
+    }
+}
+
+impl BufferProps {
+    async fn load(
+        &self,
+        chart_context: &ChartContext,
+        is_double: bool,
+    ) -> Result<engine::StorageBuffer> {
+        let initial_content = match &self.init {
+            Some(init) => init
+                .load(chart_context, self.item_size_in_vec4)
+                .await
+                .with_context(|| anyhow!("Failed to initialize buffer '{}'", self.id))?,
+            None => vec![],
+        };
+        let item_count = match self.item_count {
+            Some(item_count) => item_count,
+            None => initial_content.len() / self.item_size_in_vec4.max(1),
+        };
+        let item_count_control = self.item_count_control.as_ref().map(|name| {
+            let control_id = chart_context.values_control_id.add(ControlIdPartType::Value, name);
+            chart_context.control_set_builder.get_float_with_default(&control_id, item_count as f32)
+        });
+        Ok(engine::StorageBuffer::new(
+            &chart_context.gpu_context,
+            engine::StorageBufferProps {
+                id: self.id.clone(),
+                item_size_in_vec4: self.item_size_in_vec4,
+                item_count,
+                is_double,
+                item_count_control,
+                initial_content,
+                indirect_args: None,
+            },
+        ))
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        if self.item_size_in_vec4 == 0 {
+            check_context.report(
+                &self.id,
+                anyhow!("Buffer '{}' needs a non-zero item size", self.id),
+            );
+        }
+        match &self.init {
+            Some(init) => check_context.check_file(init.file()),
+            None => {
+                if self.item_count.is_none() {
+                    check_context.report(
+                        &self.id,
+                        anyhow!(
+                            "Buffer '{}' needs an item count or initial content",
+                            self.id
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl IndirectArgsProps {
+    fn load(&self, chart_context: &ChartContext) -> engine::StorageBuffer {
+        let mut words = [0u32; INDIRECT_ARGS_ITEM_SIZE_IN_VEC4 * 4];
+        match self.args {
+            IndirectArgs::Draw {
+                vertex_count,
+                instance_count,
+            } => words[..2].copy_from_slice(&[vertex_count, instance_count]),
+            IndirectArgs::DrawIndexed {
+                index_count,
+                instance_count,
+            } => words[..2].copy_from_slice(&[index_count, instance_count]),
+            IndirectArgs::Dispatch(x, y, z) => words[..3].copy_from_slice(&[x, y, z]),
+        }
+        engine::StorageBuffer::new(
+            &chart_context.gpu_context,
+            engine::StorageBufferProps {
+                id: self.id.clone(),
+                item_size_in_vec4: INDIRECT_ARGS_ITEM_SIZE_IN_VEC4,
+                item_count: 1,
+                is_double: false,
+                item_count_control: None,
+                initial_content: bytemuck::cast_slice(&words).to_vec(),
+                indirect_args: Some(self.args.kind()),
+            },
+        )
+    }
+}
+
+impl BufferInit {
+    fn file(&self) -> &str {
+        match self {
+            BufferInit::Binary(file) | BufferInit::Csv(file) => file,
+            BufferInit::Gltf { file, .. } => file,
+        }
+    }
+
+    async fn load(
+        &self,
+        chart_context: &ChartContext,
+        item_size_in_vec4: usize,
+    ) -> Result<Vec<[f32; 4]>> {
+        ensure!(item_size_in_vec4 > 0, "Item size must be non-zero");
+        let path = chart_context.dependency_path(self.file())?;
+        let content = chart_context.resource_repository.read_binary_file(&path).await?;
+        let item_size = item_size_in_vec4 * 4;
+        let items = match self {
+            BufferInit::Binary(_) => parse_binary(&content, item_size)?,
+            BufferInit::Csv(_) => parse_csv(&String::from_utf8_lossy(&content))?,
+            BufferInit::Gltf { node, .. } => load_points(&content, node)?
+                .into_iter()
+                .map(|[x, y, z]| vec![x, y, z, 1.0])
+                .collect(),
+        };
+        let mut result = Vec::with_capacity(items.len() * item_size_in_vec4);
+        for (index, mut item) in items.into_iter().enumerate() {
+            ensure!(
+                item.len() <= item_size,
+                "Item {index} has {} values, but items have only {item_size}",
+                item.len()
+            );
+            item.resize(item_size, 0.0);
+            result
+                .extend(item.chunks_exact(4).map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]]));
+        }
+        Ok(result)
+    }
+}
+
+/// Splits the floats of a binary file into items. The last item may be partial.
+fn parse_binary(content: &[u8], item_size: usize) -> Result<Vec<Vec<f32>>> {
+    if !content.len().is_multiple_of(4) {
+        bail!("File size {} is not a multiple of 4 bytes", content.len());
+    }
+    let floats = content
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect::<Vec<_>>();
+    Ok(floats.chunks(item_size).map(<[f32]>::to_vec).collect())
+}
+
+fn parse_csv(content: &str) -> Result<Vec<Vec<f32>>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_index, line)| {
+            line.split(',')
+                .map(|value| {
+                    value.trim().parse::<f32>().with_context(|| {
+                        anyhow!(
+                            "Invalid number '{}' in line {}",
+                            value.trim(),
+                            line_index + 1
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
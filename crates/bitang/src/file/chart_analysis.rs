@@ -0,0 +1,318 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::engine::{self, BitangImage, PixelFormat, ShaderKind};
+use crate::file::chart_file::{
+    Chart, ChartStep, Compute, Draw, DrawItem, GenerateMipLevels, ImageSelector, Pass,
+    PrefilterEnvironment, Sort,
+};
+use crate::file::shader_context::Texture;
+
+/// Returns the names of the textures a shader samples, or None if it's not known.
+///
+/// Arguments are the shader path as written in the chart, the shader kind and the textures
+/// bound to the shader.
+pub type SampledTextures<'a> =
+    dyn Fn(&str, ShaderKind, &HashMap<String, Texture>) -> Option<Vec<String>> + 'a;
+
+/// A problem found by the chart analysis.
+pub struct ChartIssue {
+    /// The id the issue is about, used to find the line in the chart file
+    pub anchor: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub struct ChartAnalysis {
+    pub errors: Vec<ChartIssue>,
+    pub warnings: Vec<ChartIssue>,
+}
+
+impl ChartAnalysis {
+    fn error(&mut self, anchor: &str, message: String) {
+        self.errors.push(ChartIssue {
+            anchor: anchor.to_string(),
+            message,
+        });
+    }
+
+    fn warning(&mut self, anchor: &str, message: String) {
+        self.warnings.push(ChartIssue {
+            anchor: anchor.to_string(),
+            message,
+        });
+    }
+
+    /// Records the error of a rule shared with the loader.
+    fn check(&mut self, anchor: &str, result: Result<()>) {
+        if let Err(err) = result {
+            self.error(anchor, format!("{err:#}"));
+        }
+    }
+
+    fn find_duplicates<'a>(&mut self, kind: &str, ids: impl Iterator<Item = &'a String>) {
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        for id in ids {
+            if !seen.insert(id) && reported.insert(id) {
+                self.error(id, format!("Duplicate {kind} id: '{id}'"));
+            }
+        }
+    }
+}
+
+impl Chart {
+    /// Validates the render graph of the chart.
+    ///
+    /// Finds duplicate ids, images that are sampled before any pass or compute writes to them,
+    /// images sampled in the same pass that renders to them and unused images and buffers.
+    /// Images, passes, materials and environment prefilter steps are validated with the
+    /// rules of the engine.
+    pub fn analyze(&self, sampled_textures: &SampledTextures) -> ChartAnalysis {
+        let mut analyzer = ChartAnalyzer::new(self, sampled_textures);
+        analyzer.analysis.find_duplicates("image", self.images.iter().map(|image| &image.id));
+        analyzer.analysis.find_duplicates("buffer", self.buffers.iter().map(|buffer| buffer.id()));
+        analyzer.analysis.find_duplicates("step", self.steps.iter().map(|step| step.id()));
+        analyzer.analyze_images();
+        for step in &self.steps {
+            match step {
+                ChartStep::Draw(draw) => analyzer.analyze_draw(draw),
+                ChartStep::Compute(compute) => analyzer.analyze_compute(compute),
+                ChartStep::GenerateMipLevels(generate_mip_levels) => {
+                    analyzer.analyze_generate_mip_levels(generate_mip_levels)
+                }
+                ChartStep::Sort(sort) => analyzer.analyze_sort(sort),
+                ChartStep::PrefilterEnvironment(prefilter) => analyzer.analyze_prefilter(prefilter),
+                ChartStep::Include(_) => {}
+            }
+        }
+        analyzer.find_unused();
+        analyzer.analysis
+    }
+}
+
+/// The state of the analysis while it walks the steps of a chart in order.
+struct ChartAnalyzer<'a> {
+    chart: &'a Chart,
+    sampled_textures: &'a SampledTextures<'a>,
+    analysis: ChartAnalysis,
+
+    /// The chart images, created without a GPU to validate them with the rules of the engine.
+    /// Unknown ids are reported by the loader.
+    images_by_id: HashMap<&'a str, Arc<BitangImage>>,
+
+    /// Stands in for the swapchain image in passes that render to the screen
+    screen: Arc<BitangImage>,
+
+    /// Images that a step before the current one renders or writes to
+    written_ids: HashSet<String>,
+
+    used_ids: HashSet<String>,
+    used_buffer_ids: HashSet<String>,
+}
+
+impl<'a> ChartAnalyzer<'a> {
+    fn new(chart: &'a Chart, sampled_textures: &'a SampledTextures<'a>) -> Self {
+        Self {
+            chart,
+            sampled_textures,
+            analysis: ChartAnalysis::default(),
+            images_by_id: chart
+                .images
+                .iter()
+                .map(|image| (image.id.as_str(), image.load()))
+                .collect(),
+            // The format of the swapchain depends on the window, but none of the rules do
+            screen: BitangImage::new_swapchain("__screen", PixelFormat::Bgra8Srgb),
+            written_ids: HashSet::new(),
+            used_ids: HashSet::new(),
+            used_buffer_ids: HashSet::new(),
+        }
+    }
+
+    fn analyze_images(&mut self) {
+        for image in &self.chart.images {
+            self.analysis.check(&image.id, image.load().ensure_valid_attachment());
+        }
+    }
+
+    /// Reports problems with an image that a step samples.
+    fn check_read(&mut self, step_id: &str, id: &str) {
+        let Some(image) = self.images_by_id.get(id) else {
+            return;
+        };
+        let result = image.ensure_sampleable().with_context(|| format!("Step '{step_id}'"));
+        self.analysis.check(step_id, result);
+        if !self.written_ids.contains(id) {
+            self.analysis.warning(
+                step_id,
+                format!(
+                    "Image '{id}' is sampled in step '{step_id}' before any pass renders to it"
+                ),
+            );
+        }
+    }
+
+    fn analyze_draw(&mut self, draw: &Draw) {
+        self.used_buffer_ids.extend(draw.items.iter().flat_map(DrawItem::bound_buffer_ids));
+        for pass in &draw.passes {
+            if let Some(engine_pass) = self.load_pass(draw, pass) {
+                self.analyze_materials(draw, pass, &engine_pass.framebuffer_info);
+            }
+
+            let targets = pass.render_target_ids();
+            let reads: BTreeSet<String> = draw
+                .items
+                .iter()
+                .flat_map(|item| item.material().sampled_image_ids(&pass.id, self.sampled_textures))
+                .collect();
+            for id in &reads {
+                if targets.contains(&id.as_str()) {
+                    self.analysis.error(
+                        &draw.id,
+                        format!(
+                            "Image '{id}' is sampled in pass '{}' of step '{}' that renders to it",
+                            pass.id, draw.id
+                        ),
+                    );
+                } else {
+                    self.check_read(&draw.id, id);
+                }
+            }
+            self.written_ids.extend(targets.iter().map(|id| id.to_string()));
+            self.used_ids.extend(targets.iter().map(|id| id.to_string()));
+            self.used_ids.extend(reads);
+        }
+    }
+
+    /// Creates the pass the way the loader does. Returns None if the pass has unknown images
+    /// or breaks a rule of the engine.
+    fn load_pass(&mut self, draw: &Draw, pass: &Pass) -> Option<engine::Pass> {
+        let load_image = |selector: &ImageSelector| match selector {
+            ImageSelector::Image(id) => self.images_by_id.get(id.as_str()).cloned(),
+            ImageSelector::Screen => Some(self.screen.clone()),
+        };
+        let color_images = pass.color_images.iter().map(load_image).collect::<Option<Vec<_>>>()?;
+        let depth_image = match &pass.depth_image {
+            Some(selector) => Some(load_image(selector)?),
+            None => None,
+        };
+        let result = engine::Pass::new(
+            &pass.id,
+            color_images,
+            depth_image,
+            pass.clear_color,
+            pass.layer,
+            pass.stencil_load,
+        );
+        match result {
+            Ok(engine_pass) => Some(engine_pass),
+            Err(err) => {
+                self.analysis.error(
+                    &draw.id,
+                    format!("{:#}", err.context(format!("Draw '{}'", draw.id))),
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks the material passes of the items against the images of the pass.
+    fn analyze_materials(
+        &mut self,
+        draw: &Draw,
+        pass: &Pass,
+        framebuffer_info: &engine::FramebufferInfo,
+    ) {
+        for item in &draw.items {
+            let material = item.material();
+            let mut results = vec![framebuffer_info
+                .ensure_color_target_count(&pass.id, material.color_target_count(&pass.id))];
+            if material.uses_stencil(&pass.id) {
+                results.push(framebuffer_info.ensure_stencil(&pass.id));
+            }
+            for result in results {
+                self.analysis.check(
+                    &draw.id,
+                    result.with_context(|| format!("Draw '{}'", draw.id)),
+                );
+            }
+        }
+    }
+
+    fn analyze_compute(&mut self, compute: &Compute) {
+        self.used_buffer_ids.extend(compute.buffer_ids());
+        let reads = compute.sampled_image_ids(self.sampled_textures);
+        for id in &reads {
+            self.check_read(&compute.id, id);
+        }
+        // Images bound but not sampled are storage textures the shader writes to
+        for id in compute.bound_image_ids() {
+            if !reads.contains(&id) {
+                self.written_ids.insert(id.clone());
+            }
+            self.used_ids.insert(id);
+        }
+    }
+
+    fn analyze_generate_mip_levels(&mut self, generate_mip_levels: &GenerateMipLevels) {
+        let id = &generate_mip_levels.image_id;
+        self.check_read(&generate_mip_levels.id, id);
+        self.used_ids.insert(id.clone());
+    }
+
+    fn analyze_sort(&mut self, sort: &Sort) {
+        // The sort reads the keys and reorders the items in place
+        self.used_buffer_ids.extend(sort.buffer_ids().into_iter().map(str::to_string));
+    }
+
+    fn analyze_prefilter(&mut self, prefilter: &PrefilterEnvironment) {
+        for (id, output) in prefilter.outputs() {
+            if let Some(image) = self.images_by_id.get(id.as_str()) {
+                let result = output.ensure_image(image);
+                self.analysis.check(&prefilter.id, result);
+            }
+        }
+        let targets = prefilter.output_image_ids();
+        if let Some(id) = prefilter.source_image_id() {
+            if let Some(image) = self.images_by_id.get(id) {
+                let result = engine::PrefilterEnvironment::ensure_source(image);
+                self.analysis.check(&prefilter.id, result);
+            }
+            if targets.contains(&id) {
+                self.analysis.error(
+                    &prefilter.id,
+                    format!(
+                        "Image '{id}' is both the source and an output of step '{}'",
+                        prefilter.id
+                    ),
+                );
+            } else {
+                self.check_read(&prefilter.id, id);
+            }
+            self.used_ids.insert(id.to_string());
+        }
+        self.written_ids.extend(targets.iter().map(|id| id.to_string()));
+        self.used_ids.extend(targets.iter().map(|id| id.to_string()));
+    }
+
+    fn find_unused(&mut self) {
+        let mut reported = HashSet::new();
+        for image in &self.chart.images {
+            if !self.used_ids.contains(&image.id) && reported.insert(&image.id) {
+                self.analysis.warning(&image.id, format!("Image '{}' is never used", image.id));
+            }
+        }
+        let mut reported = HashSet::new();
+        for buffer in &self.chart.buffers {
+            if !self.used_buffer_ids.contains(buffer.id()) && reported.insert(buffer.id()) {
+                self.analysis.warning(
+                    buffer.id(),
+                    format!("Buffer '{}' is never used", buffer.id()),
+                );
+            }
+        }
+    }
+}
@@ -1,17 +1,26 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::sync::Arc;
 
 use ahash::AHashMap;
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use futures::future::join_all;
+use itertools::Itertools;
 use serde::Deserialize;
-use tracing::{instrument, trace};
+use tracing::{instrument, trace, warn};
 
 use crate::engine::{
     ControlId, ControlIdPartType, ControlSetBuilder, GpuContext, ImageSizeRule, ShaderKind,
 };
-use crate::file::shader_context::{BufferSource, ShaderContext, Texture};
+use crate::file::buffer::Buffer;
+use crate::file::chart_analysis::SampledTextures;
+use crate::file::chart_template::Include;
+use crate::file::shader_context::{
+    bound_image_ids, check_shader, check_shader_resources, checked_sampled_textures,
+    loaded_sampled_textures, sampled_image_ids, BufferSource, ImageSource, ShaderContext, Texture,
+};
+use crate::loader::project_checker::ChartCheckContext;
 use crate::loader::resource_path::ResourcePath;
 use crate::loader::resource_repository::ResourceRepository;
 use crate::{engine, file};
@@ -24,17 +33,75 @@ pub struct ChartContext {
     pub control_set_builder: ControlSetBuilder,
     pub chart_control_id: ControlId,
     pub values_control_id: ControlId,
-    pub buffers_by_id: HashMap<String, Rc<engine::DoubleBuffer>>,
+    pub buffers_by_id: HashMap<String, Rc<engine::StorageBuffer>>,
     pub path: ResourcePath,
+
+    /// The files the chart is built from. The chart is rebuilt when any of them changes.
+    pub dependencies: RefCell<HashSet<ResourcePath>>,
+}
+
+impl ChartContext {
+    /// Resolves a file name relative to the chart file and records it as a dependency.
+    pub fn dependency_path(&self, file_name: &str) -> Result<ResourcePath> {
+        let path = self.path.relative_path(file_name)?;
+        self.add_dependency(&path);
+        Ok(path)
+    }
+
+    pub fn add_dependency(&self, path: &ResourcePath) {
+        // File change events report canonical paths
+        let path = path
+            .absolute_path()
+            .and_then(|absolute_path| ResourcePath::from_pathbuf(&path.root_path, &absolute_path))
+            .unwrap_or_else(|_| path.clone());
+        self.dependencies.borrow_mut().insert(path);
+    }
+
+    /// Creates the run condition of a run-once step that uses the given images and buffers.
+    pub fn make_run_once(
+        &self,
+        image_ids: impl IntoIterator<Item = String>,
+        buffer_ids: impl IntoIterator<Item = String>,
+    ) -> engine::RunOnce {
+        let images = image_ids
+            .into_iter()
+            .unique()
+            .filter_map(|id| self.images_by_id.get(&id).cloned())
+            .collect();
+        let buffers = buffer_ids
+            .into_iter()
+            .unique()
+            .filter_map(|id| self.buffers_by_id.get(&id).cloned())
+            .collect();
+        engine::RunOnce::new(images, buffers)
+    }
+
+    pub fn get_image(&self, id: &str) -> Result<&Arc<engine::BitangImage>> {
+        self.images_by_id.get(id).ok_or_else(|| image_not_found(id))
+    }
+
+    pub fn get_buffer(&self, id: &str) -> Result<&Rc<engine::StorageBuffer>> {
+        self.buffers_by_id.get(id).ok_or_else(|| buffer_not_found(id))
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// The error of a reference to an undefined chart image, the same in the loader and the check.
+pub fn image_not_found(id: &str) -> anyhow::Error {
+    anyhow!("Image not found: {id}")
+}
+
+/// The error of a reference to an undefined chart buffer, the same in the loader and the check.
+pub fn buffer_not_found(id: &str) -> anyhow::Error {
+    anyhow!("Buffer not found: {id}")
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct Chart {
     #[serde(default)]
     pub images: Vec<Image>,
 
     #[serde(default)]
-    pub buffers: Vec<DoubleBuffer>,
+    pub buffers: Vec<Buffer>,
 
     /// Simulation should run this long before starting the demo
     #[serde(default)]
@@ -45,19 +112,55 @@ pub struct Chart {
 
 impl Chart {
     #[instrument(skip(self, context, resource_repository, chart_file_path))]
+    /// Loads the chart. If `base_id` is set, the chart is an instance of that chart.
     pub async fn load(
         &self,
         id: &str,
+        base_id: Option<&str>,
+        context: &Arc<GpuContext>,
+        resource_repository: &Rc<ResourceRepository>,
+        chart_file_path: &ResourcePath,
+    ) -> Result<Rc<engine::Chart>> {
+        let mut template_paths = vec![];
+        let mut template_sources = vec![];
+        for include in self.includes() {
+            let path = include.path(chart_file_path)?;
+            template_sources.push(resource_repository.read_text_file(&path).await?);
+            template_paths.push(path);
+        }
+        let chart = self.resolve_includes(&template_sources)?;
+        chart
+            .load_resolved(
+                id,
+                base_id,
+                context,
+                resource_repository,
+                chart_file_path,
+                &template_paths,
+            )
+            .await
+    }
+
+    async fn load_resolved(
+        &self,
+        id: &str,
+        base_id: Option<&str>,
         context: &Arc<GpuContext>,
         resource_repository: &Rc<ResourceRepository>,
         chart_file_path: &ResourcePath,
+        template_paths: &[ResourcePath],
     ) -> Result<Rc<engine::Chart>> {
         trace!("Loading chart {}", id);
         let chart_control_id = ControlId::default().add(ControlIdPartType::Chart, id);
-        let control_set_builder = ControlSetBuilder::new(
-            chart_control_id.clone(),
-            resource_repository.control_repository.clone(),
-        );
+        let control_repository = resource_repository.control_repository.clone();
+        let control_set_builder = match base_id {
+            Some(base_id) => ControlSetBuilder::new_instance(
+                chart_control_id.clone(),
+                ControlId::default().add(ControlIdPartType::Chart, base_id),
+                control_repository,
+            ),
+            None => ControlSetBuilder::new(chart_control_id.clone(), control_repository),
+        };
 
         let images_by_id = self
             .images
@@ -65,25 +168,30 @@ impl Chart {
             .map(|image_desc| Ok((image_desc.id.clone(), image_desc.load())))
             .collect::<Result<AHashMap<_, _>>>()?;
 
-        let buffers_by_id = self
-            .buffers
-            .iter()
-            .map(|buffer_desc| {
-                let buffer = buffer_desc.load(context);
-                (buffer_desc.id.clone(), Rc::new(buffer))
-            })
-            .collect::<HashMap<_, _>>();
-
-        let chart_context = ChartContext {
+        let mut chart_context = ChartContext {
             gpu_context: context.clone(),
             resource_repository: resource_repository.clone(),
             images_by_id,
             control_set_builder,
             values_control_id: chart_control_id.add(ControlIdPartType::ChartValues, "Chart Values"),
             chart_control_id,
-            buffers_by_id,
+            buffers_by_id: HashMap::new(),
             path: chart_file_path.clone(),
+            dependencies: RefCell::new(HashSet::new()),
         };
+        chart_context.add_dependency(chart_file_path);
+        for path in template_paths {
+            chart_context.add_dependency(path);
+        }
+
+        let buffer_futures = self.buffers.iter().map(|buffer| buffer.load(&chart_context));
+        let buffers = join_all(buffer_futures)
+            .await
+            .into_iter()
+            .map(|buffer| buffer.map(Rc::new))
+            .collect::<Result<Vec<_>>>()?;
+        chart_context.buffers_by_id =
+            buffers.iter().map(|buffer| (buffer.id.clone(), buffer.clone())).collect();
 
         let chart_step_futures =
             self.steps.iter().map(|pass| async { pass.load(context, &chart_context).await });
@@ -91,21 +199,56 @@ impl Chart {
         let chart_steps =
             join_all(chart_step_futures).await.into_iter().collect::<Result<Vec<_>>>()?;
 
+        let analysis = self.analyze(&|source_path, kind, textures| {
+            loaded_sampled_textures(&chart_context, source_path, kind, textures)
+        });
+        for warning in &analysis.warnings {
+            warn!("{chart_file_path:?}: {}", warning.message);
+        }
+        ensure!(
+            analysis.errors.is_empty(),
+            "{}",
+            analysis.errors.iter().map(|error| &error.message).join("\n")
+        );
+
+        resource_repository.set_chart_dependencies(id, chart_context.dependencies.take());
         let images = chart_context.images_by_id.values().cloned().collect::<Vec<_>>();
 
         let chart = engine::Chart::new(
             id,
+            base_id,
             &chart_context.chart_control_id,
             chart_context.control_set_builder,
             images,
+            buffers,
             chart_steps,
             self.simulation_precalculation_time,
         );
         Ok(Rc::new(chart))
     }
+
+    /// Validates the chart without a GPU.
+    pub fn check(&self, check_context: &ChartCheckContext) {
+        for buffer in &self.buffers {
+            buffer.check(check_context);
+        }
+        for step in &self.steps {
+            step.check(check_context);
+        }
+
+        let analysis = self.analyze(&|source_path, kind, textures| {
+            checked_sampled_textures(check_context, source_path, kind, textures)
+        });
+        for warning in analysis.warnings {
+            check_context.report_warning(&warning.anchor, anyhow!(warning.message));
+        }
+        for error in analysis.errors {
+            check_context.report(&error.anchor, anyhow!(error.message));
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Image {
     pub id: String,
     pub size: ImageSizeRule,
@@ -113,11 +256,31 @@ pub struct Image {
 
     #[serde(default)]
     pub has_mipmaps: bool,
+
+    /// MSAA sample count. Multisampled color images are resolved at the end of every pass,
+    /// multisampled depth images can't be sampled.
+    #[serde(default = "default_samples")]
+    pub samples: u32,
+
+    /// Cube and array images have multiple layers, passes render to one layer at a time
+    #[serde(default)]
+    pub dimension: engine::ImageDimension,
+}
+
+fn default_samples() -> u32 {
+    1
 }
 
 impl Image {
     pub fn load(&self) -> Arc<engine::BitangImage> {
-        engine::BitangImage::new_attachment(&self.id, self.format, self.size, self.has_mipmaps)
+        engine::BitangImage::new_attachment(
+            &self.id,
+            self.format,
+            self.size,
+            self.has_mipmaps,
+            self.samples,
+            self.dimension,
+        )
     }
 }
 
@@ -125,11 +288,16 @@ fn default_clear_color() -> Option<[f32; 4]> {
     Some([0.03, 0.03, 0.03, 1.0])
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub enum ChartStep {
     Draw(Draw),
     Compute(Compute),
     GenerateMipLevels(GenerateMipLevels),
+    Sort(Sort),
+    PrefilterEnvironment(PrefilterEnvironment),
+
+    /// Replaced by the steps of a template before the chart is loaded
+    Include(Include),
 }
 
 impl ChartStep {
@@ -151,12 +319,48 @@ impl ChartStep {
                 let generate_mip_levels = generate_mip_levels.load(chart_context).await?;
                 Ok(engine::ChartStep::GenerateMipLevels(generate_mip_levels))
             }
+            ChartStep::Sort(sort) => {
+                let sort = sort.load(chart_context)?;
+                Ok(engine::ChartStep::Sort(sort))
+            }
+            ChartStep::PrefilterEnvironment(prefilter) => {
+                let prefilter = prefilter.load(chart_context).await?;
+                Ok(engine::ChartStep::PrefilterEnvironment(prefilter))
+            }
+            ChartStep::Include(include) => {
+                bail!("Template '{}' was not resolved", include.template)
+            }
+        }
+    }
+
+    pub fn id(&self) -> &String {
+        match self {
+            ChartStep::Draw(draw) => &draw.id,
+            ChartStep::Compute(compute) => &compute.id,
+            ChartStep::GenerateMipLevels(generate_mip_levels) => &generate_mip_levels.id,
+            ChartStep::Sort(sort) => &sort.id,
+            ChartStep::PrefilterEnvironment(prefilter) => &prefilter.id,
+            ChartStep::Include(include) => &include.template,
+        }
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        match self {
+            ChartStep::Draw(draw) => draw.check(check_context),
+            ChartStep::Compute(compute) => compute.check(check_context),
+            ChartStep::GenerateMipLevels(generate_mip_levels) => {
+                generate_mip_levels.check(check_context)
+            }
+            ChartStep::Sort(sort) => sort.check(check_context),
+            ChartStep::PrefilterEnvironment(prefilter) => prefilter.check(check_context),
+            // Includes are resolved before the check
+            ChartStep::Include(_) => {}
         }
     }
 }
 
 /// Represents a mipmap generation step in the chart sequence.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct GenerateMipLevels {
     pub id: String,
     pub image_id: String,
@@ -164,13 +368,7 @@ pub struct GenerateMipLevels {
 
 impl GenerateMipLevels {
     pub async fn load(&self, chart_context: &ChartContext) -> Result<engine::GenerateMipLevels> {
-        let image = chart_context.images_by_id.get(&self.image_id).with_context(|| {
-            anyhow!(
-                "Image id not found: '{}' (mipmap generation step: '{}')",
-                self.image_id,
-                self.id
-            )
-        })?;
+        let image = chart_context.get_image(&self.image_id)?;
 
         Ok(engine::GenerateMipLevels::new(
             &chart_context.gpu_context,
@@ -178,12 +376,189 @@ impl GenerateMipLevels {
             image,
         ))
     }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        check_context.get_image(&self.image_id);
+    }
+}
+
+/// Sorts the items of a buffer on the GPU, e.g. particles back to front for blending.
+///
+/// Both buffers of a double buffer are reordered the same way.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sort {
+    pub id: String,
+    pub buffer: String,
+    pub key: SortKey,
+
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum SortKey {
+    /// A float of the sorted items, counted from the start of the item
+    Field(usize),
+
+    /// The first float of the items of another buffer, e.g. view depth written by a compute step
+    Buffer(String),
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl Sort {
+    fn load(&self, chart_context: &ChartContext) -> Result<engine::Sort> {
+        let key = match &self.key {
+            SortKey::Field(offset) => engine::SortKey::Field(*offset),
+            SortKey::Buffer(buffer_id) => {
+                engine::SortKey::Buffer(chart_context.get_buffer(buffer_id)?.clone())
+            }
+        };
+        let order = match self.order {
+            SortOrder::Ascending => engine::SortOrder::Ascending,
+            SortOrder::Descending => engine::SortOrder::Descending,
+        };
+        engine::Sort::new(
+            &chart_context.gpu_context,
+            &self.id,
+            chart_context.get_buffer(&self.buffer)?.clone(),
+            key,
+            order,
+        )
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        let buffer = check_context.get_buffer(&self.buffer);
+        match &self.key {
+            SortKey::Field(offset) => {
+                if let Some(buffer) = buffer {
+                    let result = engine::ensure_sort_key_field(
+                        *offset,
+                        &self.buffer,
+                        buffer.item_size_in_vec4(),
+                    );
+                    if let Err(err) = result {
+                        check_context.report(&self.id, err);
+                    }
+                }
+            }
+            SortKey::Buffer(buffer_id) => {
+                check_context.get_buffer(buffer_id);
+            }
+        }
+    }
+
+    /// Returns the ids of the sorted buffer and the key buffer.
+    pub fn buffer_ids(&self) -> Vec<&str> {
+        match &self.key {
+            SortKey::Field(_) => vec![&self.buffer],
+            SortKey::Buffer(buffer_id) => vec![&self.buffer, buffer_id],
+        }
+    }
+}
+
+/// Precalculates image-based lighting maps from an equirectangular environment map when
+/// the chart is loaded. The outputs are chart images that can be bound to materials.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PrefilterEnvironment {
+    pub id: String,
+    pub source: ImageSource,
+
+    /// Image for the prefiltered specular reflections, needs mip levels. Level n is
+    /// prefiltered for roughness n / (levels - 1).
+    #[serde(default)]
+    pub specular: Option<String>,
+
+    /// Image for the diffuse irradiance
+    #[serde(default)]
+    pub irradiance: Option<String>,
+
+    /// Image for the split-sum BRDF lookup table, sampled at (n_dot_v, roughness)
+    #[serde(default)]
+    pub brdf_lut: Option<String>,
+}
+
+impl PrefilterEnvironment {
+    /// Returns the output images of the step.
+    pub fn outputs(&self) -> impl Iterator<Item = (&String, engine::PrefilterOutput)> {
+        [
+            (&self.specular, engine::PrefilterOutput::Specular),
+            (&self.irradiance, engine::PrefilterOutput::Irradiance),
+            (&self.brdf_lut, engine::PrefilterOutput::BrdfLut),
+        ]
+        .into_iter()
+        .filter_map(|(id, output)| id.as_ref().map(|id| (id, output)))
+    }
+
+    /// Returns the ids of the chart images the step writes to.
+    pub fn output_image_ids(&self) -> Vec<&str> {
+        self.outputs().map(|(id, _)| id.as_str()).collect()
+    }
+
+    /// Returns the id of the source image if it's a chart image.
+    pub fn source_image_id(&self) -> Option<&str> {
+        match &self.source {
+            ImageSource::Image(id) => Some(id),
+            ImageSource::File(_) => None,
+        }
+    }
+
+    async fn load(&self, chart_context: &ChartContext) -> Result<engine::PrefilterEnvironment> {
+        let source = match &self.source {
+            ImageSource::File(path) => {
+                chart_context
+                    .resource_repository
+                    .get_texture(
+                        &chart_context.gpu_context,
+                        &chart_context.dependency_path(path)?,
+                    )
+                    .get()
+                    .await?
+            }
+            ImageSource::Image(id) => chart_context.get_image(id)?.clone(),
+        };
+        let outputs = self
+            .outputs()
+            .map(|(id, output)| Ok((chart_context.get_image(id)?.clone(), output)))
+            .collect::<Result<Vec<_>>>()?;
+        let image_ids =
+            self.output_image_ids().into_iter().chain(self.source_image_id()).map(str::to_string);
+        engine::PrefilterEnvironment::new(
+            &chart_context.gpu_context,
+            &self.id,
+            source,
+            outputs,
+            chart_context.make_run_once(image_ids, []),
+        )
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        if let ImageSource::File(path) = &self.source {
+            check_context.check_file(path);
+        }
+        for id in self.output_image_ids().into_iter().chain(self.source_image_id()) {
+            check_context.get_image(id);
+        }
+        if self.outputs().next().is_none() {
+            check_context.report_warning(
+                &self.id,
+                anyhow!("Environment prefilter step '{}' has no outputs", self.id),
+            );
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub enum DrawItem {
     Object(file::object::Object),
     Scene(file::scene::Scene),
+    Procedural(file::object::Procedural),
+    FullscreenTriangle(file::object::FullscreenTriangle),
 }
 
 impl DrawItem {
@@ -202,16 +577,73 @@ impl DrawItem {
                 let scene = scene.load(draw_control_id, chart_context, passes).await?;
                 Ok(engine::DrawItem::Scene(scene))
             }
+            DrawItem::Procedural(procedural) => {
+                let object = procedural.load(chart_context, draw_control_id, passes).await?;
+                Ok(engine::DrawItem::Procedural(object))
+            }
+            DrawItem::FullscreenTriangle(triangle) => {
+                let object =
+                    triangle.as_procedural().load(chart_context, draw_control_id, passes).await?;
+                Ok(engine::DrawItem::Procedural(object))
+            }
+        }
+    }
+
+    /// Returns the ids of the chart buffers the item reads, including instance and
+    /// indirect argument buffers.
+    pub fn bound_buffer_ids(&self) -> Vec<String> {
+        let mut ids = self.material().bound_buffer_ids();
+        match self {
+            DrawItem::Object(object) => {
+                ids.extend(object.instance_buffer.iter().map(|buffer| buffer.buffer.id().clone()));
+                ids.extend(object.indirect_args.clone());
+            }
+            DrawItem::Procedural(procedural) => ids.extend(procedural.indirect_args.clone()),
+            DrawItem::Scene(_) | DrawItem::FullscreenTriangle(_) => {}
+        }
+        ids
+    }
+
+    pub fn material(&self) -> &file::material::Material {
+        match self {
+            DrawItem::Object(object) => &object.material,
+            DrawItem::Scene(scene) => &scene.material,
+            DrawItem::Procedural(procedural) => &procedural.material,
+            DrawItem::FullscreenTriangle(triangle) => &triangle.material,
+        }
+    }
+
+    fn check(&self, check_context: &ChartCheckContext, passes: &[Pass]) {
+        match self {
+            DrawItem::Object(object) => object.check(check_context, passes),
+            DrawItem::Scene(scene) => scene.check(check_context, passes),
+            DrawItem::Procedural(procedural) => procedural.check(check_context, passes),
+            DrawItem::FullscreenTriangle(triangle) => {
+                triangle.material.check(check_context, passes)
+            }
         }
     }
 }
 
+#[derive(Debug, Deserialize, Clone, Default)]
+pub enum DrawRun {
+    #[default]
+    Frame,
+
+    /// Renders when the chart is loaded or its images or buffers are recreated, e.g. to
+    /// precompute a lookup texture. Control changes don't rerender it.
+    Once,
+}
+
 /// Represents a draw step in the chart sequence.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Draw {
     pub id: String,
     pub passes: Vec<Pass>,
     pub items: Vec<DrawItem>,
+
+    #[serde(default)]
+    pub run: DrawRun,
 }
 
 impl Draw {
@@ -234,23 +666,101 @@ impl Draw {
         let light_dir = chart_context.control_set_builder.get_vec3(&light_dir_id);
         let shadow_map_size = chart_context.control_set_builder.get_vec3(&shadow_map_size_id);
 
-        let draw = engine::Draw::new(&self.id, passes, objects, light_dir, shadow_map_size)?;
+        let run_once = match self.run {
+            DrawRun::Frame => None,
+            DrawRun::Once => {
+                let image_ids = self
+                    .passes
+                    .iter()
+                    .flat_map(|pass| pass.render_target_ids())
+                    .map(str::to_string)
+                    .chain(self.items.iter().flat_map(|item| item.material().bound_image_ids()));
+                let buffer_ids =
+                    self.items.iter().flat_map(|item| item.material().bound_buffer_ids());
+                Some(chart_context.make_run_once(image_ids, buffer_ids))
+            }
+        };
+
+        let draw = engine::Draw::new(
+            &self.id,
+            passes,
+            objects,
+            light_dir,
+            shadow_map_size,
+            run_once,
+        )?;
         Ok(draw)
     }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        for pass in &self.passes {
+            pass.check(check_context);
+            if matches!(self.run, DrawRun::Once)
+                && pass.color_images.iter().any(|image| matches!(image, ImageSelector::Screen))
+            {
+                check_context.report(
+                    &self.id,
+                    anyhow!(
+                        "Draw '{}' runs once and can't render to the screen",
+                        self.id
+                    ),
+                );
+            }
+        }
+        for item in &self.items {
+            item.check(check_context, &self.passes);
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub enum ComputeRun {
     Init(String),
     Simulation(String),
+
+    /// Runs every frame in the order of the chart steps, e.g. to process images
+    Frame,
+
+    /// Runs in the order of the chart steps when the chart is loaded or its images or buffers
+    /// are recreated, e.g. to precompute lookup data. Control changes don't rerun it.
+    Once,
+}
+
+impl ComputeRun {
+    fn buffer_id(&self) -> Option<&String> {
+        match self {
+            ComputeRun::Init(buffer_id) | ComputeRun::Simulation(buffer_id) => Some(buffer_id),
+            ComputeRun::Frame | ComputeRun::Once => None,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// The number of compute shader invocations.
+#[derive(Debug, Deserialize, Clone)]
+pub enum DispatchSize {
+    /// One invocation per pixel of a chart image
+    Image(String),
+
+    /// One invocation per item of a buffer, follows the buffer when it's resized
+    Buffer(String),
+
+    /// Invocation counts in x, y and z
+    Size(u32, u32, u32),
+
+    /// Workgroup counts read from an `IndirectArgs` buffer with `Dispatch` arguments
+    Indirect(String),
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct Compute {
-    id: String,
+    pub id: String,
     shader: String,
     run: ComputeRun,
 
+    /// Defaults to one invocation per item of the buffer of the init or simulation run
+    #[serde(default)]
+    dispatch: Option<DispatchSize>,
+
     #[serde(default)]
     textures: HashMap<String, Texture>,
 
@@ -267,21 +777,41 @@ impl Compute {
         context: &Arc<GpuContext>,
         chart_context: &ChartContext,
     ) -> Result<engine::Compute> {
-        let run = match &self.run {
-            ComputeRun::Init(buffer_id) => {
-                let buffer = chart_context
-                    .buffers_by_id
-                    .get(buffer_id)
-                    .with_context(|| anyhow!("Buffer not found: {buffer_id}"))?;
-                engine::Run::Init(buffer.clone())
+        let buffer = self
+            .run
+            .buffer_id()
+            .map(|buffer_id| chart_context.get_buffer(buffer_id))
+            .transpose()?;
+        let run = match (&self.run, buffer) {
+            (ComputeRun::Init(_), Some(_)) => engine::Run::Init,
+            (ComputeRun::Simulation(_), Some(buffer)) => engine::Run::Simulate(buffer.clone()),
+            (ComputeRun::Once, _) => {
+                let image_ids = self.bound_image_ids().into_iter().chain(self.dispatch_image_id());
+                let buffer_ids = self.buffers.values().map(|buffer| buffer.id().clone());
+                engine::Run::Once(chart_context.make_run_once(image_ids, buffer_ids))
             }
-            ComputeRun::Simulation(buffer_id) => {
-                let buffer = chart_context
-                    .buffers_by_id
-                    .get(buffer_id)
-                    .with_context(|| anyhow!("Buffer not found: {buffer_id}"))?;
-                engine::Run::Simulate(buffer.clone())
+            _ => engine::Run::Frame,
+        };
+
+        let dispatch_size = match (&self.dispatch, buffer) {
+            (Some(DispatchSize::Image(image_id)), _) => {
+                engine::DispatchSize::Image(chart_context.get_image(image_id)?.clone())
             }
+            (Some(DispatchSize::Buffer(buffer_id)), _) => {
+                engine::DispatchSize::Buffer(chart_context.get_buffer(buffer_id)?.clone())
+            }
+            (Some(DispatchSize::Indirect(buffer_id)), _) => {
+                let buffer = chart_context.get_buffer(buffer_id)?;
+                engine::ensure_indirect_args(
+                    buffer_id,
+                    buffer.indirect_args,
+                    engine::IndirectArgsKind::Dispatch,
+                )?;
+                engine::DispatchSize::Indirect(buffer.clone())
+            }
+            (Some(DispatchSize::Size(x, y, z)), _) => engine::DispatchSize::Fixed([*x, *y, *z]),
+            (None, Some(buffer)) => engine::DispatchSize::Buffer(buffer.clone()),
+            (None, None) => bail!("Compute '{}' needs a dispatch size", self.id),
         };
 
         let control_id = chart_context.chart_control_id.add(ControlIdPartType::Compute, &self.id);
@@ -297,12 +827,93 @@ impl Compute {
         let shader =
             shader_context.make_shader(chart_context, ShaderKind::Compute, &self.shader).await?;
 
-        engine::Compute::new(context, &self.id, shader, run)
+        engine::Compute::new(context, &self.id, shader, run, dispatch_size)
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        if let Some(buffer_id) = self.run.buffer_id() {
+            check_context.get_buffer(buffer_id);
+        }
+        match &self.dispatch {
+            Some(DispatchSize::Image(image_id)) => {
+                check_context.get_image(image_id);
+            }
+            Some(DispatchSize::Buffer(buffer_id)) => {
+                check_context.get_buffer(buffer_id);
+            }
+            Some(DispatchSize::Indirect(buffer_id)) => {
+                if let Some(buffer) = check_context.get_buffer(buffer_id) {
+                    let result = engine::ensure_indirect_args(
+                        buffer_id,
+                        buffer.indirect_args(),
+                        engine::IndirectArgsKind::Dispatch,
+                    );
+                    if let Err(err) = result {
+                        check_context.report(buffer_id, err);
+                    }
+                }
+            }
+            Some(DispatchSize::Size(..)) => {}
+            None => {
+                if self.run.buffer_id().is_none() {
+                    check_context.report(
+                        &self.id,
+                        anyhow!("Compute '{}' needs a dispatch size", self.id),
+                    );
+                }
+            }
+        }
+        check_shader_resources(check_context, &self.textures, &self.buffers);
+        check_shader(
+            check_context,
+            ShaderKind::Compute,
+            &self.shader,
+            &self.textures,
+            &self.buffers,
+        );
+    }
+
+    /// Returns the ids of all chart images bound to the shader.
+    pub fn bound_image_ids(&self) -> Vec<String> {
+        bound_image_ids(&self.textures)
+    }
+
+    /// Returns the ids of all chart buffers the step uses.
+    pub fn buffer_ids(&self) -> Vec<String> {
+        let dispatch_buffer_id = match &self.dispatch {
+            Some(DispatchSize::Buffer(buffer_id) | DispatchSize::Indirect(buffer_id)) => {
+                Some(buffer_id)
+            }
+            _ => None,
+        };
+        self.buffers
+            .values()
+            .map(BufferSource::id)
+            .chain(self.run.buffer_id())
+            .chain(dispatch_buffer_id)
+            .cloned()
+            .collect()
+    }
+
+    fn dispatch_image_id(&self) -> Option<String> {
+        match &self.dispatch {
+            Some(DispatchSize::Image(image_id)) => Some(image_id.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn sampled_image_ids(&self, sampled_textures: &SampledTextures) -> Vec<String> {
+        sampled_image_ids(
+            &self.shader,
+            ShaderKind::Compute,
+            &self.textures,
+            sampled_textures,
+        )
     }
 }
 
 // TODO: get rid of this, use a plain string id instead
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub enum ImageSelector {
     /// Level 0 of the image
     Image(String),
@@ -314,22 +925,29 @@ pub enum ImageSelector {
 impl ImageSelector {
     pub fn load(&self, chart_context: &ChartContext) -> Result<Arc<engine::BitangImage>> {
         match self {
-            ImageSelector::Image(id) => {
-                let image = chart_context
-                    .images_by_id
-                    .get(id)
-                    .with_context(|| anyhow!("Render target not found: {id}"))?;
-                Ok(Arc::clone(image))
-            }
+            ImageSelector::Image(id) => Ok(Arc::clone(chart_context.get_image(id)?)),
             ImageSelector::Screen => {
                 let swapchain_image = chart_context.gpu_context.final_render_target.clone();
                 Ok(swapchain_image)
             }
         }
     }
+
+    pub fn image_id(&self) -> Option<&str> {
+        match self {
+            ImageSelector::Image(id) => Some(id),
+            ImageSelector::Screen => None,
+        }
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        if let ImageSelector::Image(id) = self {
+            check_context.get_image(id);
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Pass {
     pub id: String,
     pub depth_image: Option<ImageSelector>,
@@ -337,6 +955,14 @@ pub struct Pass {
 
     #[serde(default = "default_clear_color")]
     pub clear_color: Option<[f32; 4]>,
+
+    /// The layer of cube and array images to render to
+    #[serde(default)]
+    pub layer: engine::RenderLayer,
+
+    /// How the stencil of the depth image is initialized
+    #[serde(default)]
+    pub stencil_load: engine::StencilLoad,
 }
 
 impl Pass {
@@ -352,19 +978,28 @@ impl Pass {
             .map(|color_buffer| color_buffer.load(chart_context))
             .collect::<Result<Vec<_>>>()?;
 
-        engine::Pass::new(&self.id, color_buffers, depth_buffer, self.clear_color)
+        engine::Pass::new(
+            &self.id,
+            color_buffers,
+            depth_buffer,
+            self.clear_color,
+            self.layer,
+            self.stencil_load,
+        )
     }
-}
 
-#[derive(Debug, Deserialize)]
-pub struct DoubleBuffer {
-    id: String,
-    item_size_in_vec4: usize,
-    item_count: usize,
-}
+    fn check(&self, check_context: &ChartCheckContext) {
+        for selector in self.depth_image.iter().chain(&self.color_images) {
+            selector.check(check_context);
+        }
+    }
 
-impl DoubleBuffer {
-    pub fn load(&self, context: &Arc<GpuContext>) -> engine::DoubleBuffer {
-        engine::DoubleBuffer::new(context, self.item_size_in_vec4, self.item_count)
+    /// Returns the ids of the chart images this pass renders to.
+    pub fn render_target_ids(&self) -> Vec<&str> {
+        self.depth_image
+            .iter()
+            .chain(&self.color_images)
+            .filter_map(ImageSelector::image_id)
+            .collect()
     }
 }
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use crate::file::chart_file::{Chart, ChartStep, Image};
+use crate::loader::resource_path::ResourcePath;
+use crate::loader::resource_repository::ron_loader;
+
+/// Images and steps shared by several charts.
+///
+/// `${name}` anywhere in the template file is replaced by the value of the parameter `name`
+/// of the including step. File paths are relative to the including chart, so shared files
+/// should be referenced from the project root, e.g. "/shaders/fullscreen_vs.wgsl".
+#[derive(Debug, Deserialize)]
+pub struct ChartTemplate {
+    #[serde(default)]
+    pub images: Vec<Image>,
+
+    pub steps: Vec<ChartStep>,
+}
+
+/// A chart step that is replaced by the steps of a template.
+///
+/// The steps keep their ids, so their controls belong to the including chart.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Include {
+    /// Path of the template file, relative to the chart
+    pub template: String,
+
+    #[serde(default)]
+    pub parameters: HashMap<String, String>,
+}
+
+impl Include {
+    pub fn path(&self, chart_path: &ResourcePath) -> Result<ResourcePath> {
+        chart_path.relative_path(&self.template)
+    }
+
+    fn instantiate(&self, source: &str) -> Result<ChartTemplate> {
+        let source = self.substitute_parameters(source)?;
+        let template =
+            ron_loader().from_str::<ChartTemplate>(&source).map_err(|err| anyhow!("{err}"))?;
+        if template.steps.iter().any(|step| matches!(step, ChartStep::Include(_))) {
+            bail!("Templates can't include other templates");
+        }
+        Ok(template)
+    }
+
+    fn substitute_parameters(&self, source: &str) -> Result<String> {
+        for name in self.parameters.keys() {
+            if !source.contains(&format!("${{{name}}}")) {
+                bail!("Unknown template parameter '{name}'");
+            }
+        }
+        let mut result = String::with_capacity(source.len());
+        let mut rest = source;
+        while let Some(start) = rest.find("${") {
+            result.push_str(&rest[..start]);
+            let reference = &rest[start + 2..];
+            let end = reference.find('}').context("Unclosed parameter reference")?;
+            let name = &reference[..end];
+            let value = self
+                .parameters
+                .get(name)
+                .with_context(|| format!("Missing template parameter '{name}'"))?;
+            result.push_str(value);
+            rest = &reference[end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+impl Chart {
+    /// Returns the include steps of the chart in order.
+    pub fn includes(&self) -> impl Iterator<Item = &Include> {
+        self.steps.iter().filter_map(|step| match step {
+            ChartStep::Include(include) => Some(include),
+            _ => None,
+        })
+    }
+
+    /// Returns the chart with the include steps replaced by the images and steps of the
+    /// templates. `template_sources` are the template file contents in the order of `includes`.
+    pub fn resolve_includes(&self, template_sources: &[String]) -> Result<Chart> {
+        let mut images = self.images.clone();
+        let mut steps = vec![];
+        let mut template_sources = template_sources.iter();
+        for step in &self.steps {
+            let ChartStep::Include(include) = step else {
+                steps.push(step.clone());
+                continue;
+            };
+            let source = template_sources.next().context("Template source missing")?;
+            let template = include
+                .instantiate(source)
+                .with_context(|| format!("Failed to include template '{}'", include.template))?;
+            images.extend(template.images);
+            steps.extend(template.steps);
+        }
+        Ok(Chart {
+            images,
+            buffers: self.buffers.clone(),
+            simulation_precalculation_time: self.simulation_precalculation_time,
+            steps,
+        })
+    }
+}
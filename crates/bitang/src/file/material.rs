@@ -6,12 +6,21 @@ use futures::future::join_all;
 use serde::Deserialize;
 
 use crate::engine;
-use crate::engine::{BlendMode, ControlId, DrawCallProps, ShaderKind};
+use crate::engine::{
+    BlendMode, ColorTargetProps, ColorWriteMask, Compare, ControlId, ControlIdPartType, CullFace,
+    DepthBias, DrawCallProps, FrontFace, PolygonMode, ShaderKind, StencilOperation, Topology,
+    VertexLayout,
+};
+use crate::file::chart_analysis::SampledTextures;
 use crate::file::chart_file::ChartContext;
-use crate::file::default_true;
-use crate::file::shader_context::{BufferSource, ShaderContext, Texture};
+use crate::file::shader_context::{
+    bound_image_ids, check_shader, check_shader_resources, sampled_image_ids, BufferSource,
+    ShaderContext, Texture,
+};
+use crate::file::{chart_file, default_true};
+use crate::loader::project_checker::ChartCheckContext;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Material {
     passes: HashMap<String, MaterialPass>,
 
@@ -29,6 +38,7 @@ impl Material {
         passes: &[engine::Pass],
         control_map: &HashMap<String, String>,
         object_cid: &ControlId,
+        vertex_layout: VertexLayout,
     ) -> Result<Arc<engine::Material>> {
         let shader_context = ShaderContext::new(
             chart_context,
@@ -46,6 +56,7 @@ impl Material {
                         &shader_context,
                         chart_context,
                         &pass.framebuffer_info,
+                        vertex_layout.clone(),
                     )
                     .await?;
                 Ok(Some(pass))
@@ -61,9 +72,58 @@ impl Material {
             passes: material_passes,
         }))
     }
+
+    pub fn check(&self, check_context: &ChartCheckContext, passes: &[chart_file::Pass]) {
+        check_shader_resources(check_context, &self.textures, &self.buffers);
+        for pass in passes {
+            if let Some(material_pass) = self.passes.get(&pass.id) {
+                material_pass.check(check_context, &pass.id, &self.textures, &self.buffers);
+            }
+        }
+    }
+
+    /// Returns the ids of all chart images bound to the material.
+    pub fn bound_image_ids(&self) -> Vec<String> {
+        bound_image_ids(&self.textures)
+    }
+
+    /// Returns the ids of all chart buffers bound to the material.
+    pub fn bound_buffer_ids(&self) -> Vec<String> {
+        self.buffers.values().map(|buffer| buffer.id().clone()).collect()
+    }
+
+    /// Returns true if the material uses the stencil in a pass.
+    pub fn uses_stencil(&self, pass_id: &str) -> bool {
+        self.passes.get(pass_id).is_some_and(|material_pass| material_pass.stencil.is_some())
+    }
+
+    /// Returns the number of color targets the material declares for a pass.
+    pub fn color_target_count(&self, pass_id: &str) -> usize {
+        self.passes.get(pass_id).map_or(0, |material_pass| material_pass.color_targets.len())
+    }
+
+    /// Returns the ids of the chart images sampled in a pass.
+    pub fn sampled_image_ids(
+        &self,
+        pass_id: &str,
+        sampled_textures: &SampledTextures,
+    ) -> Vec<String> {
+        let Some(material_pass) = self.passes.get(pass_id) else {
+            return vec![];
+        };
+        [
+            (ShaderKind::Vertex, &material_pass.vertex_shader),
+            (ShaderKind::Fragment, &material_pass.fragment_shader),
+        ]
+        .into_iter()
+        .flat_map(|(kind, shader)| {
+            sampled_image_ids(shader, kind, &self.textures, sampled_textures)
+        })
+        .collect()
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 struct MaterialPass {
     vertex_shader: String,
     fragment_shader: String,
@@ -71,14 +131,107 @@ struct MaterialPass {
     #[serde(default = "default_true")]
     depth_test: bool,
 
+    /// Overrides `depth_test`, which is the same as `LessEqual`
+    #[serde(default)]
+    depth_compare: Option<Compare>,
+
     #[serde(default = "default_true")]
     depth_write: bool,
 
+    #[serde(default)]
+    depth_bias: DepthBias,
+
     #[serde(default)]
     blend_mode: BlendMode,
 
+    /// Blending and write masks of the color images in the order of the pass. Images
+    /// without an entry use `blend_mode` and write all channels.
+    #[serde(default)]
+    color_targets: Vec<ColorTarget>,
+
     #[serde(default)]
     cull: bool,
+
+    /// The face culled if `cull` is set
+    #[serde(default)]
+    cull_face: CullFace,
+
+    #[serde(default)]
+    front_face: FrontFace,
+
+    #[serde(default)]
+    topology: Topology,
+
+    /// Line and point modes fail to load on GPUs without support
+    #[serde(default)]
+    polygon_mode: PolygonMode,
+
+    /// Stencil test and write, requires a depth image with stencil
+    #[serde(default)]
+    stencil: Option<Stencil>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ColorTarget {
+    /// Defaults to the `blend_mode` of the material pass
+    #[serde(default)]
+    blend_mode: Option<BlendMode>,
+
+    #[serde(default)]
+    write_mask: ColorWriteMask,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct Stencil {
+    #[serde(default)]
+    compare: Compare,
+
+    #[serde(default)]
+    pass_op: StencilOperation,
+
+    #[serde(default)]
+    fail_op: StencilOperation,
+
+    #[serde(default)]
+    depth_fail_op: StencilOperation,
+
+    #[serde(default = "default_stencil_mask")]
+    read_mask: u32,
+
+    #[serde(default = "default_stencil_mask")]
+    write_mask: u32,
+
+    #[serde(default)]
+    reference: u32,
+
+    /// Name of a chart value that sets the reference, defaults to `reference`
+    #[serde(default)]
+    reference_control: Option<String>,
+}
+
+fn default_stencil_mask() -> u32 {
+    0xff
+}
+
+impl Stencil {
+    fn load(&self, chart_context: &ChartContext) -> engine::StencilProps {
+        let reference_control = self.reference_control.as_ref().map(|name| {
+            let control_id = chart_context.values_control_id.add(ControlIdPartType::Value, name);
+            chart_context
+                .control_set_builder
+                .get_float_with_default(&control_id, self.reference as f32)
+        });
+        engine::StencilProps {
+            compare: self.compare,
+            pass_op: self.pass_op,
+            fail_op: self.fail_op,
+            depth_fail_op: self.depth_fail_op,
+            read_mask: self.read_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
+            reference_control,
+        }
+    }
 }
 
 impl MaterialPass {
@@ -88,6 +241,7 @@ impl MaterialPass {
         shader_context: &ShaderContext,
         chart_context: &ChartContext,
         framebuffer_info: &engine::FramebufferInfo,
+        vertex_layout: VertexLayout,
     ) -> Result<engine::DrawCall> {
         let vertex_shader_future =
             shader_context.make_shader(chart_context, ShaderKind::Vertex, &self.vertex_shader);
@@ -101,14 +255,43 @@ impl MaterialPass {
                 .try_into()
                 .map_err(|_| anyhow!("shouldn't happen"))?;
 
+        framebuffer_info.ensure_color_target_count(id, self.color_targets.len())?;
+        let color_image_count = framebuffer_info.color_buffer_formats.len();
+        let color_targets = (0..color_image_count)
+            .map(|index| match self.color_targets.get(index) {
+                Some(color_target) => ColorTargetProps {
+                    blend_mode: color_target
+                        .blend_mode
+                        .clone()
+                        .unwrap_or_else(|| self.blend_mode.clone()),
+                    write_mask: color_target.write_mask,
+                },
+                None => ColorTargetProps {
+                    blend_mode: self.blend_mode.clone(),
+                    write_mask: ColorWriteMask::default(),
+                },
+            })
+            .collect();
+        let depth_compare = self.depth_compare.unwrap_or(if self.depth_test {
+            Compare::LessEqual
+        } else {
+            Compare::Always
+        });
+
         let draw_call_props = DrawCallProps {
             id: id.to_string(),
             vertex_shader: vertex_shader?,
             fragment_shader: fragment_shader?,
-            depth_test: self.depth_test,
+            depth_compare,
             depth_write: self.depth_write,
-            blend_mode: self.blend_mode.clone(),
-            cull: self.cull,
+            depth_bias: self.depth_bias,
+            color_targets,
+            cull_face: self.cull.then_some(self.cull_face),
+            front_face: self.front_face,
+            topology: self.topology,
+            polygon_mode: self.polygon_mode,
+            vertex_layout,
+            stencil: self.stencil.as_ref().map(|stencil| stencil.load(chart_context)),
         };
 
         engine::DrawCall::new(
@@ -117,4 +300,38 @@ impl MaterialPass {
             framebuffer_info,
         )
     }
+
+    fn check(
+        &self,
+        check_context: &ChartCheckContext,
+        id: &str,
+        textures: &HashMap<String, Texture>,
+        buffers: &HashMap<String, BufferSource>,
+    ) {
+        if let Err(err) = self.depth_bias.ensure_topology(id, self.topology) {
+            check_context.report(id, err);
+        }
+        let blend_modes = std::iter::once(&self.blend_mode).chain(
+            self.color_targets.iter().filter_map(|color_target| color_target.blend_mode.as_ref()),
+        );
+        for blend_mode in blend_modes {
+            if let Err(err) = blend_mode.wgpu_blend_state() {
+                check_context.report(id, err.context(format!("Draw call '{id}'")));
+            }
+        }
+        check_shader(
+            check_context,
+            ShaderKind::Vertex,
+            &self.vertex_shader,
+            textures,
+            buffers,
+        );
+        check_shader(
+            check_context,
+            ShaderKind::Fragment,
+            &self.fragment_shader,
+            textures,
+            buffers,
+        );
+    }
 }
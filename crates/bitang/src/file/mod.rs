@@ -1,4 +1,7 @@
+pub mod buffer;
+mod chart_analysis;
 pub mod chart_file;
+mod chart_template;
 mod material;
 mod object;
 pub mod project_file;
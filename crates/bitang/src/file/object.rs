@@ -1,24 +1,104 @@
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Result};
 use serde::Deserialize;
 
-use crate::engine::{ControlId, ControlIdPartType};
-use crate::file::chart_file::ChartContext;
+use crate::engine::{ControlId, ControlIdPartType, IndirectArgsKind, InstanceLayout, VertexLayout};
+use crate::file::chart_file::{self, ChartContext};
+use crate::file::shader_context::BufferSource;
+use crate::loader::project_checker::ChartCheckContext;
 use crate::{engine, file};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Object {
     pub id: String,
     pub mesh_file: String,
     pub mesh_name: String,
     pub material: file::material::Material,
 
+    /// Draws one instance per buffer item instead of the "instances" control
+    #[serde(default)]
+    pub instance_buffer: Option<InstanceBuffer>,
+
+    /// Id of an `IndirectArgs` buffer with `DrawIndexed` arguments, overrides the instance count.
+    /// The `instance_count` global keeps the value of the "instances" control, the count in the
+    /// buffer is only known on the GPU.
+    #[serde(default)]
+    pub indirect_args: Option<String>,
+
     #[serde(default)]
     pub control_map: HashMap<String, String>,
 }
 
+/// A chart buffer bound as per-instance vertex data
+#[derive(Debug, Deserialize, Clone)]
+pub struct InstanceBuffer {
+    pub buffer: BufferSource,
+
+    /// The types of the vec4s of a buffer item. They are bound to consecutive shader
+    /// locations after the mesh attributes, starting at `@location(5)`.
+    pub attributes: Vec<InstanceAttribute>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub enum InstanceAttribute {
+    F32,
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+impl InstanceAttribute {
+    fn vertex_format(self) -> wgpu::VertexFormat {
+        match self {
+            InstanceAttribute::F32 => wgpu::VertexFormat::Float32,
+            InstanceAttribute::Vec2 => wgpu::VertexFormat::Float32x2,
+            InstanceAttribute::Vec3 => wgpu::VertexFormat::Float32x3,
+            InstanceAttribute::Vec4 => wgpu::VertexFormat::Float32x4,
+        }
+    }
+}
+
+impl InstanceBuffer {
+    fn load(&self, chart_context: &ChartContext) -> Result<engine::InstanceBuffer> {
+        let id = self.buffer.id();
+        let buffer = chart_context.get_buffer(id)?;
+        self.ensure_attributes_fit(buffer.item_size_in_vec4)?;
+        Ok(engine::InstanceBuffer {
+            buffer: buffer.clone(),
+            slot: self.buffer.slot(),
+        })
+    }
+
+    fn vertex_layout(&self, instance_buffer: &engine::InstanceBuffer) -> VertexLayout {
+        VertexLayout::MeshWithInstances(InstanceLayout {
+            item_size_in_vec4: instance_buffer.buffer.item_size_in_vec4,
+            attributes: self.attributes.iter().map(|attribute| attribute.vertex_format()).collect(),
+        })
+    }
+
+    fn check(&self, check_context: &ChartCheckContext) {
+        let id = self.buffer.id();
+        if let Some(buffer) = check_context.get_buffer(id) {
+            if let Err(err) = self.ensure_attributes_fit(buffer.item_size_in_vec4()) {
+                check_context.report(id, err);
+            }
+        }
+    }
+
+    /// Fails if the buffer items have fewer vec4s than the declared attributes.
+    fn ensure_attributes_fit(&self, item_size_in_vec4: usize) -> Result<()> {
+        ensure!(
+            self.attributes.len() <= item_size_in_vec4,
+            "Buffer '{}' has {item_size_in_vec4} vec4s per item, but {} instance attributes are declared",
+            self.buffer.id(),
+            self.attributes.len()
+        );
+        Ok(())
+    }
+}
+
 impl Object {
     pub async fn load(
         &self,
@@ -29,13 +109,30 @@ impl Object {
         let object_cid = parent_id.add(ControlIdPartType::Object, &self.id);
         let mesh_future = chart_context.resource_repository.get_mesh(
             &chart_context.gpu_context,
-            &chart_context.path.relative_path(&self.mesh_file)?,
+            &chart_context.dependency_path(&self.mesh_file)?,
             &self.mesh_name,
         );
 
+        let (instance_buffer, vertex_layout) = match &self.instance_buffer {
+            Some(instance_buffer_desc) => {
+                let instance_buffer = instance_buffer_desc.load(chart_context)?;
+                let vertex_layout = instance_buffer_desc.vertex_layout(&instance_buffer);
+                (Some(instance_buffer), vertex_layout)
+            }
+            None => (None, VertexLayout::Mesh),
+        };
+
         // Load material
-        let material =
-            self.material.load(chart_context, passes, &self.control_map, &object_cid).await?;
+        let material = self
+            .material
+            .load(
+                chart_context,
+                passes,
+                &self.control_map,
+                &object_cid,
+                vertex_layout,
+            )
+            .await?;
 
         // Wait for resources to be loaded
         let mesh = mesh_future.get().await?;
@@ -46,12 +143,184 @@ impl Object {
 
         let object = crate::engine::RenderObject {
             _id: self.id.clone(),
-            mesh,
             material,
             position: chart_context.control_set_builder.get_vec3(&position_id),
             rotation: chart_context.control_set_builder.get_vec3(&rotation_id),
             instances: chart_context.control_set_builder.get_float_with_default(&instances_id, 1.),
+            indirect_buffer: load_indirect_args(
+                chart_context,
+                &self.indirect_args,
+                mesh_indirect_args(mesh.index_buffer.is_some()),
+            )?,
+            mesh,
+            instance_buffer,
         };
         Ok(Rc::new(object))
     }
+
+    pub fn check(&self, check_context: &ChartCheckContext, passes: &[chart_file::Pass]) {
+        let has_indices = check_context.get_mesh_indexing(&self.mesh_file).and_then(|meshes| {
+            let has_indices = meshes.get(&self.mesh_name).copied();
+            if has_indices.is_none() {
+                check_context.report(
+                    &self.mesh_name,
+                    anyhow!(
+                        "Could not find mesh '{}' in '{}'",
+                        self.mesh_name,
+                        self.mesh_file
+                    ),
+                );
+            }
+            has_indices
+        });
+        if let Some(instance_buffer) = &self.instance_buffer {
+            instance_buffer.check(check_context);
+        }
+        check_indirect_args(
+            check_context,
+            &self.indirect_args,
+            has_indices.map(mesh_indirect_args),
+        );
+        self.material.check(check_context, passes);
+    }
+}
+
+/// Draws `vertex_count` vertices `instance_count` times without a mesh.
+/// The vertex shader generates the geometry from `vertex_index` and `instance_index`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Procedural {
+    pub id: String,
+
+    #[serde(default)]
+    pub vertex_count: u32,
+
+    #[serde(default = "default_instance_count")]
+    pub instance_count: u32,
+
+    /// Id of an `IndirectArgs` buffer with `Draw` arguments, overrides the vertex and
+    /// instance counts. The `instance_count` global keeps the value of `instance_count`, the
+    /// count in the buffer is only known on the GPU.
+    #[serde(default)]
+    pub indirect_args: Option<String>,
+
+    pub material: file::material::Material,
+
+    #[serde(default)]
+    pub control_map: HashMap<String, String>,
+}
+
+fn default_instance_count() -> u32 {
+    1
+}
+
+/// The layout of the indirect arguments of a mesh draw, meshes without indices use `Draw`.
+fn mesh_indirect_args(has_indices: bool) -> IndirectArgsKind {
+    if has_indices {
+        IndirectArgsKind::DrawIndexed
+    } else {
+        IndirectArgsKind::Draw
+    }
+}
+
+fn load_indirect_args(
+    chart_context: &ChartContext,
+    buffer_id: &Option<String>,
+    expected: IndirectArgsKind,
+) -> Result<Option<Rc<engine::StorageBuffer>>> {
+    buffer_id
+        .as_ref()
+        .map(|id| {
+            let buffer = chart_context.get_buffer(id)?;
+            engine::ensure_indirect_args(id, buffer.indirect_args, expected)?;
+            Ok(buffer.clone())
+        })
+        .transpose()
+}
+
+/// Checks the indirect argument buffer. The kind is only checked if `expected` is known.
+fn check_indirect_args(
+    check_context: &ChartCheckContext,
+    buffer_id: &Option<String>,
+    expected: Option<IndirectArgsKind>,
+) {
+    let Some(id) = buffer_id else {
+        return;
+    };
+    if let (Some(buffer), Some(expected)) = (check_context.get_buffer(id), expected) {
+        if let Err(err) = engine::ensure_indirect_args(id, buffer.indirect_args(), expected) {
+            check_context.report(id, err);
+        }
+    }
+}
+
+impl Procedural {
+    pub async fn load(
+        &self,
+        chart_context: &ChartContext,
+        parent_id: &ControlId,
+        passes: &[engine::Pass],
+    ) -> Result<Rc<engine::ProceduralObject>> {
+        let object_cid = parent_id.add(ControlIdPartType::Object, &self.id);
+        let material = self
+            .material
+            .load(
+                chart_context,
+                passes,
+                &self.control_map,
+                &object_cid,
+                VertexLayout::Empty,
+            )
+            .await?;
+
+        let object = engine::ProceduralObject {
+            _id: self.id.clone(),
+            material,
+            vertex_count: self.vertex_count,
+            instance_count: self.instance_count,
+            indirect_buffer: load_indirect_args(
+                chart_context,
+                &self.indirect_args,
+                IndirectArgsKind::Draw,
+            )?,
+        };
+        Ok(Rc::new(object))
+    }
+
+    pub fn check(&self, check_context: &ChartCheckContext, passes: &[chart_file::Pass]) {
+        if self.indirect_args.is_none() && (self.vertex_count == 0 || self.instance_count == 0) {
+            check_context
+                .report_warning(&self.id, anyhow!("Procedural '{}' draws nothing", self.id));
+        }
+        check_indirect_args(
+            check_context,
+            &self.indirect_args,
+            Some(IndirectArgsKind::Draw),
+        );
+        self.material.check(check_context, passes);
+    }
+}
+
+/// A single triangle covering the whole screen, for full screen shaders. The vertex
+/// shader should output clip space positions from `vertex_index`, see
+/// "/shaders/fullscreen_triangle_vs.wgsl" in the demo project.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FullscreenTriangle {
+    pub id: String,
+    pub material: file::material::Material,
+
+    #[serde(default)]
+    pub control_map: HashMap<String, String>,
+}
+
+impl FullscreenTriangle {
+    pub fn as_procedural(&self) -> Procedural {
+        Procedural {
+            id: self.id.clone(),
+            vertex_count: 3,
+            instance_count: 1,
+            indirect_args: None,
+            material: self.material.clone(),
+            control_map: self.control_map.clone(),
+        }
+    }
 }
@@ -1,62 +1,409 @@
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use futures::future::join_all;
 use serde::Deserialize;
 use tracing::debug;
 
 use crate::engine;
-use crate::engine::GpuContext;
+use crate::engine::{ControlId, ControlIdPartType, ControlSetBuilder, GpuContext};
+use crate::loader::project_checker::ProjectChecker;
+use crate::loader::resource_path::ResourcePath;
 use crate::loader::resource_repository::ResourceRepository;
+use crate::loader::shader_compiler::validate_wgsl;
+use crate::loader::{CHARTS_FOLDER, PROJECT_FILE_NAME};
 
 #[derive(Debug, Deserialize)]
 pub struct Project {
     pub cuts: Vec<Cut>,
+
+    /// Tempo of the music, needed for bars:beats times and the `g_beat` and `g_bar` globals
+    #[serde(default)]
+    pub tempo: Option<Tempo>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Cut {
     pub chart: String,
-    pub start_time: f32,
-    pub end_time: f32,
+
+    /// Loads the chart under this id with its own controls, so a chart can appear in
+    /// several states. Controls follow the chart's own controls until they are overridden.
+    #[serde(default)]
+    pub instance: Option<String>,
+    pub start_time: ProjectTime,
+    pub end_time: ProjectTime,
     pub offset: f32,
+
+    /// Blends the previous cut into this one at the start of the cut
+    #[serde(default)]
+    pub transition: Option<Transition>,
+}
+
+/// A point in project time, either in seconds or as one-based "bar:beat", e.g. "17:1" or "4:2.5".
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ProjectTime {
+    Seconds(f32),
+    BarsBeats(String),
+}
+
+impl ProjectTime {
+    fn to_seconds(&self, tempo_map: Option<&engine::TempoMap>) -> Result<f32> {
+        match self {
+            ProjectTime::Seconds(seconds) => Ok(*seconds),
+            ProjectTime::BarsBeats(_) => {
+                let Some(tempo_map) = tempo_map else {
+                    bail!("Time {self:?} is in bars:beats, but the project has no tempo");
+                };
+                let beat = self.to_beat(tempo_map)?;
+                Ok(tempo_map.time_at_beat(beat))
+            }
+        }
+    }
+
+    /// Returns the zero-based beat position.
+    fn to_beat(&self, tempo_map: &engine::TempoMap) -> Result<f32> {
+        let text = match self {
+            ProjectTime::Seconds(seconds) => return Ok(tempo_map.beat_at(*seconds)),
+            ProjectTime::BarsBeats(text) => text,
+        };
+        let (bar, beat) = text.split_once(':').unwrap_or((text, "1"));
+        let (Ok(bar), Ok(beat)) = (bar.trim().parse::<u32>(), beat.trim().parse::<f32>()) else {
+            bail!("Invalid bars:beats time '{text}'");
+        };
+        if bar < 1 || beat < 1.0 || beat >= tempo_map.beats_per_bar as f32 + 1.0 {
+            bail!(
+                "Invalid bars:beats time '{text}', bars start at 1 and beats must be between 1 and {}",
+                tempo_map.beats_per_bar
+            );
+        }
+        Ok((bar - 1) as f32 * tempo_map.beats_per_bar as f32 + beat - 1.0)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tempo {
+    pub bpm: f32,
+
+    /// Beats per bar and the note value of a beat. BPM counts beats of this note value.
+    #[serde(default = "default_time_signature")]
+    pub time_signature: (u32, u32),
+
+    /// Tempo changes in order
+    #[serde(default)]
+    pub changes: Vec<TempoChange>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TempoChange {
+    pub at: ProjectTime,
+    pub bpm: f32,
+}
+
+fn default_time_signature() -> (u32, u32) {
+    (4, 4)
+}
+
+impl Tempo {
+    fn to_tempo_map(&self) -> Result<engine::TempoMap> {
+        let (beats_per_bar, _note_value) = self.time_signature;
+        let mut tempo_map = engine::TempoMap::new(self.bpm, beats_per_bar)?;
+        for change in &self.changes {
+            let beat = change.at.to_beat(&tempo_map)?;
+            tempo_map.add_tempo_change(beat, change.bpm)?;
+        }
+        Ok(tempo_map)
+    }
+}
+
+/// The progress of the transition is the `transition_progress_<cut index>` control under the
+/// incoming chart.
+#[derive(Debug, Deserialize)]
+pub struct Transition {
+    pub duration: f32,
+
+    #[serde(default)]
+    pub kind: TransitionKind,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub enum TransitionKind {
+    #[default]
+    Crossfade,
+    Wipe,
+
+    /// Path of a WGSL file relative to the project root that defines the
+    /// `transition` function, see transition.wgsl
+    Shader(String),
+}
+
+impl Cut {
+    /// Returns the id the chart is loaded under.
+    fn chart_id(&self) -> &str {
+        self.instance.as_deref().unwrap_or(&self.chart)
+    }
+
+    /// Returns the chart this cut shows an instance of.
+    fn base_id(&self) -> Option<&str> {
+        self.instance
+            .as_ref()
+            .filter(|&instance| instance != &self.chart)
+            .map(|_| self.chart.as_str())
+    }
+
+    /// Returns the start and end time in seconds.
+    fn times(&self, tempo_map: Option<&engine::TempoMap>) -> Result<(f32, f32)> {
+        let to_seconds = |time: &ProjectTime| {
+            time.to_seconds(tempo_map)
+                .with_context(|| format!("Invalid time in cut of chart '{}'", self.chart_id()))
+        };
+        Ok((to_seconds(&self.start_time)?, to_seconds(&self.end_time)?))
+    }
+}
+
+impl Transition {
+    async fn load(
+        &self,
+        context: &GpuContext,
+        resource_repository: &ResourceRepository,
+        progress: Rc<engine::Control>,
+    ) -> Result<engine::Transition> {
+        let kind = match &self.kind {
+            TransitionKind::Crossfade => engine::TransitionKind::Crossfade,
+            TransitionKind::Wipe => engine::TransitionKind::Wipe,
+            TransitionKind::Shader(file_name) => {
+                let path = project_file_path(resource_repository).relative_path(file_name)?;
+                let source = resource_repository.read_text_file(&path).await?;
+                engine::TransitionKind::Shader(source)
+            }
+        };
+        validate_wgsl(&kind.shader_source())
+            .with_context(|| format!("Invalid transition shader: {:?}", self.kind))?;
+        Ok(engine::Transition::new(
+            context,
+            self.duration,
+            &kind,
+            progress,
+        ))
+    }
+
+    fn check(&self, checker: &ProjectChecker, path: &ResourcePath, chart_id: &str) {
+        if self.duration <= 0.0 {
+            checker.report(anyhow!(
+                "{path:?}: Transition of chart '{chart_id}' must have a positive duration"
+            ));
+        }
+        let TransitionKind::Shader(file_name) = &self.kind else {
+            return;
+        };
+        let result = path
+            .relative_path(file_name)
+            .and_then(|shader_path| shader_path.absolute_path())
+            .and_then(|absolute_path| {
+                std::fs::read_to_string(&absolute_path)
+                    .with_context(|| format!("Failed to read file: {absolute_path:?}"))
+            })
+            .and_then(|source| {
+                validate_wgsl(&engine::TransitionKind::Shader(source).shader_source())
+            });
+        if let Err(err) = result {
+            checker.report(err.context(format!(
+                "{path:?}: Invalid transition shader '{file_name}' of chart '{chart_id}'"
+            )));
+        }
+    }
+}
+
+fn project_file_path(resource_repository: &ResourceRepository) -> ResourcePath {
+    ResourcePath::new(
+        &resource_repository.root_path,
+        PathBuf::new(),
+        PROJECT_FILE_NAME,
+    )
 }
 
 impl Project {
+    /// Loads the project. Charts load independently of each other.
+    ///
+    /// Charts of `previous_project` are reused if none of their files changed.
+    /// A chart that fails to load is replaced by its version in `previous_project`, or by a
+    /// placeholder if there's none. The error is attached to the cuts of the chart.
     pub async fn load(
         &self,
         context: &Arc<GpuContext>,
         resource_repository: &Rc<ResourceRepository>,
+        previous_project: Option<&engine::Project>,
     ) -> Result<engine::Project> {
-        let chart_ids: HashSet<_> = self.cuts.iter().map(|cut| &cut.chart).collect();
-        let chart_futures_by_id = chart_ids.iter().map(|&chart_name| async move {
+        let tempo_map = self.tempo_map()?;
+        let cut_times = self
+            .cuts
+            .iter()
+            .map(|cut| cut.times(tempo_map.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let chart_instances = self.chart_instances(&resource_repository.root_path)?;
+        let chart_futures_by_id = chart_instances.iter().map(|(&chart_id, &base_id)| async move {
+            let previous_chart = previous_project
+                .and_then(|project| project.charts_by_id.get(chart_id))
+                .filter(|chart| chart.base_id.as_deref() == base_id)
+                .filter(|_| !resource_repository.is_chart_changed(chart_id));
+            if let Some(chart) = previous_chart {
+                debug!("Chart {} is unchanged", chart_id);
+                resource_repository.keep_chart_dependencies(chart_id).await;
+                chart.controls.restore_component_usage_counts();
+                return (chart_id, Ok(chart.clone()));
+            }
             let now = Instant::now();
-            let chart = resource_repository.load_chart(chart_name, context).await?;
-            debug!("Loaded chart {} in {:?}", chart_name, now.elapsed());
-            Ok((chart_name.clone(), chart))
+            let chart = resource_repository.load_chart(chart_id, base_id, context).await;
+            debug!("Loaded chart {} in {:?}", chart_id, now.elapsed());
+            (chart_id, chart)
         });
 
         // Load all charts in parallel.
-        let charts_by_id: HashMap<_, _> =
-            join_all(chart_futures_by_id).await.into_iter().collect::<Result<_>>()?;
+        let mut charts_by_id = HashMap::new();
+        let mut load_errors_by_id = HashMap::new();
+        for (chart_id, result) in join_all(chart_futures_by_id).await {
+            let chart = match result {
+                Ok(chart) => chart,
+                Err(err) => {
+                    let previous_chart = previous_project
+                        .and_then(|project| project.charts_by_id.get(chart_id))
+                        .cloned();
+                    let chart = match previous_chart {
+                        Some(chart) => {
+                            chart.controls.restore_component_usage_counts();
+                            chart
+                        }
+                        None => Rc::new(engine::Chart::new_placeholder(
+                            context,
+                            chart_id,
+                            chart_instances[chart_id],
+                        )),
+                    };
+                    load_errors_by_id.insert(chart_id, Rc::new(format!("{err:#}")));
+                    chart
+                }
+            };
+            charts_by_id.insert(chart_id.to_string(), chart);
+        }
 
-        let cuts = self
-            .cuts
-            .iter()
-            .map(|cut| engine::Cut {
-                chart: charts_by_id[&cut.chart].clone(),
-                start_time: cut.start_time,
-                end_time: cut.end_time,
+        // Transition controls belong to the project, with ids under the incoming chart. The ids
+        // include the cut index, as a chart can have several cuts.
+        let control_set_builder = ControlSetBuilder::new(
+            ControlId::default(),
+            resource_repository.control_repository.clone(),
+        );
+        let mut cuts = vec![];
+        for (cut_index, (cut, (start_time, end_time))) in
+            self.cuts.iter().zip(cut_times).enumerate()
+        {
+            let mut load_error = load_errors_by_id.get(cut.chart_id()).cloned();
+            let transition = match &cut.transition {
+                Some(transition) => {
+                    let progress_id =
+                        ControlId::default().add(ControlIdPartType::Chart, cut.chart_id()).add(
+                            ControlIdPartType::Value,
+                            &format!("transition_progress_{cut_index}"),
+                        );
+                    let progress = control_set_builder.get_float_with_default(&progress_id, 0.0);
+                    // A transition that fails to load is replaced by a hard cut
+                    match transition.load(context, resource_repository, progress).await {
+                        Ok(transition) => Some(transition),
+                        Err(err) => {
+                            let message = format!(
+                                "Failed to load transition of chart '{}': {err:#}",
+                                cut.chart_id()
+                            );
+                            let message = match &load_error {
+                                Some(chart_error) => format!("{chart_error}\n{message}"),
+                                None => message,
+                            };
+                            load_error = Some(Rc::new(message));
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+            cuts.push(engine::Cut {
+                chart: charts_by_id[cut.chart_id()].clone(),
+                start_time,
+                end_time,
                 offset: cut.offset,
-            })
-            .collect();
+                load_error,
+                transition,
+            });
+        }
         Ok(engine::Project::new(
+            context,
             &resource_repository.root_path,
             charts_by_id,
             cuts,
+            control_set_builder.into_control_set(&[]),
+            tempo_map,
         ))
     }
+
+    /// Returns the chart each chart id is an instance of, or None if it's not an instance.
+    fn chart_instances(&self, root_path: &Path) -> Result<HashMap<&str, Option<&str>>> {
+        let mut chart_instances = HashMap::new();
+        for cut in &self.cuts {
+            let (chart_id, base_id) = (cut.chart_id(), cut.base_id());
+            if let Some(previous_base_id) = chart_instances.insert(chart_id, base_id) {
+                ensure!(
+                    previous_base_id == base_id,
+                    "Chart id '{chart_id}' is used for different charts"
+                );
+            }
+            if base_id.is_some() {
+                ensure!(
+                    !root_path.join(CHARTS_FOLDER).join(chart_id).exists(),
+                    "Chart instance '{chart_id}' has the same name as a chart folder"
+                );
+            }
+        }
+        Ok(chart_instances)
+    }
+
+    fn tempo_map(&self) -> Result<Option<engine::TempoMap>> {
+        let Some(tempo) = &self.tempo else {
+            return Ok(None);
+        };
+        let tempo_map = tempo.to_tempo_map().context("Invalid tempo")?;
+        Ok(Some(tempo_map))
+    }
+
+    /// Validates the project and all its charts without a GPU.
+    pub fn check(&self, checker: &ProjectChecker, path: &ResourcePath) {
+        let tempo_map = self.tempo_map().unwrap_or_else(|err| {
+            checker.report(err.context(format!("{path:?}")));
+            None
+        });
+        for cut in &self.cuts {
+            match cut.times(tempo_map.as_ref()) {
+                Ok((start_time, end_time)) if end_time < start_time => {
+                    checker.report(anyhow!(
+                        "{path:?}: Cut of chart '{}' ends before it starts",
+                        cut.chart_id()
+                    ));
+                }
+                Ok(_) => {}
+                Err(err) => checker.report(err.context(format!("{path:?}"))),
+            }
+            if let Some(transition) = &cut.transition {
+                transition.check(checker, path, cut.chart_id());
+            }
+        }
+        if let Err(err) = self.chart_instances(&path.root_path) {
+            checker.report(err.context(format!("{path:?}")));
+        }
+        let chart_ids: HashSet<_> = self.cuts.iter().map(|cut| &cut.chart).collect();
+        for chart_id in chart_ids {
+            checker.check_chart(chart_id);
+        }
+    }
 }
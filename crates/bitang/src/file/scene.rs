@@ -6,15 +6,16 @@ use serde::Deserialize;
 use tracing::instrument;
 
 use crate::engine;
-use crate::engine::{ControlId, ControlIdPartType};
-use crate::file::chart_file::ChartContext;
+use crate::engine::{ControlId, ControlIdPartType, VertexLayout};
+use crate::file::chart_file::{self, ChartContext};
 use crate::file::material::Material;
+use crate::loader::project_checker::ChartCheckContext;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub(crate) struct Scene {
     id: String,
     file: String,
-    material: Material,
+    pub material: Material,
 
     #[serde(default)]
     pub control_map: HashMap<String, String>,
@@ -32,14 +33,20 @@ impl Scene {
         let mesh_collection_future = tokio::spawn({
             let mesh_cache = chart_context.resource_repository.mesh_cache.clone();
             let vulkan_context = chart_context.gpu_context.clone();
-            let path = chart_context.path.relative_path(&self.file)?;
+            let path = chart_context.dependency_path(&self.file)?;
             async move { mesh_cache.load(&vulkan_context, &path).await }
         });
 
         // Load material
         let material = self
             .material
-            .load(chart_context, passes, &self.control_map, &scene_cid)
+            .load(
+                chart_context,
+                passes,
+                &self.control_map,
+                &scene_cid,
+                VertexLayout::Mesh,
+            )
             .await
             .with_context(|| format!("Failed to load material for scene '{}'", self.id))?;
 
@@ -72,6 +79,8 @@ impl Scene {
                     instances: chart_context
                         .control_set_builder
                         .get_float_with_default(&instances_id, 1.),
+                    instance_buffer: None,
+                    indirect_buffer: None,
                 }
             })
             .collect();
@@ -82,4 +91,9 @@ impl Scene {
         };
         Ok(Rc::new(scene))
     }
+
+    pub fn check(&self, check_context: &ChartCheckContext, passes: &[chart_file::Pass]) {
+        check_context.check_file(&self.file);
+        self.material.check(check_context, passes);
+    }
 }
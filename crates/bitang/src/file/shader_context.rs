@@ -1,20 +1,46 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::Deserialize;
 use tracing::instrument;
 
 use crate::engine::{
-    self, BitangImage, ControlId, ControlIdPartType, DescriptorResource, DescriptorSource,
-    ImageDescriptor, LocalUniformMapping, SamplerDescriptor, Shader, ShaderKind,
+    self, BitangImage, BufferDescriptor, BufferSlot, ControlId, ControlIdPartType,
+    DescriptorResource, DescriptorSource, GpuContext, ImageDescriptor, LocalUniformMapping,
+    SamplerDescriptor, Shader, ShaderKind, StorageBuffer, StorageImageDescriptor,
 };
+use crate::file::chart_analysis::SampledTextures;
 use crate::file::chart_file::ChartContext;
 use crate::loader::async_cache::LoadFuture;
+use crate::loader::project_checker::ChartCheckContext;
+use crate::loader::shader_compiler::TextureBinding;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub enum BufferSource {
+    /// The buffer read by the current simulation step
     Current(String),
+
+    /// The buffer written by the current simulation step
     Next(String),
+
+    /// A single buffer, or the current buffer of a double buffer
+    Buffer(String),
+}
+
+impl BufferSource {
+    pub fn id(&self) -> &String {
+        match self {
+            BufferSource::Current(id) | BufferSource::Next(id) | BufferSource::Buffer(id) => id,
+        }
+    }
+
+    pub fn slot(&self) -> BufferSlot {
+        match self {
+            BufferSource::Current(_) | BufferSource::Buffer(_) => BufferSlot::Current,
+            BufferSource::Next(_) => BufferSlot::Next,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -60,7 +86,7 @@ pub struct ShaderContext {
     control_id: ControlId,
     texture_futures: HashMap<String, (LoadFuture<BitangImage>, Texture)>,
     samplers: HashMap<String, Sampler>,
-    buffers_by_binding: HashMap<String, DescriptorSource>,
+    buffers_by_binding: HashMap<String, (Rc<StorageBuffer>, BufferSlot)>,
 }
 
 impl ShaderContext {
@@ -79,14 +105,10 @@ impl ShaderContext {
                     match &texture.bind {
                         ImageSource::File(texture_path) => resource_repository.get_texture(
                             &chart_context.gpu_context,
-                            &chart_context.path.relative_path(texture_path)?,
+                            &chart_context.dependency_path(texture_path)?,
                         ),
                         ImageSource::Image(id) => {
-                            let image = chart_context
-                                .images_by_id
-                                .get(id)
-                                .with_context(|| anyhow!("Render target '{id}' not found"))?
-                                .clone();
+                            let image = chart_context.get_image(id)?.clone();
                             LoadFuture::new_from_value(format!("image:{}", id), image)
                         }
                     }
@@ -98,62 +120,14 @@ impl ShaderContext {
 
         let buffers_by_binding = buffers
             .iter()
-            .map(|(name, buffer)| {
-                let buffer_source = match buffer {
-                    BufferSource::Current(id) => {
-                        let buffer = chart_context
-                            .buffers_by_id
-                            .get(id)
-                            .with_context(|| anyhow!("Buffer '{id}' not found"))?
-                            .clone();
-                        DescriptorSource::BufferCurrent(buffer)
-                    }
-                    BufferSource::Next(id) => {
-                        let buffer = chart_context
-                            .buffers_by_id
-                            .get(id)
-                            .with_context(|| anyhow!("Buffer '{id}' not found"))?
-                            .clone();
-                        DescriptorSource::BufferNext(buffer)
-                    }
-                };
-                Ok((name.clone(), buffer_source))
+            .map(|(name, buffer_source)| {
+                let id = buffer_source.id();
+                let buffer = chart_context.get_buffer(id)?.clone();
+                Ok((name.clone(), (buffer, buffer_source.slot())))
             })
             .collect::<Result<HashMap<_, _>>>()?;
 
-        // TODO: put this somewhere more global
-        let samplers = HashMap::from([
-            (
-                "sampler_repeat".to_string(),
-                Sampler {
-                    mode: SamplerMode::Repeat,
-                },
-            ),
-            (
-                "sampler_clamp_to_edge".to_string(),
-                Sampler {
-                    mode: SamplerMode::ClampToEdge,
-                },
-            ),
-            (
-                "sampler_mirror".to_string(),
-                Sampler {
-                    mode: SamplerMode::MirroredRepeat,
-                },
-            ),
-            (
-                "sampler_envmap".to_string(),
-                Sampler {
-                    mode: SamplerMode::Envmap,
-                },
-            ),
-            (
-                "sampler_shadow".to_string(),
-                Sampler {
-                    mode: SamplerMode::Shadow,
-                },
-            ),
-        ]);
+        let samplers = default_samplers();
 
         Ok(ShaderContext {
             control_map: control_map.clone(),
@@ -171,30 +145,23 @@ impl ShaderContext {
         kind: ShaderKind,
         source_path: &str,
     ) -> Result<Shader> {
-        let mut features = self
-            .texture_futures
-            .keys()
-            .map(|s| format!("TEXTURE_BOUND_TO_{}", s.to_uppercase()))
-            .collect::<Vec<_>>();
-
-        let (source_path, entry_point) = if let Some(i) = source_path.find(':') {
-            (&source_path[..i], source_path[i + 1..].to_string())
-        } else {
-            (source_path, kind.default_entry_point().to_string())
-        };
-        features.push(format!("ENTRY_POINT_{}", entry_point.to_uppercase()));
+        let (source_path, entry_point, features) =
+            shader_compile_args(self.texture_futures.keys(), kind, source_path);
 
         let shader_artifact = chart_context
             .resource_repository
             .shader_cache
             .get(
                 &chart_context.gpu_context,
-                chart_context.path.relative_path(source_path)?,
+                chart_context.dependency_path(source_path)?,
                 kind,
                 entry_point.clone(),
                 features,
             )
             .await?;
+        for path in &shader_artifact.include_chain {
+            chart_context.add_dependency(path);
+        }
 
         let local_uniform_bindings = shader_artifact
             .local_uniform_bindings
@@ -219,15 +186,21 @@ impl ShaderContext {
 
         // Collect buffer generator bindings
         for buffer in &shader_artifact.buffers {
-            let Some(descriptor_source) = self.buffers_by_binding.get(&buffer.name) else {
-                // TODO: once we only collect used resources, this should be an error
+            let Some((storage_buffer, slot)) = self.buffers_by_binding.get(&buffer.name) else {
+                if buffer.is_used {
+                    bail!("Buffer definition for '{}' not found", buffer.name);
+                }
                 continue;
             };
 
             let buffer_descriptor = DescriptorResource {
                 id: buffer.name.clone(),
                 binding: buffer.binding,
-                source: descriptor_source.clone(),
+                source: DescriptorSource::Buffer(BufferDescriptor {
+                    buffer: storage_buffer.clone(),
+                    slot: *slot,
+                    read_only: buffer.read_only,
+                }),
             };
             descriptor_resources.push(buffer_descriptor);
         }
@@ -243,11 +216,53 @@ impl ShaderContext {
             let sampler_descriptor = DescriptorResource {
                 id: texture.name.clone(),
                 binding: texture.binding,
-                source: DescriptorSource::Image(ImageDescriptor::new(image)?),
+                source: DescriptorSource::Image(
+                    ImageDescriptor::new(
+                        image,
+                        texture.view_dimension,
+                        texture.sample_type,
+                        is_filtered(texture, &self.samplers),
+                        chart_context.gpu_context.device.features(),
+                    )
+                    .with_context(|| anyhow!("Failed to bind texture '{}'", texture.name))?,
+                ),
             };
             descriptor_resources.push(sampler_descriptor);
         }
 
+        // Collect storage texture bindings
+        for storage_texture in &shader_artifact.storage_textures {
+            let (image_future, texture) =
+                self.texture_futures.get(&storage_texture.name).with_context(|| {
+                    anyhow!(
+                        "Texture definition for '{}' not found",
+                        storage_texture.name
+                    )
+                })?;
+            if !matches!(texture.bind, ImageSource::Image(_)) {
+                bail!(
+                    "Storage texture '{}' must be bound to a chart image",
+                    storage_texture.name
+                );
+            }
+            let image = image_future.get().await?;
+            let storage_image_descriptor = DescriptorResource {
+                id: storage_texture.name.clone(),
+                binding: storage_texture.binding,
+                source: DescriptorSource::StorageImage(
+                    StorageImageDescriptor::new(
+                        image,
+                        storage_texture.view_dimension,
+                        storage_texture.access,
+                    )
+                    .with_context(|| {
+                        anyhow!("Failed to bind storage texture '{}'", storage_texture.name)
+                    })?,
+                ),
+            };
+            descriptor_resources.push(storage_image_descriptor);
+        }
+
         // Collect sampler bindings
         for sampler in &shader_artifact.samplers {
             let source = self
@@ -274,8 +289,302 @@ impl ShaderContext {
             local_uniform_bindings,
             shader_artifact.uniform_buffer_byte_size,
             descriptor_resources,
+            shader_artifact.workgroup_size,
         );
 
         Ok(shader)
     }
 }
+
+/// Splits the "path:entry_point" shader reference and collects the features to compile it with.
+fn shader_compile_args<'a>(
+    texture_names: impl Iterator<Item = &'a String>,
+    kind: ShaderKind,
+    source_path: &str,
+) -> (&str, String, Vec<String>) {
+    let mut features = texture_names
+        .map(|s| format!("TEXTURE_BOUND_TO_{}", s.to_uppercase()))
+        .collect::<Vec<_>>();
+
+    let (source_path, entry_point) = if let Some(i) = source_path.find(':') {
+        (&source_path[..i], source_path[i + 1..].to_string())
+    } else {
+        (source_path, kind.default_entry_point().to_string())
+    };
+    features.push(format!("ENTRY_POINT_{}", entry_point.to_uppercase()));
+    (source_path, entry_point, features)
+}
+
+/// Returns true if the shader samples the texture with a filtering sampler.
+fn is_filtered(texture: &TextureBinding, samplers: &HashMap<String, Sampler>) -> bool {
+    // Comparison samplers don't filter
+    texture.samplers.iter().any(|sampler| {
+        samplers
+            .get(sampler)
+            .is_some_and(|sampler| sampler.mode.load().to_wgpu_compare_op().is_none())
+    })
+}
+
+// TODO: put this somewhere more global
+fn default_samplers() -> HashMap<String, Sampler> {
+    HashMap::from([
+        (
+            "sampler_repeat".to_string(),
+            Sampler {
+                mode: SamplerMode::Repeat,
+            },
+        ),
+        (
+            "sampler_clamp_to_edge".to_string(),
+            Sampler {
+                mode: SamplerMode::ClampToEdge,
+            },
+        ),
+        (
+            "sampler_mirror".to_string(),
+            Sampler {
+                mode: SamplerMode::MirroredRepeat,
+            },
+        ),
+        (
+            "sampler_envmap".to_string(),
+            Sampler {
+                mode: SamplerMode::Envmap,
+            },
+        ),
+        (
+            "sampler_shadow".to_string(),
+            Sampler {
+                mode: SamplerMode::Shadow,
+            },
+        ),
+    ])
+}
+
+/// Checks the texture and buffer references of a shader context without a GPU,
+/// the same way `ShaderContext::new` resolves them.
+pub fn check_shader_resources(
+    check_context: &ChartCheckContext,
+    textures: &HashMap<String, Texture>,
+    buffers: &HashMap<String, BufferSource>,
+) {
+    for texture in textures.values() {
+        match &texture.bind {
+            ImageSource::File(texture_path) => check_context.check_file(texture_path),
+            ImageSource::Image(id) => {
+                check_context.get_image(id);
+            }
+        }
+    }
+    for buffer in buffers.values() {
+        check_context.get_buffer(buffer.id());
+    }
+}
+
+/// Compiles a shader without a GPU and checks that all its resources are defined,
+/// the same way `ShaderContext::make_shader` binds them.
+pub fn check_shader(
+    check_context: &ChartCheckContext,
+    kind: ShaderKind,
+    source_path: &str,
+    textures: &HashMap<String, Texture>,
+    buffers: &HashMap<String, BufferSource>,
+) {
+    let (path, entry_point, features) = shader_compile_args(textures.keys(), kind, source_path);
+    let Some(interface) = check_context.get_shader_interface(path, kind, entry_point, features)
+    else {
+        return;
+    };
+    let samplers = default_samplers();
+
+    for texture in &interface.textures {
+        match textures.get(&texture.name).map(|definition| &definition.bind) {
+            Some(ImageSource::Image(id)) => {
+                // Missing images are reported by `check_shader_resources`
+                let Some(image) = check_context.images_by_id.get(id) else {
+                    continue;
+                };
+                let result = ImageDescriptor::new(
+                    image.clone(),
+                    texture.view_dimension,
+                    texture.sample_type,
+                    is_filtered(texture, &samplers),
+                    GpuContext::REQUIRED_FEATURES,
+                );
+                if let Err(err) = result {
+                    check_context.report(
+                        source_path,
+                        err.context(format!("Failed to bind texture '{}'", texture.name)),
+                    );
+                }
+            }
+            Some(ImageSource::File(_)) => {
+                if texture.view_dimension != wgpu::TextureViewDimension::D2 {
+                    check_context.report(
+                        source_path,
+                        anyhow!(
+                            "Texture '{}' is a {:?} texture, but image files are 2D",
+                            texture.name,
+                            texture.view_dimension
+                        ),
+                    );
+                }
+                if !matches!(texture.sample_type, wgpu::TextureSampleType::Float { .. }) {
+                    check_context.report(
+                        source_path,
+                        anyhow!(
+                            "Texture '{}' has sample type {:?}, but image files are float",
+                            texture.name,
+                            texture.sample_type
+                        ),
+                    );
+                }
+            }
+            None => check_context.report(
+                source_path,
+                anyhow!("Texture definition for '{}' not found", texture.name),
+            ),
+        }
+    }
+    for storage_texture in &interface.storage_textures {
+        match textures.get(&storage_texture.name).map(|texture| &texture.bind) {
+            Some(ImageSource::Image(id)) => {
+                let Some(image) = check_context.images_by_id.get(id) else {
+                    continue;
+                };
+                let result = StorageImageDescriptor::new(
+                    image.clone(),
+                    storage_texture.view_dimension,
+                    storage_texture.access,
+                );
+                if let Err(err) = result {
+                    check_context.report(
+                        source_path,
+                        err.context(format!(
+                            "Failed to bind storage texture '{}'",
+                            storage_texture.name
+                        )),
+                    );
+                }
+            }
+            Some(ImageSource::File(_)) => check_context.report(
+                source_path,
+                anyhow!(
+                    "Storage texture '{}' must be bound to a chart image",
+                    storage_texture.name
+                ),
+            ),
+            None => check_context.report(
+                source_path,
+                anyhow!(
+                    "Texture definition for '{}' not found",
+                    storage_texture.name
+                ),
+            ),
+        }
+    }
+    // The access of each buffer slot, to find slots bound both read-only and read-write
+    let mut buffer_slot_access: HashMap<(&String, BufferSlot), bool> = HashMap::new();
+    for buffer in &interface.buffers {
+        let Some(buffer_source) = buffers.get(&buffer.name) else {
+            if buffer.is_used {
+                check_context.report(
+                    source_path,
+                    anyhow!("Buffer definition for '{}' not found", buffer.name),
+                );
+            }
+            continue;
+        };
+        if let BufferSource::Current(id) = buffer_source {
+            if !buffer.read_only {
+                check_context.report(
+                    source_path,
+                    anyhow!(
+                        "Buffer '{}' is read-write, but the current buffer of '{id}' is read-only",
+                        buffer.name
+                    ),
+                );
+            }
+        }
+        let slot = (buffer_source.id(), buffer_source.slot());
+        if let Some(read_only) = buffer_slot_access.insert(slot, buffer.read_only) {
+            if read_only != buffer.read_only {
+                check_context.report(
+                    source_path,
+                    anyhow!(
+                        "Buffer '{}' is bound both read-only and read-write",
+                        buffer_source.id()
+                    ),
+                );
+            }
+        }
+    }
+    for sampler in &interface.samplers {
+        if !samplers.contains_key(&sampler.name) {
+            check_context.report(
+                source_path,
+                anyhow!("Sampler definition for '{}' not found", sampler.name),
+            );
+        }
+    }
+}
+
+/// Returns the ids of the chart images bound to a shader, sampled or as storage textures.
+pub fn bound_image_ids(textures: &HashMap<String, Texture>) -> Vec<String> {
+    textures
+        .values()
+        .filter_map(|texture| match &texture.bind {
+            ImageSource::Image(id) => Some(id.clone()),
+            ImageSource::File(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the ids of the chart images a shader samples.
+///
+/// If the shader is not known, every image bound to it is assumed to be sampled.
+pub fn sampled_image_ids(
+    source_path: &str,
+    kind: ShaderKind,
+    textures: &HashMap<String, Texture>,
+    sampled_textures: &SampledTextures,
+) -> Vec<String> {
+    let sampled_names = sampled_textures(source_path, kind, textures);
+    textures
+        .iter()
+        .filter(|(name, _)| sampled_names.as_ref().is_none_or(|names| names.contains(name)))
+        .filter_map(|(_, texture)| match &texture.bind {
+            ImageSource::Image(id) => Some(id.clone()),
+            ImageSource::File(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the names of the textures a shader loaded in the current load cycle samples.
+pub fn loaded_sampled_textures(
+    chart_context: &ChartContext,
+    source_path: &str,
+    kind: ShaderKind,
+    textures: &HashMap<String, Texture>,
+) -> Option<Vec<String>> {
+    let (path, entry_point, features) = shader_compile_args(textures.keys(), kind, source_path);
+    let shader_artifact = chart_context.resource_repository.shader_cache.get_loaded(
+        chart_context.path.relative_path(path).ok()?,
+        kind,
+        entry_point,
+        features,
+    )?;
+    Some(shader_artifact.textures.iter().map(|texture| texture.name.clone()).collect())
+}
+
+/// Returns the names of the textures a shader compiled by the project check samples.
+pub fn checked_sampled_textures(
+    check_context: &ChartCheckContext,
+    source_path: &str,
+    kind: ShaderKind,
+    textures: &HashMap<String, Texture>,
+) -> Option<Vec<String>> {
+    let (path, entry_point, features) = shader_compile_args(textures.keys(), kind, source_path);
+    let interface = check_context.try_get_shader_interface(path, kind, entry_point, features)?;
+    Some(interface.textures.iter().map(|texture| texture.name.clone()).collect())
+}
@@ -97,6 +97,13 @@ impl<T: Send + Sync + 'static> LoadFuture<T> {
         }
     }
 
+    /// Returns the value if the future resolved successfully.
+    async fn get_ok(&self) -> Option<Arc<T>> {
+        let mut inner = self.inner.lock().await;
+        Self::resolve(&mut inner).await;
+        inner.value.as_ref().unwrap().as_ref().as_ref().ok().cloned()
+    }
+
     /// Displays the root case of a load error.
     async fn display_load_error(&self) {
         let mut inner = self.inner.lock().await;
@@ -156,11 +163,24 @@ impl<Key: Hash + Eq + Clone + Debug, Value: Send + Sync + 'static> AsyncCache<Ke
         future.get().await
     }
 
+    /// Returns the value for a key if it was loaded successfully. Doesn't start loading.
+    pub fn get_loaded(&self, key: &Key) -> Option<Arc<Value>> {
+        let future = self.items.get(key)?.clone();
+        block_on(future.get_ok())
+    }
+
     /// Call this before starting a new loading cycle.
     pub fn start_load_cycle(&self) {
         self.accessed_in_current_load_cycle.clear();
     }
 
+    /// Keeps the successfully loaded values for which `keep` returns true and removes the rest.
+    pub fn retain_loaded(&self, keep: impl Fn(&Key, &Value) -> bool) {
+        self.items
+            .retain(|key, future| block_on(future.get_ok()).is_some_and(|value| keep(key, &value)));
+        self.accessed_in_current_load_cycle.clear();
+    }
+
     /// Removes a key from the cache.
     pub fn remove(&self, key: &Key) {
         self.items.remove(key);
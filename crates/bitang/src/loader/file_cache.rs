@@ -54,6 +54,13 @@ impl FileCache {
         self.has_missing_files.store(false, Ordering::Relaxed);
     }
 
+    /// Keeps watching a file that wasn't read in the current load cycle.
+    pub async fn keep_watching(&self, path: &ResourcePath) {
+        if let Ok(absolute_path) = path.absolute_path() {
+            self.paths_accessed_in_loading_cycle.lock().await.insert(absolute_path);
+        }
+    }
+
     pub async fn get(&self, path: &ResourcePath) -> Result<Arc<FileCacheEntry>> {
         let absolute_path = path.absolute_path()?;
         {
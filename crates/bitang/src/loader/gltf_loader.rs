@@ -27,92 +27,149 @@ pub fn load_mesh_collection(
     let scene = gltf.default_scene().context("No default scene found")?;
     let mut nodes_by_name = HashMap::new();
 
-    for node in scene.nodes() {
-        let Some(name) = node.name() else { continue };
+    for (name, node, primitive) in named_primitives(&scene) {
         debug!("Loading mesh '{name}'");
-
-        let Some(mesh) = node.mesh() else { continue };
-
-        let primitives =
-            mesh.primitives().filter(|p| p.mode() == gltf::mesh::Mode::Triangles).collect_vec();
-
-        for (pi, primitive) in primitives.into_iter().enumerate() {
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-
-            // Read positions
-            let mut vertices = if let Some(iter) = reader.read_positions() {
-                iter.map(|p| Vertex3 {
-                    a_position: gltf_to_left_handed_y_up(&p),
-                    ..Default::default()
-                })
-                .collect::<Vec<_>>()
-            } else {
-                info!("Mesh '{name}' has no vertex positions, ignoring.");
-                continue;
-            };
-            let vertex_count = vertices.len();
-
-            // Read normals
-            if let Some(iter) = reader.read_normals() {
-                for (i, normal) in iter.take(vertex_count).enumerate() {
-                    vertices[i].a_normal = gltf_to_left_handed_y_up(&normal);
-                }
-            } else {
-                warn!("Mesh '{name}' has no vertex normals.");
-            };
-
-            // Read tangents
-            if let Some(iter) = reader.read_tangents() {
-                for (i, tangent) in iter.take(vertex_count).enumerate() {
-                    // The fourth component of the tangent is the handedness, but I'm feeling lucky and ignore it.
-                    vertices[i].a_tangent =
-                        gltf_to_left_handed_y_up(&[tangent[0], tangent[1], tangent[2]]);
-                }
-            } else {
-                warn!("Mesh '{name}' has no vertex tangents.");
-            };
-
-            // Read texture coordinates
-            if let Some(iter) = reader.read_tex_coords(0) {
-                for (i, uv) in iter.into_f32().take(vertex_count).enumerate() {
-                    vertices[i].a_uv = uv;
-                }
-            } else {
-                warn!("Mesh '{name}' has no texture coordinates.");
-            };
-
-            // Read indices
-            let indices =
-                reader.read_indices().and_then(|indices| Some(indices.into_u32().collect_vec()));
-
-            debug!("Loaded {} vertices", vertices.len());
-
-            let (translation, r, _scale) = node.transform().decomposed();
-
-            let rotation =
-                glam::Quat::from_xyzw(r[0], r[1], r[2], r[3]).to_euler(glam::EulerRot::ZXY);
-
-            // Bake scaling into vertices
-            for vertex in &mut vertices {
-                vertex.a_position[0] *= _scale[0];
-                vertex.a_position[1] *= _scale[1];
-                vertex.a_position[2] *= _scale[2];
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+        // Read positions
+        let mut vertices = if let Some(iter) = reader.read_positions() {
+            iter.map(|p| Vertex3 {
+                a_position: gltf_to_left_handed_y_up(&p),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>()
+        } else {
+            info!("Mesh '{name}' has no vertex positions, ignoring.");
+            continue;
+        };
+        let vertex_count = vertices.len();
+
+        // Read normals
+        if let Some(iter) = reader.read_normals() {
+            for (i, normal) in iter.take(vertex_count).enumerate() {
+                vertices[i].a_normal = gltf_to_left_handed_y_up(&normal);
+            }
+        } else {
+            warn!("Mesh '{name}' has no vertex normals.");
+        };
+
+        // Read tangents
+        if let Some(iter) = reader.read_tangents() {
+            for (i, tangent) in iter.take(vertex_count).enumerate() {
+                // The fourth component of the tangent is the handedness, but I'm feeling lucky and ignore it.
+                vertices[i].a_tangent =
+                    gltf_to_left_handed_y_up(&[tangent[0], tangent[1], tangent[2]]);
             }
+        } else {
+            warn!("Mesh '{name}' has no vertex tangents.");
+        };
+
+        // Read texture coordinates
+        if let Some(iter) = reader.read_tex_coords(0) {
+            for (i, uv) in iter.into_f32().take(vertex_count).enumerate() {
+                vertices[i].a_uv = uv;
+            }
+        } else {
+            warn!("Mesh '{name}' has no texture coordinates.");
+        };
 
-            let mesh = Arc::new(Mesh::try_new(context, vertices, indices)?);
+        // Read indices
+        let indices =
+            reader.read_indices().and_then(|indices| Some(indices.into_u32().collect_vec()));
 
-            let node = SceneNode {
-                position: gltf_to_left_handed_y_up(&translation),
-                rotation: [-rotation.1, -rotation.2, rotation.0],
-                mesh,
-            };
+        debug!("Loaded {} vertices", vertices.len());
 
-            let mesh_name = if pi > 0 { format!("{name}.{pi}") } else { name.to_string() };
+        let (translation, r, _scale) = node.transform().decomposed();
 
-            nodes_by_name.insert(mesh_name, Arc::new(node));
+        let rotation = glam::Quat::from_xyzw(r[0], r[1], r[2], r[3]).to_euler(glam::EulerRot::ZXY);
+
+        // Bake scaling into vertices
+        for vertex in &mut vertices {
+            vertex.a_position[0] *= _scale[0];
+            vertex.a_position[1] *= _scale[1];
+            vertex.a_position[2] *= _scale[2];
         }
+
+        let mesh = Arc::new(Mesh::try_new(context, vertices, indices)?);
+
+        let node = SceneNode {
+            position: gltf_to_left_handed_y_up(&translation),
+            rotation: [-rotation.1, -rotation.2, rotation.0],
+            mesh,
+        };
+
+        nodes_by_name.insert(name, Arc::new(node));
     }
     info!("Load time {:?}", now.elapsed());
 
     Ok(Arc::new(SceneFile { nodes_by_name }))
 }
+
+/// Returns the triangle primitives of the nodes of the default scene with their mesh names:
+/// the node name, followed by the primitive index after the first primitive.
+fn named_primitives<'a>(
+    scene: &gltf::Scene<'a>,
+) -> Vec<(String, gltf::Node<'a>, gltf::Primitive<'a>)> {
+    let mut primitives = vec![];
+    for node in scene.nodes() {
+        let Some(name) = node.name() else { continue };
+        let Some(mesh) = node.mesh() else { continue };
+        let triangle_primitives =
+            mesh.primitives().filter(|p| p.mode() == gltf::mesh::Mode::Triangles);
+        for (pi, primitive) in triangle_primitives.enumerate() {
+            let mesh_name = if pi > 0 { format!("{name}.{pi}") } else { name.to_string() };
+            primitives.push((mesh_name, node.clone(), primitive));
+        }
+    }
+    primitives
+}
+
+/// Returns whether each mesh of a mesh collection has indices, without loading the meshes.
+pub fn mesh_indexing(content: &[u8]) -> Result<HashMap<String, bool>> {
+    let gltf = gltf::Gltf::from_slice(content)?;
+    let scene = gltf.default_scene().context("No default scene found")?;
+    Ok(named_primitives(&scene)
+        .into_iter()
+        // `load_mesh_collection` skips primitives without positions
+        .filter(|(_, _, primitive)| primitive.get(&gltf::Semantic::Positions).is_some())
+        .map(|(name, _, primitive)| (name, primitive.indices().is_some()))
+        .collect())
+}
+
+/// Finds a node by name in the hierarchy below `node`. Returns the node and its transform
+/// to world space.
+fn find_node<'a>(
+    node: gltf::Node<'a>,
+    parent_transform: glam::Mat4,
+    node_name: &str,
+) -> Option<(gltf::Node<'a>, glam::Mat4)> {
+    let transform = parent_transform * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+    if node.name() == Some(node_name) {
+        return Some((node, transform));
+    }
+    node.children().find_map(|child| find_node(child, transform, node_name))
+}
+
+/// Loads the vertex positions of the mesh of a node as points, transformed to world space.
+pub fn load_points(content: &[u8], node_name: &str) -> Result<Vec<[f32; 3]>> {
+    let (gltf, buffers, _) = gltf::import_slice(content)?;
+    let scene = gltf.default_scene().context("No default scene found")?;
+    let (node, transform) = scene
+        .nodes()
+        .find_map(|node| find_node(node, glam::Mat4::IDENTITY, node_name))
+        .with_context(|| format!("Could not find node '{node_name}'"))?;
+    let mesh = node.mesh().with_context(|| format!("Node '{node_name}' has no mesh"))?;
+
+    let mut points = vec![];
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        points.extend(positions.map(|p| {
+            let p = transform.transform_point3(glam::Vec3::from(p));
+            gltf_to_left_handed_y_up(&p.to_array())
+        }));
+    }
+    Ok(points)
+}
@@ -3,7 +3,8 @@ use std::hash::Hasher;
 use ahash::AHasher;
 pub mod async_cache;
 pub mod file_cache;
-mod gltf_loader;
+pub mod gltf_loader;
+pub mod project_checker;
 pub mod project_loader;
 pub mod resource_cache;
 pub mod resource_path;
@@ -12,7 +13,7 @@ pub mod shader_cache;
 pub mod shader_compiler;
 
 /// Project file name
-const PROJECT_FILE_NAME: &str = "project.ron";
+pub const PROJECT_FILE_NAME: &str = "project.ron";
 
 /// Folder for charts
 pub const CHARTS_FOLDER: &str = "charts";
@@ -0,0 +1,314 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use dunce::canonicalize;
+use tracing::{error, info, warn};
+
+use crate::engine::{BitangImage, ShaderKind};
+use crate::file::buffer::Buffer;
+use crate::file::chart_file::{buffer_not_found, image_not_found};
+use crate::file::{chart_file, project_file};
+use crate::loader::gltf_loader::mesh_indexing;
+use crate::loader::resource_path::ResourcePath;
+use crate::loader::resource_repository::ron_loader;
+use crate::loader::shader_compiler::{ShaderInterface, SpirvCompilation};
+use crate::loader::{CHARTS_FOLDER, CHART_FILE_NAME, PROJECT_FILE_NAME};
+
+#[derive(Hash, PartialEq, Eq)]
+struct ShaderKey {
+    source_path: ResourcePath,
+    kind: ShaderKind,
+    entry_point: String,
+    features: Vec<String>,
+}
+
+/// Validates the project files and shaders without creating a GPU device.
+///
+/// Every error is collected and reported at the end, so a single run finds all of them.
+pub struct ProjectChecker {
+    root_path: Arc<PathBuf>,
+
+    /// Shaders are only compiled once. Failed compilations store the error message.
+    shader_interfaces: RefCell<HashMap<ShaderKey, Result<Rc<ShaderInterface>, String>>>,
+    errors: RefCell<Vec<anyhow::Error>>,
+    warnings: RefCell<Vec<anyhow::Error>>,
+}
+
+impl ProjectChecker {
+    pub fn try_new(root_path: &str) -> Result<Self> {
+        let pwd = std::env::current_dir()?;
+        let root_path = canonicalize(pwd.join(PathBuf::from(root_path)))
+            .with_context(|| format!("Project folder not found: '{root_path}'"))?;
+        ensure!(root_path.exists());
+        Ok(Self {
+            root_path: Arc::new(root_path),
+            shader_interfaces: RefCell::new(HashMap::new()),
+            errors: RefCell::new(Vec::new()),
+            warnings: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Checks the project and all its charts. Fails if any error was found.
+    pub fn check_project(&self) -> Result<()> {
+        let path = ResourcePath::new(&self.root_path, PathBuf::new(), PROJECT_FILE_NAME);
+        if let Some((project, _)) = self.read_ron_file::<project_file::Project>(&path) {
+            project.check(self, &path);
+        }
+
+        for warning in self.warnings.borrow().iter() {
+            warn!("{warning:#}");
+        }
+        let errors = self.errors.borrow();
+        for err in errors.iter() {
+            error!("{err:#}");
+        }
+        if !errors.is_empty() {
+            bail!(
+                "Found {} errors in project {:?}",
+                errors.len(),
+                self.root_path
+            );
+        }
+        info!("Project check passed.");
+        Ok(())
+    }
+
+    pub fn check_chart(&self, id: &str) {
+        let subdirectory = [CHARTS_FOLDER, id].iter().collect::<PathBuf>();
+        let path = ResourcePath::new(&self.root_path, subdirectory, CHART_FILE_NAME);
+        let Some((chart, source)) = self.read_ron_file::<chart_file::Chart>(&path) else {
+            return;
+        };
+        let chart = match self.resolve_includes(&chart, &path) {
+            Ok(chart) => chart,
+            Err(err) => {
+                self.report(err.context(format!("{path:?}")));
+                return;
+            }
+        };
+        let check_context = ChartCheckContext {
+            checker: self,
+            images_by_id: chart
+                .images
+                .iter()
+                .map(|image| (image.id.clone(), image.load()))
+                .collect(),
+            buffers_by_id: chart
+                .buffers
+                .iter()
+                .map(|buffer| (buffer.id().clone(), buffer.clone()))
+                .collect(),
+            path,
+            source,
+        };
+        chart.check(&check_context);
+    }
+
+    fn resolve_includes(
+        &self,
+        chart: &chart_file::Chart,
+        path: &ResourcePath,
+    ) -> Result<chart_file::Chart> {
+        let template_sources = chart
+            .includes()
+            .map(|include| {
+                let template_path = include.path(path)?.absolute_path()?;
+                std::fs::read_to_string(&template_path)
+                    .with_context(|| format!("Failed to read file: {template_path:?}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        chart.resolve_includes(&template_sources)
+    }
+
+    pub fn report(&self, err: anyhow::Error) {
+        self.errors.borrow_mut().push(err);
+    }
+
+    pub fn report_warning(&self, warning: anyhow::Error) {
+        self.warnings.borrow_mut().push(warning);
+    }
+
+    /// Reads and parses a RON file the same way the loader does.
+    fn read_ron_file<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &ResourcePath,
+    ) -> Option<(T, String)> {
+        let source = match path.absolute_path().and_then(|absolute_path| {
+            std::fs::read_to_string(&absolute_path)
+                .with_context(|| format!("Failed to read file: {path:?}"))
+        }) {
+            Ok(source) => source,
+            Err(err) => {
+                self.report(err);
+                return None;
+            }
+        };
+        match ron_loader().from_str::<T>(&source) {
+            Ok(value) => Some((value, source)),
+            Err(err) => {
+                self.report(anyhow!("{path:?}:{err}"));
+                None
+            }
+        }
+    }
+
+    fn get_shader_interface(
+        &self,
+        source_path: ResourcePath,
+        kind: ShaderKind,
+        entry_point: String,
+        features: Vec<String>,
+    ) -> Result<Rc<ShaderInterface>> {
+        let key = ShaderKey {
+            source_path,
+            kind,
+            entry_point,
+            features,
+        };
+        if let Some(result) = self.shader_interfaces.borrow().get(&key) {
+            return result.clone().map_err(|err| anyhow!(err));
+        }
+        let result = SpirvCompilation::compile(&key.source_path, key.features.clone())
+            .and_then(|compilation| {
+                ShaderInterface::reflect(
+                    kind,
+                    &key.entry_point,
+                    &compilation.spirv,
+                    &compilation.global_usage,
+                )
+            })
+            .map(Rc::new)
+            .map_err(|err| format!("{err:#}"));
+        self.shader_interfaces.borrow_mut().insert(key, result.clone());
+        result.map_err(|err| anyhow!(err))
+    }
+}
+
+/// Chart-level state of the project check, the GPU-less counterpart of `ChartContext`.
+pub struct ChartCheckContext<'a> {
+    checker: &'a ProjectChecker,
+
+    /// The images of the chart. Creating them needs no GPU, their textures are never allocated.
+    pub images_by_id: HashMap<String, Arc<BitangImage>>,
+
+    /// The buffer definitions of the chart, engine buffers can't be created without a GPU
+    pub buffers_by_id: HashMap<String, Buffer>,
+
+    /// Path of the chart file
+    pub path: ResourcePath,
+
+    /// Content of the chart file, used to find the line of an error
+    source: String,
+}
+
+impl ChartCheckContext<'_> {
+    /// Reports an error at the first line of the chart file that mentions `anchor`.
+    pub fn report(&self, anchor: &str, err: anyhow::Error) {
+        self.checker.report(err.context(self.location(anchor)));
+    }
+
+    pub fn report_warning(&self, anchor: &str, warning: anyhow::Error) {
+        self.checker.report_warning(warning.context(self.location(anchor)));
+    }
+
+    fn location(&self, anchor: &str) -> String {
+        let quoted = format!("\"{anchor}\"");
+        match self.source.lines().position(|line| line.contains(&quoted)) {
+            Some(line) => format!("{:?}:{}", self.path, line + 1),
+            None => format!("{:?}", self.path),
+        }
+    }
+
+    /// Returns a chart image. Reports an error and returns None if the chart doesn't define it.
+    pub fn get_image(&self, id: &str) -> Option<&Arc<BitangImage>> {
+        let image = self.images_by_id.get(id);
+        if image.is_none() {
+            self.report(id, image_not_found(id));
+        }
+        image
+    }
+
+    /// Returns a chart buffer definition. Reports an error and returns None if the chart doesn't
+    /// define it.
+    pub fn get_buffer(&self, id: &str) -> Option<&Buffer> {
+        let buffer = self.buffers_by_id.get(id);
+        if buffer.is_none() {
+            self.report(id, buffer_not_found(id));
+        }
+        buffer
+    }
+
+    /// Reports an error if a file referenced by the chart doesn't exist.
+    pub fn check_file(&self, file_name: &str) {
+        let result = self.path.relative_path(file_name).and_then(|path| path.absolute_path());
+        if let Err(err) = result {
+            self.report(
+                file_name,
+                err.context(format!("File not found: '{file_name}'")),
+            );
+        }
+    }
+
+    /// Reads a mesh file and returns whether each of its meshes has indices. Errors are reported
+    /// and None is returned.
+    pub fn get_mesh_indexing(&self, file_name: &str) -> Option<HashMap<String, bool>> {
+        let result = self
+            .path
+            .relative_path(file_name)
+            .and_then(|path| path.absolute_path())
+            .and_then(|path| {
+                std::fs::read(&path).with_context(|| format!("Failed to read file: {path:?}"))
+            })
+            .and_then(|content| mesh_indexing(&content));
+        match result {
+            Ok(meshes) => Some(meshes),
+            Err(err) => {
+                self.report(
+                    file_name,
+                    err.context(format!("Failed to load mesh file '{file_name}'")),
+                );
+                None
+            }
+        }
+    }
+
+    /// Returns a compiled shader without reporting errors.
+    pub fn try_get_shader_interface(
+        &self,
+        source_path: &str,
+        kind: ShaderKind,
+        entry_point: String,
+        features: Vec<String>,
+    ) -> Option<Rc<ShaderInterface>> {
+        let path = self.path.relative_path(source_path).ok()?;
+        self.checker.get_shader_interface(path, kind, entry_point, features).ok()
+    }
+
+    /// Compiles and reflects a shader. Errors are reported and None is returned.
+    pub fn get_shader_interface(
+        &self,
+        source_path: &str,
+        kind: ShaderKind,
+        entry_point: String,
+        features: Vec<String>,
+    ) -> Option<Rc<ShaderInterface>> {
+        let result = self
+            .path
+            .relative_path(source_path)
+            .and_then(|path| self.checker.get_shader_interface(path, kind, entry_point, features));
+        match result {
+            Ok(interface) => Some(interface),
+            Err(err) => {
+                self.report(
+                    source_path,
+                    err.context(format!("Failed to compile shader '{source_path}'")),
+                );
+                None
+            }
+        }
+    }
+}
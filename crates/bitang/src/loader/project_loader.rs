@@ -5,7 +5,7 @@ use std::time::{Duration, Instant};
 
 use anyhow::{ensure, Result};
 use dunce::canonicalize;
-use tracing::{error, info, instrument};
+use tracing::{error, info, instrument, warn};
 
 use crate::engine::{GpuContext, Project};
 use crate::loader::file_cache::{FileCache, FileChangeHandler};
@@ -19,7 +19,12 @@ pub struct ProjectLoader {
     // TODO: remove if not needed
     pub _root_path: Arc<PathBuf>,
     pub resource_repository: Rc<ResourceRepository>,
+
+    /// The last successfully loaded project. It's kept alive when a reload fails.
     cached_root: Option<Rc<Project>>,
+
+    /// The error of the last load attempt, cleared when a load succeeds.
+    load_error: Option<String>,
     last_load_time: Instant,
     file_change_handler: FileChangeHandler,
     async_runtime: tokio::runtime::Runtime,
@@ -38,6 +43,7 @@ impl ProjectLoader {
             _root_path: root_path,
             resource_repository: Rc::new(ResourceRepository::try_new(Arc::clone(&file_cache))?),
             cached_root: None,
+            load_error: None,
             last_load_time: Instant::now() - LOAD_RETRY_INTERVAL,
             file_change_handler,
             async_runtime,
@@ -45,8 +51,10 @@ impl ProjectLoader {
     }
 
     fn run_project_loader(&mut self, context: &Arc<GpuContext>) -> Result<Project> {
+        let previous_project = self.cached_root.clone();
         self.async_runtime.block_on(async {
-            let result = self.resource_repository.load_project(context).await;
+            let result =
+                self.resource_repository.load_project(context, previous_project.as_deref()).await;
             self.file_change_handler.update_watchers().await;
             result
         })
@@ -55,7 +63,7 @@ impl ProjectLoader {
     #[instrument(skip_all, name = "load")]
     pub fn get_or_load_project(&mut self, context: &Arc<GpuContext>) -> Option<Rc<Project>> {
         let changed_files = self.file_change_handler.handle_file_changes();
-        let needs_retry = self.cached_root.is_none()
+        let needs_retry = self.load_error.is_some()
             && self.file_change_handler.has_missing_files()
             && self.last_load_time.elapsed() > LOAD_RETRY_INTERVAL;
         if changed_files.is_some() || needs_retry {
@@ -65,18 +73,47 @@ impl ProjectLoader {
                 Ok(project) => {
                     info!("Project length: {} seconds", project.length);
                     info!("Load time {:?}", now.elapsed());
+                    let chart_errors = project.load_errors();
+                    if chart_errors.is_empty() {
+                        self.load_error = None;
+                    } else {
+                        if changed_files.is_some() {
+                            for err in &chart_errors {
+                                error!("{err}");
+                            }
+                        }
+                        self.resource_repository.display_load_errors();
+                        self.load_error = Some(chart_errors.join("\n"));
+                    }
                     self.cached_root = Some(Rc::new(project));
                 }
                 Err(err) => {
                     if changed_files.is_some() {
                         error!("Failed to load project: {:?}", err);
+                        if self.cached_root.is_some() {
+                            warn!("Keeping the last successfully loaded project.");
+                        }
                     }
                     self.resource_repository.display_load_errors();
-                    self.cached_root = None;
+
+                    // The failed load cycle reset the usage counts of the controls the
+                    // last good project is still bound to.
+                    if let Some(project) = &self.cached_root {
+                        project.controls.restore_component_usage_counts();
+                        for chart in &project.charts {
+                            chart.controls.restore_component_usage_counts();
+                        }
+                    }
+                    self.load_error = Some(format!("{err:#}"));
                 }
             };
             self.last_load_time = Instant::now();
         }
         self.cached_root.clone()
     }
+
+    /// Returns the error of the last load attempt if it failed.
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
 }
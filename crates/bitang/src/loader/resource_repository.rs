@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -40,6 +41,12 @@ pub struct ResourceRepository {
     project_file_cache: Arc<ResourceCache<project_file::Project>>,
     pub shader_cache: ShaderCache,
     pub control_repository: Rc<ControlRepository>,
+
+    /// The files each successfully loaded chart depends on
+    chart_dependencies: RefCell<HashMap<String, HashSet<ResourcePath>>>,
+
+    /// Files changed since the last load cycle. None if every file should be considered changed.
+    changed_files: RefCell<Option<Vec<ResourcePath>>>,
 }
 
 impl ResourceRepository {
@@ -54,6 +61,8 @@ impl ResourceRepository {
             project_file_cache: Arc::new(ResourceCache::new(&file_cache, load_project_file)),
             file_cache,
             control_repository: Rc::new(control_repository),
+            chart_dependencies: RefCell::new(HashMap::new()),
+            changed_files: RefCell::new(None),
         })
     }
 
@@ -73,6 +82,32 @@ impl ResourceRepository {
         self.project_file_cache.start_load_cycle();
         self.control_repository.reset_component_usage_counts();
         self.shader_cache.reset_load_cycle(changed_files);
+        *self.changed_files.borrow_mut() = changed_files.cloned();
+    }
+
+    /// Returns true if the chart has to be rebuilt because it was never loaded successfully
+    /// or one of its files changed.
+    pub fn is_chart_changed(&self, id: &str) -> bool {
+        let chart_dependencies = self.chart_dependencies.borrow();
+        let Some(dependencies) = chart_dependencies.get(id) else {
+            return true;
+        };
+        match self.changed_files.borrow().as_ref() {
+            Some(changed_files) => changed_files.iter().any(|path| dependencies.contains(path)),
+            None => true,
+        }
+    }
+
+    pub fn set_chart_dependencies(&self, id: &str, dependencies: HashSet<ResourcePath>) {
+        self.chart_dependencies.borrow_mut().insert(id.to_string(), dependencies);
+    }
+
+    /// Keeps the file watchers of a chart that is reused without reloading.
+    pub async fn keep_chart_dependencies(&self, id: &str) {
+        let dependencies = self.chart_dependencies.borrow().get(id).cloned().unwrap_or_default();
+        for path in &dependencies {
+            self.file_cache.keep_watching(path).await;
+        }
     }
 
     #[instrument(skip(self, context))]
@@ -109,24 +144,45 @@ impl ResourceRepository {
     }
 
     #[instrument(skip(self, context))]
+    /// Loads a chart. If `base_id` is set, it loads an instance of that chart under `id`.
     pub async fn load_chart(
         self: &Rc<Self>,
         id: &str,
+        base_id: Option<&str>,
         context: &Arc<GpuContext>,
     ) -> Result<Rc<Chart>> {
-        let subdirectory = [CHARTS_FOLDER, id].iter().collect::<PathBuf>();
+        self.chart_dependencies.borrow_mut().remove(id);
+        let directory = base_id.unwrap_or(id);
+        let subdirectory = [CHARTS_FOLDER, directory].iter().collect::<PathBuf>();
         let path = ResourcePath::new(&self.root_path, subdirectory, CHART_FILE_NAME);
         let chart = self.chart_file_cache.load(context, &path).await?;
         chart
-            .load(id, context, self, &path)
+            .load(id, base_id, context, self, &path)
             .await
             .with_context(|| anyhow!("Failed to load chart '{id}'"))
     }
 
-    pub async fn load_project(self: &Rc<Self>, context: &Arc<GpuContext>) -> Result<Project> {
+    /// Reads a text file and watches it for changes.
+    pub async fn read_text_file(&self, path: &ResourcePath) -> Result<String> {
+        let entry = self.file_cache.get(path).await?;
+        String::from_utf8(entry.content.clone())
+            .with_context(|| format!("File is not valid UTF-8: {path:?}"))
+    }
+
+    /// Reads a binary file and watches it for changes.
+    pub async fn read_binary_file(&self, path: &ResourcePath) -> Result<Vec<u8>> {
+        let entry = self.file_cache.get(path).await?;
+        Ok(entry.content.clone())
+    }
+
+    pub async fn load_project(
+        self: &Rc<Self>,
+        context: &Arc<GpuContext>,
+        previous_project: Option<&Project>,
+    ) -> Result<Project> {
         let path = ResourcePath::new(&self.root_path, PathBuf::new(), PROJECT_FILE_NAME);
         let project = self.project_file_cache.load(context, &path).await?;
-        project.load(context, self).await
+        project.load(context, self, previous_project).await
     }
 }
 
@@ -185,7 +241,7 @@ fn load_texture(
     image
 }
 
-fn ron_loader() -> ron::Options {
+pub fn ron_loader() -> ron::Options {
     ron::Options::default().with_default_extension(
         ron::extensions::Extensions::IMPLICIT_SOME
             | ron::extensions::Extensions::UNWRAP_NEWTYPES
@@ -118,12 +118,38 @@ impl ShaderCache {
         self.load_cycle_shader_cache.get(format!("shader:{key:?}"), key, shader_load_func).await
     }
 
+    /// Returns a shader compiled in the current load cycle.
+    pub fn get_loaded(
+        &self,
+        source_path: ResourcePath,
+        kind: ShaderKind,
+        entry_point: String,
+        features: Vec<String>,
+    ) -> Option<Arc<ShaderArtifact>> {
+        self.load_cycle_shader_cache.get_loaded(&ShaderCacheKey {
+            source_path,
+            kind,
+            entry_point,
+            features,
+        })
+    }
+
     pub fn display_load_errors(&self) {
         self.load_cycle_shader_cache.display_load_errors();
     }
 
-    pub fn reset_load_cycle(&self, _changed_files: Option<&Vec<ResourcePath>>) {
-        self.load_cycle_shader_cache.clear();
+    /// Drops the shaders that depend on a changed file.
+    ///
+    /// If `changed_files` is None, every shader is dropped.
+    pub fn reset_load_cycle(&self, changed_files: Option<&Vec<ResourcePath>>) {
+        let Some(changed_files) = changed_files else {
+            self.load_cycle_shader_cache.clear();
+            return;
+        };
+        self.load_cycle_shader_cache.retain_loaded(|key, shader_artifact| {
+            !changed_files.contains(&key.source_path)
+                && !shader_artifact.include_chain.iter().any(|path| changed_files.contains(path))
+        });
     }
 
     async fn load_shader(
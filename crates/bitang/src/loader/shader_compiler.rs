@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::mem::size_of;
 use std::path::{Path, PathBuf};
@@ -11,12 +12,14 @@ use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 use itertools::Itertools;
 use naga::valid::Capabilities;
+use spirq::constant::ConstantValue;
+use spirq::spirv;
 use spirq::ty::ScalarType::Float;
-use spirq::ty::{DescriptorType, SpirvType, Type, VectorType};
+use spirq::ty::{AccessType, DescriptorType, ScalarType, SpirvType, Type, VectorType};
 use spirq::var::Variable;
 use spirq::ReflectConfig;
 use tracing::{debug, error, info, instrument, trace, warn};
-use wesl::{Feature, HashMangler, Wesl};
+use wesl::{CompileResult, Feature, HashMangler, ModulePath, SourceMap, Wesl};
 use wgpu::{ShaderModule, ShaderModuleDescriptor};
 
 use crate::engine::{GlobalType, GlobalUniformMapping, GpuContext, ShaderKind};
@@ -38,15 +41,59 @@ impl ShaderCompilation {
         entry_point: &str,
         features: Vec<String>,
     ) -> Result<Self> {
+        let SpirvCompilation {
+            spirv,
+            global_usage,
+            include_chain,
+        } = SpirvCompilation::compile(path, features)?;
+
+        let shader_artifact = ShaderArtifact::from_spirv_binary(
+            context,
+            kind,
+            entry_point,
+            &spirv,
+            &global_usage,
+            include_chain.clone(),
+        )?;
+
+        Ok(Self {
+            shader_artifact,
+            include_chain,
+        })
+    }
+}
+
+/// The global variables an entry point uses, by name
+#[derive(Default)]
+pub struct GlobalUsage {
+    pub used: HashSet<String>,
+
+    /// The (texture, sampler) pairs the entry point samples with
+    pub sampling_pairs: HashSet<(String, String)>,
+}
+
+/// A validated SPIRV binary. Compiling it doesn't need a GPU.
+pub struct SpirvCompilation {
+    pub spirv: Vec<u8>,
+
+    /// How each entry point uses the global variables, by entry point name
+    pub global_usage: HashMap<String, GlobalUsage>,
+
+    pub include_chain: Vec<ResourcePath>,
+}
+
+impl SpirvCompilation {
+    pub fn compile(path: &ResourcePath, features: Vec<String>) -> Result<Self> {
         let now = std::time::Instant::now();
 
-        let compile_result = {
+        let (compile_result, module_path) = {
             let mut wesl = Wesl::new(
                 path.root_path
                     .to_str()
                     .with_context(|| format!("Invalid root path '{:?}'", path.root_path))?,
             );
             wesl.set_custom_mangler(HashMangler::default());
+            wesl.use_sourcemap(true);
             let mut parent_module = path
                 .subdirectory
                 .to_str()
@@ -60,11 +107,12 @@ impl ShaderCompilation {
                 .with_context(|| format!("File name has no stem: '{:?}'", path.file_name))?
                 .to_str()
                 .with_context(|| format!("Invalid file name: '{:?}'", path.file_name))?;
-            let module_path = format!("package::{}{}", parent_module, base_name);
+            let module_path: ModulePath =
+                format!("package::{}{}", parent_module, base_name).parse()?;
             for feature in &features {
                 wesl.set_feature(feature, Feature::Enable);
             }
-            wesl.compile(&module_path.parse()?)?
+            (wesl.compile(&module_path)?, module_path)
         };
 
         let include_chain = compile_result
@@ -72,16 +120,13 @@ impl ShaderCompilation {
             .iter()
             .map(|module| {
                 ensure!(module.origin.is_absolute());
-                let path_buf = path
-                    .root_path
-                    .join(module.components.iter().collect::<PathBuf>().with_extension("wgsl"));
-                ResourcePath::from_pathbuf(&path.root_path, &path_buf)
+                module_resource_path(path, module)
             })
             .collect::<Result<Vec<_>>>()?;
 
         let source = compile_result.to_string();
 
-        let spirv = {
+        let (spirv, global_usage) = {
             // TODO: report code spans on the top level, not here
             let mut frontend = naga::front::wgsl::Frontend::new();
             let res = match frontend.parse(&source) {
@@ -109,8 +154,21 @@ impl ShaderCompilation {
                         &diagnostic,
                     )?;
 
+                    let location = err
+                        .labels()
+                        .find_map(|(span, _)| span.to_range())
+                        .and_then(|range| {
+                            find_source_location(
+                                path,
+                                &compile_result,
+                                &module_path,
+                                &source,
+                                range.start,
+                            )
+                        })
+                        .unwrap_or_else(|| format!("{path:?}"));
                     bail!(
-                        "Failed to parse shader source file '{path:?}', error: {}",
+                        "Failed to parse shader source file '{location}', error: {}",
                         err.message()
                     );
                 }
@@ -136,7 +194,7 @@ impl ShaderCompilation {
                 Ok(res) => res,
                 Err(err) => {
                     let mut files = SimpleFiles::new();
-                    let file_id = files.add(path.to_pwd_relative_path().unwrap(), source);
+                    let file_id = files.add(path.to_pwd_relative_path().unwrap(), &source);
 
                     let labels = err
                         .spans()
@@ -157,7 +215,20 @@ impl ShaderCompilation {
                         &diagnostic,
                     )?;
 
-                    bail!("Failed to parse shader source file '{path:?}', error: {err:?}",);
+                    let location = err
+                        .spans()
+                        .find_map(|(span, _)| span.to_range())
+                        .and_then(|range| {
+                            find_source_location(
+                                path,
+                                &compile_result,
+                                &module_path,
+                                &source,
+                                range.start,
+                            )
+                        })
+                        .unwrap_or_else(|| format!("{path:?}"));
+                    bail!("Failed to parse shader source file '{location}', error: {err:?}",);
                 }
             };
 
@@ -167,20 +238,118 @@ impl ShaderCompilation {
             let spirv_u32 = naga::back::spv::write_vec(&res, &module_info, &spv_options, None)?;
             let spirv_u8 =
                 spirv_u32.iter().flat_map(|&w| w.to_le_bytes().to_vec()).collect::<Vec<u8>>();
-            spirv_u8
+
+            // SPIRV reflection can't tell which resources an entry point accesses
+            let global_name = |handle: naga::Handle<naga::GlobalVariable>| {
+                res.global_variables[handle].name.clone()
+            };
+            let global_usage = res
+                .entry_points
+                .iter()
+                .enumerate()
+                .map(|(index, entry_point)| {
+                    let info = module_info.get_entry_point(index);
+                    let used = res
+                        .global_variables
+                        .iter()
+                        .filter(|(handle, _)| !info[*handle].is_empty())
+                        .filter_map(|(_, var)| var.name.clone())
+                        .collect();
+                    let sampling_pairs = info
+                        .sampling_set
+                        .iter()
+                        .filter_map(|key| {
+                            Some((global_name(key.image)?, global_name(key.sampler)?))
+                        })
+                        .collect();
+                    let usage = GlobalUsage {
+                        used,
+                        sampling_pairs,
+                    };
+                    (entry_point.name.clone(), usage)
+                })
+                .collect();
+            (spirv_u8, global_usage)
         };
         info!("compiled in {:?}.", now.elapsed());
 
-        let shader_artifact =
-            ShaderArtifact::from_spirv_binary(context, kind, entry_point, &spirv)?;
-
         Ok(Self {
-            shader_artifact,
+            spirv,
+            global_usage,
             include_chain,
         })
     }
 }
 
+/// Parses and validates a WGSL source that is passed directly to wgpu.
+///
+/// wgpu panics on invalid shaders, so this has to succeed before creating a shader module.
+pub fn validate_wgsl(source: &str) -> Result<()> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|err| anyhow::anyhow!(err.emit_to_string(source)))?;
+    let mut validator =
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), Capabilities::all());
+    validator.validate(&module).map_err(|err| anyhow::anyhow!(err.emit_to_string(source)))?;
+    Ok(())
+}
+
+fn module_resource_path(path: &ResourcePath, module: &ModulePath) -> Result<ResourcePath> {
+    let path_buf =
+        path.root_path.join(module.components.iter().collect::<PathBuf>().with_extension("wgsl"));
+    ResourcePath::from_pathbuf(&path.root_path, &path_buf)
+}
+
+/// Finds the original file and line of a position in the compiled WGSL source.
+///
+/// Wesl reformats the code, so this points to the top level declaration containing
+/// the position, not the exact line.
+fn find_source_location(
+    path: &ResourcePath,
+    compile_result: &CompileResult,
+    root_module: &ModulePath,
+    source: &str,
+    offset: usize,
+) -> Option<String> {
+    let compiled_name = source.get(..offset)?.lines().rev().find_map(declaration_name)?;
+    let (module, name) = match compile_result.sourcemap.get_decl(compiled_name) {
+        Some((module, name)) => (module, name),
+        // Declarations of the root module are not mangled
+        None => (root_module, compiled_name),
+    };
+    let original_source = compile_result.sourcemap.get_source(module)?;
+    let line = original_source.lines().position(|line| declaration_name(line) == Some(name))?;
+    let file = module_resource_path(path, module).ok()?.to_pwd_relative_path().ok()?;
+    Some(format!("{file}:{}", line + 1))
+}
+
+/// Returns the name of a top level WGSL declaration starting in this line.
+fn declaration_name(line: &str) -> Option<&str> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    // Skip attributes, eg. "@compute @workgroup_size(64, 1, 1)"
+    let mut rest = line;
+    while let Some(attribute) = rest.strip_prefix('@') {
+        let name_end =
+            attribute.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(attribute.len());
+        rest = &attribute[name_end..];
+        if rest.starts_with('(') {
+            rest = &rest[rest.find(')')? + 1..];
+        }
+        rest = rest.trim_start();
+    }
+
+    let (keyword, rest) = rest.split_once(char::is_whitespace)?;
+    let keyword = keyword.split('<').next()?;
+    if !["fn", "struct", "var", "const", "override", "alias"].contains(&keyword) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let name_end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+    (name_end > 0).then(|| &rest[..name_end])
+}
+
 /// A descriptor binding point for a named resource
 #[derive(Debug)]
 pub struct NamedResourceBinding {
@@ -188,6 +357,42 @@ pub struct NamedResourceBinding {
     pub binding: u32,
 }
 
+/// A descriptor binding point for a sampled texture
+#[derive(Debug)]
+pub struct TextureBinding {
+    pub name: String,
+    pub binding: u32,
+    pub view_dimension: wgpu::TextureViewDimension,
+
+    /// The sample type declared in the shader. Float textures are reported as filterable.
+    pub sample_type: wgpu::TextureSampleType,
+
+    /// The samplers the entry point samples the texture with
+    pub samplers: Vec<String>,
+}
+
+/// A descriptor binding point for a storage texture
+#[derive(Debug)]
+pub struct StorageTextureBinding {
+    pub name: String,
+    pub binding: u32,
+    pub access: wgpu::StorageTextureAccess,
+
+    /// `texture_storage_2d` or `texture_storage_2d_array`
+    pub view_dimension: wgpu::TextureViewDimension,
+}
+
+/// A descriptor binding point for a storage buffer
+#[derive(Debug)]
+pub struct StorageBufferBinding {
+    pub name: String,
+    pub binding: u32,
+    pub read_only: bool,
+
+    /// False if the entry point doesn't access the buffer, so it can be left unbound
+    pub is_used: bool,
+}
+
 /// Metadata of a local uniform extracted from the compiled shader
 #[derive(Debug)]
 pub struct ShaderCompilationLocalUniform {
@@ -200,13 +405,20 @@ pub struct ShaderCompilationLocalUniform {
 pub struct ShaderArtifact {
     pub module: ShaderModule,
     pub samplers: Vec<NamedResourceBinding>,
-    pub textures: Vec<NamedResourceBinding>,
-    pub buffers: Vec<NamedResourceBinding>,
+    pub textures: Vec<TextureBinding>,
+    pub storage_textures: Vec<StorageTextureBinding>,
+    pub buffers: Vec<StorageBufferBinding>,
     pub global_uniform_bindings: Vec<GlobalUniformMapping>,
     pub local_uniform_bindings: Vec<ShaderCompilationLocalUniform>,
 
     /// The size of the uniform buffer in 32-bit floats
     pub uniform_buffer_byte_size: usize,
+
+    /// The `@workgroup_size` of a compute shader, [1, 1, 1] for other kinds
+    pub workgroup_size: [u32; 3],
+
+    /// The source files the shader was compiled from
+    pub include_chain: Vec<ResourcePath>,
 }
 
 impl ShaderArtifact {
@@ -215,6 +427,64 @@ impl ShaderArtifact {
         kind: ShaderKind,
         entry_point: &str,
         spirv_binary: &[u8],
+        global_usage: &HashMap<String, GlobalUsage>,
+        include_chain: Vec<ResourcePath>,
+    ) -> Result<Self> {
+        let ShaderInterface {
+            samplers,
+            textures,
+            storage_textures,
+            buffers,
+            global_uniform_bindings,
+            local_uniform_bindings,
+            uniform_buffer_byte_size,
+            workgroup_size,
+        } = ShaderInterface::reflect(kind, entry_point, spirv_binary, global_usage)?;
+
+        let source = wgpu::util::make_spirv(spirv_binary);
+        let module = context.device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: source,
+        });
+
+        Ok(ShaderArtifact {
+            module,
+            samplers,
+            textures,
+            storage_textures,
+            buffers,
+            global_uniform_bindings,
+            local_uniform_bindings,
+            uniform_buffer_byte_size,
+            workgroup_size,
+            include_chain,
+        })
+    }
+}
+
+/// The resources and uniforms of a shader, extracted from its SPIRV binary.
+#[derive(Debug)]
+pub struct ShaderInterface {
+    pub samplers: Vec<NamedResourceBinding>,
+    pub textures: Vec<TextureBinding>,
+    pub storage_textures: Vec<StorageTextureBinding>,
+    pub buffers: Vec<StorageBufferBinding>,
+    pub global_uniform_bindings: Vec<GlobalUniformMapping>,
+    pub local_uniform_bindings: Vec<ShaderCompilationLocalUniform>,
+
+    /// The size of the uniform buffer in 32-bit floats
+    pub uniform_buffer_byte_size: usize,
+
+    /// The `@workgroup_size` of a compute shader, [1, 1, 1] for other kinds
+    pub workgroup_size: [u32; 3],
+}
+
+impl ShaderInterface {
+    pub fn reflect(
+        kind: ShaderKind,
+        entry_point: &str,
+        spirv_binary: &[u8],
+        global_usage: &HashMap<String, GlobalUsage>,
     ) -> Result<Self> {
         // Extract metadata from SPIRV
         let entry_points = ReflectConfig::new()
@@ -229,11 +499,9 @@ impl ShaderArtifact {
             .find(|ep| ep.name == entry_point)
             .with_context(|| format!("Failed to find entry point '{entry_point}'"))?;
 
-        let source = wgpu::util::make_spirv(spirv_binary);
-        let module = context.device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: source,
-        });
+        let global_usage = global_usage
+            .get(entry_point)
+            .with_context(|| format!("Failed to find entry point '{entry_point}'"))?;
 
         let descriptor_set_index = match kind {
             ShaderKind::Vertex => 0,
@@ -244,6 +512,7 @@ impl ShaderArtifact {
 
         let mut samplers = Vec::new();
         let mut textures = Vec::new();
+        let mut storage_textures = Vec::new();
         let mut buffers = Vec::new();
         let mut global_uniform_bindings = Vec::new();
         let mut local_uniform_bindings = Vec::new();
@@ -280,17 +549,88 @@ impl ShaderArtifact {
                             });
                         }
                         DescriptorType::SampledImage() => {
-                            textures.push(NamedResourceBinding {
-                                name: name.clone().with_context(|| {
-                                    format!("Failed to get name for texture at binding={binding}")
-                                })?,
+                            let name = name.clone().with_context(|| {
+                                format!("Failed to get name for texture at binding={binding}")
+                            })?;
+                            let Type::SampledImage(image_type) = ty else {
+                                bail!("Texture '{name}' has unexpected type {ty:?}");
+                            };
+                            let view_dimension = match (image_type.dim, image_type.is_array) {
+                                (spirv::Dim::Dim2D, false) => wgpu::TextureViewDimension::D2,
+                                (spirv::Dim::Dim2D, true) => wgpu::TextureViewDimension::D2Array,
+                                (spirv::Dim::DimCube, false) => wgpu::TextureViewDimension::Cube,
+                                (spirv::Dim::DimCube, true) => {
+                                    wgpu::TextureViewDimension::CubeArray
+                                }
+                                (dim, _) => {
+                                    bail!("Texture '{name}' has unsupported dimension {dim:?}")
+                                }
+                            };
+                            let sample_type = match image_type.scalar_ty {
+                                _ if image_type.is_depth == Some(true) => {
+                                    wgpu::TextureSampleType::Depth
+                                }
+                                ScalarType::Integer {
+                                    is_signed: false, ..
+                                } => wgpu::TextureSampleType::Uint,
+                                ScalarType::Integer {
+                                    is_signed: true, ..
+                                } => wgpu::TextureSampleType::Sint,
+                                _ => wgpu::TextureSampleType::Float { filterable: true },
+                            };
+                            let samplers = global_usage
+                                .sampling_pairs
+                                .iter()
+                                .filter(|(texture, _)| *texture == name)
+                                .map(|(_, sampler)| sampler.clone())
+                                .collect();
+                            textures.push(TextureBinding {
+                                name,
                                 binding,
+                                view_dimension,
+                                sample_type,
+                                samplers,
                             });
                         }
-                        DescriptorType::StorageBuffer(_) => {
-                            buffers.push(NamedResourceBinding {
-                                name: name.clone().with_context(|| format!("Failed to get name for storage buffer at binding={binding}"))?,
+                        DescriptorType::StorageImage(access) => {
+                            let name = name.clone().with_context(|| {
+                                format!(
+                                    "Failed to get name for storage texture at binding={binding}"
+                                )
+                            })?;
+                            let Type::StorageImage(image_type) = ty else {
+                                bail!("Storage texture '{name}' has unexpected type {ty:?}");
+                            };
+                            let view_dimension = match (image_type.dim, image_type.is_array) {
+                                (spirv::Dim::Dim2D, false) => wgpu::TextureViewDimension::D2,
+                                (spirv::Dim::Dim2D, true) => wgpu::TextureViewDimension::D2Array,
+                                (dim, _) => bail!(
+                                    "Storage texture '{name}' has unsupported dimension {dim:?}"
+                                ),
+                            };
+                            let access = match access {
+                                AccessType::ReadOnly => wgpu::StorageTextureAccess::ReadOnly,
+                                AccessType::WriteOnly => wgpu::StorageTextureAccess::WriteOnly,
+                                AccessType::ReadWrite => wgpu::StorageTextureAccess::ReadWrite,
+                            };
+                            storage_textures.push(StorageTextureBinding {
+                                name,
                                 binding,
+                                access,
+                                view_dimension,
+                            });
+                        }
+                        DescriptorType::StorageBuffer(access) => {
+                            let name = name.clone().with_context(|| {
+                                format!(
+                                    "Failed to get name for storage buffer at binding={binding}"
+                                )
+                            })?;
+                            buffers.push(StorageBufferBinding {
+                                is_used: global_usage.used.contains(&name),
+                                name,
+                                binding,
+                                read_only: matches!(access, AccessType::ReadOnly),
                             });
                         }
                         DescriptorType::UniformBuffer() => {
@@ -373,6 +713,27 @@ impl ShaderArtifact {
             }
         }
 
+        let workgroup_size = match spv_entry_point
+            .exec_modes
+            .iter()
+            .find(|exec_mode| exec_mode.exec_mode == spirv::ExecutionMode::LocalSize)
+        {
+            Some(local_size) => {
+                let dimensions = local_size
+                    .operands
+                    .iter()
+                    .map(|operand| match operand.value {
+                        ConstantValue::U32(size) => Ok(size),
+                        _ => bail!("Unsupported workgroup size {:?}", operand.value),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                dimensions.try_into().map_err(|dimensions| {
+                    anyhow::anyhow!("Invalid workgroup size {dimensions:?}")
+                })?
+            }
+            None => [1, 1, 1],
+        };
+
         debug!(
             "Found {} samplers and {} buffers, SPIRV size: {}.",
             samplers.len(),
@@ -380,14 +741,15 @@ impl ShaderArtifact {
             spirv_binary.len()
         );
 
-        let result = ShaderArtifact {
-            module,
+        let result = ShaderInterface {
             samplers,
             textures,
+            storage_textures,
             buffers,
             local_uniform_bindings,
             global_uniform_bindings,
             uniform_buffer_byte_size,
+            workgroup_size,
         };
 
         trace!(
@@ -1,7 +1,10 @@
 use std::sync::OnceLock;
 
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::tool::cli::{Cli, Command, ProjectArgs, RenderArgs, WindowArgs};
+
 const APP_CONFIG_FILE: &str = "config.ron";
 static APP_CONFIG: OnceLock<AppConfig> = OnceLock::new();
 
@@ -9,6 +12,14 @@ fn default_root_folder() -> String {
     "demo".to_string()
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunMode {
+    #[default]
+    Window,
+    Render,
+    Check,
+}
+
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct AppConfig {
     #[serde(default = "default_root_folder")]
@@ -16,6 +27,48 @@ pub struct AppConfig {
 
     #[serde(default)]
     pub start_in_demo_mode: bool,
+
+    /// Start time of the editor or the demo in seconds
+    #[serde(default)]
+    pub start_time: f32,
+
+    #[serde(default)]
+    pub render: RenderConfig,
+
+    /// Set by the command line only
+    #[serde(skip)]
+    pub run_mode: RunMode,
+}
+
+/// Settings of the offline renderer
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RenderConfig {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+
+    /// Start time in seconds
+    pub from: f32,
+
+    /// End time in seconds, the end of the project if not set
+    pub to: Option<f32>,
+
+    /// Folder where the frames are saved
+    pub out: String,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            width: 3840,
+            height: 2160,
+            fps: 60,
+            from: 0.0,
+            to: None,
+            out: "framedump".to_string(),
+        }
+    }
 }
 
 impl AppConfig {
@@ -23,6 +76,83 @@ impl AppConfig {
         APP_CONFIG.get_or_init(|| Self::load())
     }
 
+    /// Loads `config.ron` and applies the command line overrides.
+    ///
+    /// Must be called before the first `get`.
+    pub fn init(cli: Cli) -> Result<&'static AppConfig> {
+        let mut config = Self::load();
+        config.apply_cli(cli)?;
+        if APP_CONFIG.set(config).is_err() {
+            bail!("App config is already initialized");
+        }
+        Ok(Self::get())
+    }
+
+    fn apply_cli(&mut self, cli: Cli) -> Result<()> {
+        let Some(command) = cli.command else {
+            return Ok(());
+        };
+        match command {
+            Command::Edit(args) => {
+                self.apply_window_args(args);
+                self.start_in_demo_mode = false;
+            }
+            Command::Play(args) => {
+                self.apply_window_args(args);
+                self.start_in_demo_mode = true;
+            }
+            Command::Render(args) => {
+                self.apply_render_args(args)?;
+                self.run_mode = RunMode::Render;
+            }
+            Command::Check(args) => {
+                self.apply_project_args(args);
+                self.run_mode = RunMode::Check;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_project_args(&mut self, args: ProjectArgs) {
+        if let Some(project) = args.project {
+            self.root_folder = project;
+        }
+    }
+
+    fn apply_window_args(&mut self, args: WindowArgs) {
+        self.apply_project_args(args.project);
+        if let Some(start) = args.start {
+            self.start_time = start;
+        }
+        self.run_mode = RunMode::Window;
+    }
+
+    fn apply_render_args(&mut self, args: RenderArgs) -> Result<()> {
+        self.apply_project_args(args.project);
+        let render = &mut self.render;
+        render.width = args.width.unwrap_or(render.width);
+        render.height = args.height.unwrap_or(render.height);
+        render.fps = args.fps.unwrap_or(render.fps);
+        render.from = args.from.unwrap_or(render.from);
+        render.to = args.to.or(render.to);
+        if let Some(out) = args.out {
+            render.out = out;
+        }
+
+        if render.width == 0 || render.height == 0 {
+            bail!("Invalid render size: {}x{}", render.width, render.height);
+        }
+        if render.fps == 0 {
+            bail!("FPS must be positive");
+        }
+        if let Some(to) = render.to {
+            if to <= render.from {
+                bail!("End time {to} must be after start time {}", render.from);
+            }
+        }
+        Ok(())
+    }
+
     fn load() -> Self {
         if let Ok(config_str) = std::fs::read_to_string(APP_CONFIG_FILE) {
             match ron::de::from_str(&config_str) {
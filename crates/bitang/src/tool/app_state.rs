@@ -5,6 +5,9 @@ use crate::tool::timer::Timer;
 
 pub struct AppState {
     pub project: Option<Rc<Project>>,
+
+    /// Set if the last reload failed. The previous project is still displayed.
+    pub load_error: Option<String>,
     pub selected_control_id: ControlId,
     pub cursor_time: f32,
     cursor: Timer,
@@ -19,6 +22,7 @@ impl AppState {
     ) -> AppState {
         AppState {
             project,
+            load_error: None,
             selected_control_id: ControlId::default(),
             cursor: Timer::new(),
             cursor_time: 0.0,
@@ -43,7 +47,12 @@ impl AppState {
         None
     }
 
+    /// Returns the controls of the selected chart, or the project controls if the project
+    /// node is selected.
     pub fn get_current_chart_control_set(&self) -> Option<Rc<ControlSet>> {
+        if self.selected_control_id.parts.is_empty() {
+            return self.project.as_ref().map(|project| project.controls.clone());
+        }
         self.get_chart().map(|chart| chart.controls.clone())
     }
 
@@ -52,7 +61,14 @@ impl AppState {
     /// If a chart is selected, it returns the time relative to the start of the chart
     /// so music can be played properly.
     pub fn get_project_relative_time(&self) -> f32 {
-        let cursor_time = self.cursor.now();
+        self.cursor.now() + self.get_chart_start_time()
+    }
+
+    /// Returns the project time where the time of the selected chart is zero, or zero if no
+    /// chart is selected. This is the start of the cut minus its offset.
+    ///
+    /// The cursor time plus this is the project time.
+    pub fn get_chart_start_time(&self) -> f32 {
         if let Some(part) = self.selected_control_id.parts.first() {
             if let Some(project) = &self.project {
                 if part.part_type == ControlIdPartType::Chart {
@@ -60,14 +76,14 @@ impl AppState {
                         .cuts
                         .iter()
                         .find(|cut| cut.chart.id == part.name)
-                        .map(|cut| cut.start_time)
+                        .map(|cut| cut.start_time - cut.offset)
                     {
-                        return time + cursor_time;
+                        return time;
                     }
                 }
             }
         }
-        cursor_time
+        0.0
     }
 
     pub fn start(&mut self) {
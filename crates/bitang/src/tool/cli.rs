@@ -0,0 +1,72 @@
+use clap::{Args, Parser, Subcommand};
+
+/// Bitang demo tool
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    /// Runs the editor if no command is given, or the demo if `start_in_demo_mode` is set
+    /// in `config.ron`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Opens the project in the editor
+    Edit(WindowArgs),
+
+    /// Plays the project fullscreen and exits at the end
+    Play(WindowArgs),
+
+    /// Renders the project offscreen and saves every frame as a PNG file
+    Render(RenderArgs),
+
+    /// Loads the project and reports errors without running it
+    Check(ProjectArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ProjectArgs {
+    /// Project folder, overrides `root_folder` in `config.ron`
+    pub project: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct WindowArgs {
+    #[command(flatten)]
+    pub project: ProjectArgs,
+
+    /// Start time in seconds
+    #[arg(long)]
+    pub start: Option<f32>,
+}
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub project: ProjectArgs,
+
+    /// Width of the rendered frames in pixels
+    #[arg(long)]
+    pub width: Option<u32>,
+
+    /// Height of the rendered frames in pixels
+    #[arg(long)]
+    pub height: Option<u32>,
+
+    /// Frames per second
+    #[arg(long)]
+    pub fps: Option<u32>,
+
+    /// Start time in seconds. Earlier frames are still rendered to run simulations, but not saved.
+    #[arg(long)]
+    pub from: Option<f32>,
+
+    /// End time in seconds, defaults to the end of the project
+    #[arg(long)]
+    pub to: Option<f32>,
+
+    /// Output folder
+    #[arg(long)]
+    pub out: Option<String>,
+}
@@ -1,3 +1,4 @@
+use std::mem;
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -18,6 +19,9 @@ pub struct ContentRenderer {
     has_render_failure: bool,
     music_player: MusicPlayer,
     last_render_time: Option<f32>,
+
+    /// Charts rebuilt by the last reload whose simulation hasn't been reset yet
+    reloaded_charts: Vec<Rc<Chart>>,
 }
 
 impl ContentRenderer {
@@ -31,10 +35,11 @@ impl ContentRenderer {
         let project = project_loader.get_or_load_project(context);
         let has_render_failure = project.is_none();
 
-        let app_state = AppState::new(
+        let mut app_state = AppState::new(
             project,
             project_loader.resource_repository.control_repository.clone(),
         );
+        app_state.load_error = project_loader.load_error().map(str::to_string);
 
         Ok(Self {
             project_loader,
@@ -42,6 +47,7 @@ impl ContentRenderer {
             has_render_failure,
             music_player,
             last_render_time: None,
+            reloaded_charts: vec![],
         })
     }
 
@@ -65,12 +71,7 @@ impl ContentRenderer {
             }
         }
 
-        let draw_result = match self.app_state.get_chart() {
-            Some(chart) => self.draw_chart(&chart, context),
-            None => self.draw_project(context),
-        };
-
-        self.last_render_time = Some(context.globals.app_time);
+        let draw_result = self.draw_content(context);
 
         if let Err(err) = draw_result {
             if !self.has_render_failure {
@@ -82,8 +83,38 @@ impl ContentRenderer {
         }
     }
 
+    /// Renders the project at a given time, advancing the simulation by a fixed time step.
+    ///
+    /// Used for offline rendering where frames are not tied to the wall clock.
+    pub fn draw_at_fixed_time(
+        &mut self,
+        context: &mut FrameContext,
+        time: f32,
+        elapsed_since_last_frame: f32,
+    ) -> Result<()> {
+        self.app_state.set_time(time);
+        context.globals.app_time = time;
+        context.globals.is_paused = false;
+        context.globals.simulation_step_seconds = SIMULATION_STEP_SECONDS;
+        context.globals.simulation_elapsed_time_since_last_render = elapsed_since_last_frame;
+        self.draw_content(context)
+    }
+
+    fn draw_content(&mut self, context: &mut FrameContext) -> Result<()> {
+        let draw_result = match self.app_state.get_chart() {
+            Some(chart) => self.draw_chart(&chart, context),
+            None => self.draw_project(context),
+        };
+        self.last_render_time = Some(context.globals.app_time);
+        draw_result
+    }
+
     fn draw_chart(&mut self, chart: &Chart, context: &mut FrameContext) -> Result<()> {
         context.globals.chart_time = self.app_state.cursor_time;
+        if let Some(project) = &self.app_state.project {
+            let project_time = self.app_state.cursor_time + self.app_state.get_chart_start_time();
+            project.set_tempo_globals(&mut context.globals, project_time);
+        }
         chart.render(context)
     }
 
@@ -92,22 +123,31 @@ impl ContentRenderer {
             bail!("Can't load project.");
         };
 
-        let cursor_time = self.app_state.cursor_time;
-        for cut in &project.cuts {
-            if cut.start_time <= cursor_time && cursor_time <= cut.end_time {
-                context.globals.chart_time = cursor_time - cut.start_time + cut.offset;
-                cut.chart.render(context)?
-            }
-        }
-        Ok(())
+        project.render(context, self.app_state.cursor_time)
     }
 
-    /// Returns true if the project changed.
+    /// Returns true if the project changed. The rebuilt charts are stored in `reloaded_charts`.
     pub fn reload_project(&mut self, context: &Arc<GpuContext>) -> bool {
         let project = self.project_loader.get_or_load_project(context);
+        let load_error = self.project_loader.load_error();
+        if self.app_state.load_error.as_deref() != load_error {
+            self.app_state.load_error = load_error.map(str::to_string);
+        }
 
         // Compare references to see if it's the same cached value that we tried rendering last time
         if project.as_ref().map(Rc::as_ptr) != self.app_state.project.as_ref().map(Rc::as_ptr) {
+            // Charts that weren't affected by the changes are reused with their simulation state
+            let previous_charts = self.app_state.project.as_ref().map(|project| &project.charts);
+            self.reloaded_charts = project
+                .iter()
+                .flat_map(|project| &project.charts)
+                .filter(|chart| {
+                    !previous_charts.is_some_and(|charts| {
+                        charts.iter().any(|previous| Rc::ptr_eq(previous, chart))
+                    })
+                })
+                .cloned()
+                .collect();
             self.app_state.project = project;
             self.has_render_failure = false;
             return true;
@@ -134,6 +174,31 @@ impl ContentRenderer {
     }
 
     pub fn reset_simulation(&mut self, context: &GpuContext) -> Result<()> {
+        let charts = match self.app_state.get_chart() {
+            // Reset only the selected chart
+            Some(chart) => vec![chart],
+
+            // No chart selected, reset all of them
+            None => match &self.app_state.project {
+                Some(project) => project.charts.clone(),
+                None => vec![],
+            },
+        };
+        self.reset_chart_simulations(context, &charts)
+    }
+
+    /// Resets the simulation of the charts that were rebuilt by the last reload.
+    pub fn reset_reloaded_simulations(&mut self, context: &GpuContext) -> Result<()> {
+        let charts = mem::take(&mut self.reloaded_charts);
+        self.reset_chart_simulations(context, &charts)
+    }
+
+    fn reset_chart_simulations(
+        &mut self,
+        context: &GpuContext,
+        charts: &[Rc<Chart>],
+    ) -> Result<()> {
+        context.uniform_ring.start_frame();
         let mut command_encoder =
             context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
@@ -150,19 +215,9 @@ impl ContentRenderer {
             compute_pass_context.globals.simulation_step_seconds = SIMULATION_STEP_SECONDS;
             compute_pass_context.globals.simulation_elapsed_time_since_last_render = 0.0;
 
-            match self.app_state.get_chart() {
-                // Reset only the selected chart
-                Some(chart) => chart.reset_simulation(&mut compute_pass_context)?,
-
-                // No chart selected, reset all of them
-                None => {
-                    if let Some(project) = &self.app_state.project {
-                        for cut in &project.cuts {
-                            cut.chart.reset_simulation(&mut compute_pass_context)?;
-                        }
-                    }
-                }
-            };
+            for chart in charts {
+                chart.reset_simulation(&mut compute_pass_context)?;
+            }
         }
 
         context.queue.submit(Some(command_encoder.finish()));
@@ -1,5 +1,6 @@
 mod app_config;
 mod app_state;
+mod cli;
 pub mod content_renderer;
 mod music_player;
 mod runners;
@@ -8,25 +9,24 @@ mod timer;
 mod ui;
 
 use anyhow::Result;
-// use runners::frame_dump_runner::FrameDumpRunner;
+use app_config::{AppConfig, RunMode};
+use clap::Parser;
+use cli::Cli;
+use runners::check_runner::CheckRunner;
+use runners::frame_dump_runner::FrameDumpRunner;
 use runners::window_runner::WindowRunner;
 
 use crate::engine::PixelFormat;
 
-pub const FRAMEDUMP_MODE: bool = false;
-pub const _FRAMEDUMP_WIDTH: u32 = 3840;
-pub const _FRAMEDUMP_HEIGHT: u32 = 2160;
-pub const _FRAMEDUMP_FPS: u32 = 60;
-
 const SCREEN_RATIO: (u32, u32) = (16, 9);
 
-pub const _FRAMEDUMP_PIXEL_FORMAT: PixelFormat = PixelFormat::Rgba8U;
+pub const FRAMEDUMP_PIXEL_FORMAT: PixelFormat = PixelFormat::Rgba8U;
 
 pub fn run_app() -> Result<()> {
-    if FRAMEDUMP_MODE {
-        // FrameDumpRunner::run()
-        unimplemented!()
-    } else {
-        WindowRunner::run()
+    let app_config = AppConfig::init(Cli::parse())?;
+    match app_config.run_mode {
+        RunMode::Window => WindowRunner::run(),
+        RunMode::Render => FrameDumpRunner::run(),
+        RunMode::Check => CheckRunner::run(),
     }
 }
@@ -0,0 +1,14 @@
+use anyhow::Result;
+
+use crate::loader::project_checker::ProjectChecker;
+use crate::tool::app_config::AppConfig;
+
+/// Validates the project files and shaders without a GPU.
+pub struct CheckRunner;
+
+impl CheckRunner {
+    pub fn run() -> Result<()> {
+        let checker = ProjectChecker::try_new(&AppConfig::get().root_folder)?;
+        checker.check_project()
+    }
+}
@@ -1,155 +1,225 @@
-// use std::sync::atomic::{AtomicUsize, Ordering};
-// use std::sync::Arc;
-// use std::thread::sleep;
-// use std::time::{Duration, Instant};
-
-// use anyhow::Result;
-// use tracing::info;
-
-// use crate::engine::{BitangImage, FrameContext, GpuContext, ImageSizeRule, Viewport};
-// use crate::tool::content_renderer::ContentRenderer;
-// use crate::tool::{FRAMEDUMP_FPS, FRAMEDUMP_HEIGHT, FRAMEDUMP_PIXEL_FORMAT, FRAMEDUMP_WIDTH};
-
-// pub struct FrameDumpRunner {
-//     gpu_context: Arc<GpuContext>,
-//     app: ContentRenderer,
-//     dst_buffer: wgpu::Buffer,
-// }
-
-// impl FrameDumpRunner {
-//     pub fn run() -> Result<()> {
-//         let rt = tokio::runtime::Runtime::new()?;
-//         let final_render_target = BitangImage::new_attachment(
-//             "__screen",
-//             FRAMEDUMP_PIXEL_FORMAT,
-//             ImageSizeRule::Fixed(FRAMEDUMP_WIDTH, FRAMEDUMP_HEIGHT),
-//             false,
-//         );
-//         let gpu_context =
-//             rt.block_on(async { GpuContext::new_for_offscreen(final_render_target).await })?;
-//         let gpu_context = Arc::new(gpu_context);
-//         let mut app = ContentRenderer::new(&gpu_context)?;
-//         app.reset_simulation(&gpu_context)?;
-
-//         let dst_buffer = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
-//             label: Some("Image save buffer"),
-//             size: (FRAMEDUMP_WIDTH * FRAMEDUMP_HEIGHT * 4) as u64,
-//             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-//             mapped_at_creation: false,
-//         });
-
-//         let mut frame_dump_runner = Self {
-//             gpu_context,
-//             app,
-//             dst_buffer,
-//         };
-
-//         frame_dump_runner.render_demo_to_file();
-//         Ok(())
-//     }
-
-//     fn render_demo_to_file(&mut self) {
-//         let timer = Instant::now();
-//         // PNG compression is slow, so let's use all the CPU cores
-//         let runtime = tokio::runtime::Runtime::new().unwrap();
-//         let job_count: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
-//         let cpu_count = num_cpus::get();
-//         info!("Rendering demo using {cpu_count} CPUs");
-//         let project_length = self.app.app_state.project.as_ref().unwrap().length;
-//         let mut frame_count = 0;
-
-//         loop {
-//             let time = frame_count as f32 / (FRAMEDUMP_FPS as f32);
-//             if time >= project_length {
-//                 break;
-//             }
-//             self.app.app_state.set_time(time);
-
-//             // Render frame and save it into host memory
-//             self.render_frame_to_buffer();
-//             let content = self.get_frame_content();
-
-//             // If we're rendering too fast, wait a bit
-//             while job_count.load(Ordering::Relaxed) >= cpu_count + 20 {
-//                 sleep(Duration::from_millis(1));
-//             }
-
-//             // Save the frame to a file in a separate thread
-//             job_count.fetch_add(1, Ordering::Relaxed);
-//             let job_count_clone = job_count.clone();
-//             runtime.spawn_blocking(move || {
-//                 Self::save_frame_buffer_to_file(content, frame_count);
-//                 job_count_clone.fetch_sub(1, Ordering::Relaxed);
-//             });
-//             frame_count += 1;
-//         }
-//         info!(
-//             "Rendered {frame_count} frames in {:.1} secs",
-//             timer.elapsed().as_secs_f32()
-//         );
-//     }
-
-//     fn save_frame_buffer_to_file(mut content: Vec<u8>, frame_number: usize) {
-//         // Fix the alpha channel
-//         for i in 0..content.len() / 4 {
-//             content[i * 4 + 3] = 255;
-//         }
-
-//         let path = format!("framedump/dump-{:0>8}.png", frame_number);
-//         let save_timer = Instant::now();
-//         image::save_buffer_with_format(
-//             &path,
-//             &content,
-//             FRAMEDUMP_WIDTH,
-//             FRAMEDUMP_HEIGHT,
-//             image::ColorType::Rgba8,
-//             image::ImageFormat::Png,
-//         )
-//         .unwrap();
-//         info!(
-//             "Saved frame {frame_number} to {path} ({}ms)",
-//             save_timer.elapsed().as_millis()
-//         );
-//     }
-
-//     fn render_frame_to_buffer(&mut self) {
-//         let size = [FRAMEDUMP_WIDTH, FRAMEDUMP_HEIGHT];
-//         let screen_viewport = Viewport { x: 0, y: 0, size };
-//         self.gpu_context.final_render_target.enforce_size_rule(&self.gpu_context, &size).unwrap();
-
-//         let command_encoder = self
-//             .gpu_context
-//             .device
-//             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-//         let mut frame_context = FrameContext {
-//             gpu_context: self.gpu_context.clone(),
-//             command_encoder,
-//             globals: Default::default(),
-//             screen_viewport,
-//         };
-//         frame_context.globals.simulation_elapsed_time_since_last_render =
-//             1.0 / (FRAMEDUMP_FPS as f32);
-//         frame_context.globals.app_time = self.app.app_state.cursor_time;
-
-//         self.app.draw(&mut frame_context);
-
-//         // Add a copy command to the end of the command buffer
-//         self.gpu_context
-//             .final_render_target
-//             .copy_attachment_to_buffer(&mut frame_context, &self.dst_buffer)
-//             .unwrap();
-
-//         self.gpu_context.queue.submit(Some(frame_context.command_encoder.finish()));
-//     }
-
-//     fn get_frame_content(&mut self) -> Vec<u8> {
-//         let res = {
-//             let dst_buffer_slice = self.dst_buffer.slice(..);
-//             dst_buffer_slice.map_async(wgpu::MapMode::Read, |_| ());
-//             self.gpu_context.device.poll(wgpu::Maintain::Wait);
-//             dst_buffer_slice.get_mapped_range().to_vec()
-//         };
-//         self.dst_buffer.unmap();
-//         res
-//     }
-// }
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use smallvec::SmallVec;
+use tracing::{error, info};
+
+use crate::engine::{
+    BitangImage, FrameContext, GpuContext, ImageDimension, ImageSizeRule, RenderPassDrawBatch,
+};
+use crate::tool::app_config::{AppConfig, RenderConfig};
+use crate::tool::content_renderer::ContentRenderer;
+use crate::tool::FRAMEDUMP_PIXEL_FORMAT;
+
+/// Renders the whole project offscreen and saves every frame as a PNG file.
+pub struct FrameDumpRunner {
+    config: &'static RenderConfig,
+    gpu_context: Arc<GpuContext>,
+    content_renderer: ContentRenderer,
+    dst_buffer: wgpu::Buffer,
+    padded_bytes_per_row: u32,
+}
+
+impl FrameDumpRunner {
+    pub fn run() -> Result<()> {
+        let config = &AppConfig::get().render;
+        let rt = tokio::runtime::Runtime::new()?;
+        let final_render_target = BitangImage::new_attachment(
+            "__screen",
+            FRAMEDUMP_PIXEL_FORMAT,
+            ImageSizeRule::Fixed(config.width, config.height),
+            false,
+            1,
+            ImageDimension::D2,
+        );
+        let gpu_context =
+            rt.block_on(async { GpuContext::new_for_offscreen(final_render_target).await })?;
+        let gpu_context = Arc::new(gpu_context);
+        let adapter_info = gpu_context.adapter.get_info();
+        info!(
+            "WGPU adapter: {:?} on {}",
+            adapter_info.backend, adapter_info.name
+        );
+
+        let mut content_renderer = ContentRenderer::new(&gpu_context)?;
+        if content_renderer.app_state.project.is_none() {
+            bail!("Can't load project, nothing to render.");
+        }
+        if let Some(load_error) = &content_renderer.app_state.load_error {
+            bail!("Some charts failed to load:\n{load_error}");
+        }
+        content_renderer.reset_simulation(&gpu_context)?;
+
+        let padded_bytes_per_row =
+            gpu_context.final_render_target.padded_bytes_per_row(config.width);
+        let dst_buffer = gpu_context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Image save buffer"),
+            size: (padded_bytes_per_row * config.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut frame_dump_runner = Self {
+            config,
+            gpu_context,
+            content_renderer,
+            dst_buffer,
+            padded_bytes_per_row,
+        };
+
+        frame_dump_runner.render_demo_to_file()
+    }
+
+    fn render_demo_to_file(&mut self) -> Result<()> {
+        let config = self.config;
+        std::fs::create_dir_all(&config.out)
+            .with_context(|| format!("Failed to create folder '{}'", config.out))?;
+
+        let timer = Instant::now();
+        // PNG compression is slow, so let's use all the CPU cores
+        let runtime = tokio::runtime::Runtime::new()?;
+        let job_count: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let failed_job_count: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let cpu_count = num_cpus::get();
+        info!("Rendering demo using {cpu_count} CPUs");
+        let project_length = self.content_renderer.app_state.project.as_ref().unwrap().length;
+        let end_time = config.to.unwrap_or(project_length);
+        let frame_duration = 1.0 / (config.fps as f32);
+
+        // Frames are numbered from the start of the project, so ranges can be rendered separately.
+        // The frames before the range are rendered but not saved, so simulations reach the same
+        // state as in a full render.
+        let first_frame = (config.from * config.fps as f32).ceil() as usize;
+        let mut frame_number = 0;
+
+        loop {
+            let time = frame_number as f32 * frame_duration;
+            if time >= end_time {
+                break;
+            }
+
+            // Render frame and save it into host memory
+            self.render_frame_to_buffer(time, frame_duration)?;
+            if frame_number < first_frame {
+                self.gpu_context.device.poll(wgpu::PollType::Wait)?;
+                frame_number += 1;
+                continue;
+            }
+            let content = self.get_frame_content()?;
+
+            // If we're rendering too fast, wait a bit
+            while job_count.load(Ordering::Relaxed) >= cpu_count + 20 {
+                sleep(Duration::from_millis(1));
+            }
+
+            // Save the frame to a file in a separate thread
+            job_count.fetch_add(1, Ordering::Relaxed);
+            let job_count_clone = job_count.clone();
+            let failed_job_count_clone = failed_job_count.clone();
+            runtime.spawn_blocking(move || {
+                if let Err(err) = Self::save_frame_buffer_to_file(config, content, frame_number) {
+                    error!("Failed to save frame {frame_number}: {err:?}");
+                    failed_job_count_clone.fetch_add(1, Ordering::Relaxed);
+                }
+                job_count_clone.fetch_sub(1, Ordering::Relaxed);
+            });
+            frame_number += 1;
+        }
+
+        // Wait for all frames to be saved
+        while job_count.load(Ordering::Relaxed) > 0 {
+            sleep(Duration::from_millis(1));
+        }
+        info!(
+            "Rendered {} frames in {:.1} secs",
+            frame_number - first_frame,
+            timer.elapsed().as_secs_f32()
+        );
+
+        let failed_job_count = failed_job_count.load(Ordering::Relaxed);
+        if failed_job_count > 0 {
+            bail!("Failed to save {failed_job_count} frames");
+        }
+        Ok(())
+    }
+
+    fn save_frame_buffer_to_file(
+        config: &RenderConfig,
+        mut content: Vec<u8>,
+        frame_number: usize,
+    ) -> Result<()> {
+        // Fix the alpha channel
+        for i in 0..content.len() / 4 {
+            content[i * 4 + 3] = 255;
+        }
+
+        let path = Path::new(&config.out).join(format!("dump-{:0>8}.png", frame_number));
+        let save_timer = Instant::now();
+        image::save_buffer_with_format(
+            &path,
+            &content,
+            config.width,
+            config.height,
+            image::ColorType::Rgba8,
+            image::ImageFormat::Png,
+        )?;
+        info!(
+            "Saved frame {frame_number} to {path:?} ({}ms)",
+            save_timer.elapsed().as_millis()
+        );
+        Ok(())
+    }
+
+    fn render_frame_to_buffer(&mut self, time: f32, frame_duration: f32) -> Result<()> {
+        let size = [self.config.width, self.config.height];
+        self.gpu_context.final_render_target.enforce_size_rule(&self.gpu_context, &size)?;
+        self.gpu_context.uniform_ring.start_frame();
+
+        let command_encoder = self
+            .gpu_context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let mut frame_context = FrameContext {
+            gpu_context: self.gpu_context.clone(),
+            command_encoder,
+            globals: Default::default(),
+            screen_size: size,
+            screen_pass_draw_batch: RenderPassDrawBatch {
+                draw_commands: SmallVec::new(),
+            },
+            screen_override: None,
+        };
+
+        self.content_renderer.draw_at_fixed_time(&mut frame_context, time, frame_duration)?;
+
+        // Add a copy command to the end of the command buffer
+        self.gpu_context
+            .final_render_target
+            .copy_attachment_to_buffer(&mut frame_context, &self.dst_buffer)?;
+
+        self.gpu_context.queue.submit(Some(frame_context.command_encoder.finish()));
+        Ok(())
+    }
+
+    /// Reads back the rendered frame and removes the row padding.
+    fn get_frame_content(&mut self) -> Result<Vec<u8>> {
+        let row_size = (self.config.width * 4) as usize;
+        let content = {
+            let dst_buffer_slice = self.dst_buffer.slice(..);
+            dst_buffer_slice.map_async(wgpu::MapMode::Read, |_| ());
+            self.gpu_context.device.poll(wgpu::PollType::Wait)?;
+            let mapped = dst_buffer_slice.get_mapped_range();
+            mapped
+                .chunks(self.padded_bytes_per_row as usize)
+                .flat_map(|row| &row[..row_size])
+                .copied()
+                .collect::<Vec<u8>>()
+        };
+        self.dst_buffer.unmap();
+        Ok(content)
+    }
+}
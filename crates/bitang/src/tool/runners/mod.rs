@@ -1,2 +1,3 @@
-// pub mod frame_dump_runner;
+pub mod check_runner;
+pub mod frame_dump_runner;
 pub mod window_runner;
@@ -12,7 +12,8 @@ use tracing::{debug, error, info};
 use wgpu::Backends;
 
 use crate::engine::{
-    BitangImage, FrameContext, GpuContext, PixelFormat, RenderPassDrawBatch, Size2D, Viewport,
+    BitangImage, FrameContext, GpuContext, PixelFormat, RenderPassDrawBatch, Size2D, UniformRing,
+    Viewport,
 };
 use crate::tool::app_config::AppConfig;
 use crate::tool::content_renderer::ContentRenderer;
@@ -43,10 +44,8 @@ impl WindowRunner {
                     backends: Backends::DX12,
                     ..Default::default()
                 },
-                device_descriptor: Arc::new(|_adapter| wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::FLOAT32_FILTERABLE
-                        | wgpu::Features::ADDRESS_MODE_CLAMP_TO_BORDER
-                        | wgpu::Features::VERTEX_WRITABLE_STORAGE,
+                device_descriptor: Arc::new(|adapter| wgpu::DeviceDescriptor {
+                    required_features: GpuContext::device_features(adapter),
                     ..Default::default()
                 }),
                 ..Default::default()
@@ -90,10 +89,12 @@ impl WindowRunner {
             queue: render_state.queue.clone(),
             device: render_state.device.clone(),
             final_render_target,
+            uniform_ring: UniformRing::new(&render_state.device),
         });
 
         let mut content_renderer = ContentRenderer::new(&gpu_context)?;
         content_renderer.reset_simulation(&gpu_context)?;
+        content_renderer.app_state.set_time(AppConfig::get().start_time);
 
         let ui = Ui::new()?;
 
@@ -164,6 +165,7 @@ impl WindowRunner {
     fn render_frame_to_screen(&mut self) -> RenderPassDrawBatch {
         // Create frame context
         // TODO: create it content_renderer
+        self.gpu_context.uniform_ring.start_frame();
         let command_encoder = self
             .gpu_context
             .device
@@ -176,13 +178,14 @@ impl WindowRunner {
             screen_pass_draw_batch: RenderPassDrawBatch {
                 draw_commands: SmallVec::new(),
             },
+            screen_override: None,
         };
         frame_context.globals.app_time = self.app_start_time.elapsed().as_secs_f32();
 
         // Reload project
         // TODO: start render function with this block
         if self.content_renderer.reload_project(&self.gpu_context) {
-            self.content_renderer.reset_simulation(&self.gpu_context).unwrap();
+            self.content_renderer.reset_reloaded_simulations(&self.gpu_context).unwrap();
             frame_context.globals.app_time = self.app_start_time.elapsed().as_secs_f32();
             self.content_renderer.unset_last_render_time();
         }
@@ -4,13 +4,52 @@ use egui::Color32;
 use egui_plot::{Line, Plot, PlotBounds, PlotPoint, PlotUi};
 use glam::Vec2;
 
-use crate::engine::{Control, SplinePoint};
+use crate::engine::{Control, SplinePoint, TempoMap};
 use crate::tool::app_state::AppState;
 
 enum SplineEditorState {
     Idle,
     Pan,
-    PointMove { index: usize },
+    PointMove {
+        index: usize,
+
+        /// The time of the dragged point without snapping
+        unsnapped_time: f32,
+    },
+}
+
+/// Beat subdivisions points can snap to
+const SNAP_SUBDIVISIONS: [u32; 4] = [1, 2, 4, 8];
+
+/// Grid lines closer than this many pixels are not drawn
+const MIN_GRID_LINE_DISTANCE: f32 = 6.0;
+
+/// Converts between the time of the spline editor and the beats of the music.
+struct BeatGrid<'a> {
+    tempo_map: &'a TempoMap,
+
+    /// Project time at zero editor time, the cut start minus the cut offset
+    chart_start_time: f32,
+}
+
+impl BeatGrid<'_> {
+    fn beat_at(&self, time: f32) -> f32 {
+        self.tempo_map.beat_at(time + self.chart_start_time)
+    }
+
+    fn time_at_beat(&self, beat: f32) -> f32 {
+        self.tempo_map.time_at_beat(beat) - self.chart_start_time
+    }
+
+    fn format_bars_beats(&self, time: f32) -> String {
+        self.tempo_map.format_bars_beats(time + self.chart_start_time)
+    }
+
+    fn snap(&self, time: f32, subdivisions: u32) -> f32 {
+        let subdivisions = subdivisions as f32;
+        let beat = (self.beat_at(time) * subdivisions).round() / subdivisions;
+        self.time_at_beat(beat)
+    }
 }
 
 pub struct SplineEditor {
@@ -21,6 +60,9 @@ pub struct SplineEditor {
     control: Option<Rc<Control>>,
     component_index: usize,
     selected_index: Option<usize>,
+
+    /// Snaps points to this many subdivisions of a beat, if the project has a tempo
+    snap_subdivisions: Option<u32>,
 }
 
 impl SplineEditor {
@@ -33,6 +75,7 @@ impl SplineEditor {
             control: None,
             component_index: 0,
             selected_index: None,
+            snap_subdivisions: None,
         }
     }
 
@@ -66,9 +109,19 @@ impl SplineEditor {
         let max_y = self.center_y + (screen_size.y * pixel_size.y) / 2.0;
         let min_y = self.center_y - (screen_size.y * pixel_size.y) / 2.0;
 
+        let project = app_state.project.clone();
+        let beat_grid =
+            project.as_ref().and_then(|project| project.tempo_map.as_ref()).map(|tempo_map| {
+                BeatGrid {
+                    tempo_map,
+                    chart_start_time: app_state.get_chart_start_time(),
+                }
+            });
+        let beat_grid = beat_grid.as_ref();
+
         ui.with_layout(egui::Layout::top_down(egui::Align::Min), |ui| {
             let time = app_state.cursor_time;
-            self.draw_info(ui, time);
+            self.draw_info(ui, time, beat_grid);
             let plot = Plot::new("spline_editor")
                 .show_x(false)
                 .show_y(false)
@@ -87,6 +140,9 @@ impl SplineEditor {
                     [self.min_x as f64, min_y as f64],
                     [max_x as f64, max_y as f64],
                 ));
+                if let Some(beat_grid) = beat_grid {
+                    self.paint_beat_grid(plot_ui, beat_grid, max_x, screen_size);
+                }
                 self.paint_time_cursor(plot_ui, time, screen_size);
                 (hover_index, pointer_coordinate) = self.draw_spline(plot_ui, pixel_width);
             });
@@ -94,17 +150,36 @@ impl SplineEditor {
             self.handle_events(
                 ui,
                 &plot_response.response,
-                &pixel_size,
                 hover_index,
                 pointer_coordinate,
+                beat_grid,
                 app_state,
             );
         });
     }
 
     // Info about on the top
-    fn draw_info(&mut self, ui: &mut egui::Ui, time: f32) {
+    fn draw_info(&mut self, ui: &mut egui::Ui, time: f32, beat_grid: Option<&BeatGrid>) {
+        let snap_subdivisions = beat_grid.and(self.snap_subdivisions);
         ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
+            if let Some(beat_grid) = beat_grid {
+                ui.label(beat_grid.format_bars_beats(time));
+                egui::ComboBox::from_id_salt("snap")
+                    .selected_text(match self.snap_subdivisions {
+                        Some(subdivisions) => format!("Snap: 1/{subdivisions} beat"),
+                        None => "Snap: off".to_string(),
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.snap_subdivisions, None, "Off");
+                        for subdivisions in SNAP_SUBDIVISIONS {
+                            ui.selectable_value(
+                                &mut self.snap_subdivisions,
+                                Some(subdivisions),
+                                format!("1/{subdivisions} beat"),
+                            );
+                        }
+                    });
+            }
             if let Some(control) = self.control.as_mut() {
                 let components = &mut control.components.borrow_mut();
                 let spline = &mut components[self.component_index].spline;
@@ -115,6 +190,10 @@ impl SplineEditor {
                     .on_hover_text("Adds a new point at the current time")
                     .clicked()
                 {
+                    let time = match (beat_grid, snap_subdivisions) {
+                        (Some(beat_grid), Some(subdivisions)) => beat_grid.snap(time, subdivisions),
+                        _ => time,
+                    };
                     let value = spline.get_value(time);
 
                     // Unwrap is safe: time is always a valid float
@@ -154,6 +233,9 @@ impl SplineEditor {
                             ui.add(
                                 egui::DragValue::new(&mut point.time).speed(0.01).max_decimals(6),
                             );
+                            if let Some(beat_grid) = beat_grid {
+                                ui.label(beat_grid.format_bars_beats(point.time));
+                            }
                             ui.label("Value:");
                             ui.add(
                                 egui::DragValue::new(&mut point.value).speed(0.01).max_decimals(6),
@@ -178,6 +260,43 @@ impl SplineEditor {
         plot_ui.line(Line::new("", points).color(Color32::from_rgb(150, 0, 150)).width(1.0));
     }
 
+    // Vertical lines at bars, and at beats if they are far enough apart
+    fn paint_beat_grid(
+        &self,
+        plot_ui: &mut PlotUi,
+        beat_grid: &BeatGrid,
+        max_x: f32,
+        screen_size: egui::Vec2,
+    ) {
+        let pixel_size_x = Self::calculate_pixel_size(self.zoom.x);
+        let time_dy = (screen_size.y * Self::calculate_pixel_size(self.zoom.y)) as f64;
+        let first_beat = beat_grid.beat_at(self.min_x).floor() as i64;
+        let last_beat = beat_grid.beat_at(max_x).ceil() as i64;
+        let beats_per_bar = beat_grid.tempo_map.beats_per_bar as i64;
+        let mut last_line_x = None;
+        for beat in first_beat..=last_beat {
+            let is_bar = beat.rem_euclid(beats_per_bar) == 0;
+            let x = beat_grid.time_at_beat(beat as f32);
+            let beat_width = (beat_grid.time_at_beat((beat + 1) as f32) - x) / pixel_size_x;
+            if !is_bar && beat_width < MIN_GRID_LINE_DISTANCE {
+                continue;
+            }
+            if last_line_x
+                .is_some_and(|last_x| (x - last_x) / pixel_size_x < MIN_GRID_LINE_DISTANCE)
+            {
+                continue;
+            }
+            last_line_x = Some(x);
+            let color =
+                if is_bar { Color32::from_rgb(80, 80, 80) } else { Color32::from_rgb(45, 45, 45) };
+            let points = vec![
+                [x as f64, self.center_y as f64 - time_dy],
+                [x as f64, self.center_y as f64 + time_dy],
+            ];
+            plot_ui.line(Line::new("", points).color(color).width(1.0));
+        }
+    }
+
     // Returns the index of the hovered point
     fn draw_spline(
         &mut self,
@@ -203,7 +322,7 @@ impl SplineEditor {
         );
 
         // Find hovered point
-        let hover_index = if let SplineEditorState::PointMove { index } = self.state {
+        let hover_index = if let SplineEditorState::PointMove { index, .. } = self.state {
             Some(index)
         } else if plot_ui.response().hovered() {
             pointer_coordinate.and_then(|c| {
@@ -258,9 +377,9 @@ impl SplineEditor {
         &mut self,
         ui: &egui::Ui,
         response: &egui::Response,
-        pixel_size: &Vec2,
         hover_index: Option<usize>,
         pointer_coordinate: Option<PlotPoint>,
+        beat_grid: Option<&BeatGrid>,
         app_state: &mut AppState,
     ) {
         let scroll_delta = ui.input(|i| i.smooth_scroll_delta);
@@ -270,6 +389,10 @@ impl SplineEditor {
         let primary_down = ui.input(|i| i.pointer.primary_down());
         let secondary_down = ui.input(|i| i.pointer.secondary_down());
         let pointer_delta = ui.input(|i| i.pointer.delta());
+        let pixel_size = Vec2::new(
+            Self::calculate_pixel_size(self.zoom.x),
+            Self::calculate_pixel_size(self.zoom.y),
+        );
 
         if let Some(hover) = response.hover_pos() {
             let hover = hover - response.rect.min;
@@ -304,7 +427,14 @@ impl SplineEditor {
                 // Left click: select point
                 if primary_clicked {
                     if let Some(index) = hover_index {
-                        self.state = SplineEditorState::PointMove { index };
+                        if let Some(control) = self.control.as_ref() {
+                            let components = control.components.borrow();
+                            let spline = &components[self.component_index].spline;
+                            self.state = SplineEditorState::PointMove {
+                                index,
+                                unsnapped_time: spline.points[index].time,
+                            };
+                        }
                         self.selected_index = Some(index);
                     }
                 }
@@ -325,12 +455,26 @@ impl SplineEditor {
                     self.state = SplineEditorState::Idle;
                 }
             }
-            SplineEditorState::PointMove { index } => {
+            SplineEditorState::PointMove {
+                index,
+                unsnapped_time,
+            } => {
+                let unsnapped_time =
+                    unsnapped_time + pointer_delta.x * Self::calculate_pixel_size(self.zoom.x);
+                self.state = SplineEditorState::PointMove {
+                    index,
+                    unsnapped_time,
+                };
                 if let Some(control) = self.control.as_mut() {
                     let mut components = control.components.borrow_mut();
                     let spline = &mut components[self.component_index].spline;
                     let point = spline.points.get_mut(index).unwrap();
-                    point.time += pointer_delta.x * Self::calculate_pixel_size(self.zoom.x);
+                    point.time = match (beat_grid, self.snap_subdivisions) {
+                        (Some(beat_grid), Some(subdivisions)) => {
+                            beat_grid.snap(unsnapped_time, subdivisions)
+                        }
+                        _ => unsnapped_time,
+                    };
                     point.value -= pointer_delta.y * Self::calculate_pixel_size(self.zoom.y);
                 }
                 if !primary_down {
@@ -20,6 +20,13 @@ impl Ui {
     pub fn draw(&mut self, ui: &mut egui::Ui, app_state: &mut AppState) {
         let spline_editor = &mut self.spline_editor;
         ui.add_space(5.0);
+        if let Some(load_error) = &app_state.load_error {
+            ui.colored_label(
+                egui::Color32::LIGHT_RED,
+                format!("Reload failed: {load_error}"),
+            );
+            ui.separator();
+        }
         ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
             Self::draw_control_tree(ui, app_state);
             ui.separator();
@@ -132,39 +139,64 @@ impl Ui {
                     .map(|p| p.name.clone())
                     .collect::<Vec<_>>()
                     .join("/");
+                // Only controls of chart instances can follow another control
+                let is_overridden = c.fallback.borrow().as_ref().map(|_| c.is_overridden.get());
                 (
                     index,
                     name,
                     c.used_component_count.get(),
+                    is_overridden,
                     c.components.borrow_mut(),
                 )
             });
         let mut selected = None;
+        let mut override_changes = vec![];
 
         egui::ScrollArea::vertical().show(ui, |ui| {
             ui.with_layout(egui::Layout::top_down(egui::Align::Min), |ui| {
-                for (control_index, control_name, component_count, mut control) in controls_borrow {
-                    ui.label(&control_name);
+                for (control_index, control_name, component_count, is_overridden, mut control) in
+                    controls_borrow
+                {
+                    ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
+                        ui.label(&control_name);
+                        if let Some(is_overridden) = is_overridden {
+                            let mut new_is_overridden = is_overridden;
+                            ui.checkbox(&mut new_is_overridden, "Override").on_hover_text(
+                                "Otherwise the value follows the control of the base chart",
+                            );
+                            if new_is_overridden != is_overridden {
+                                override_changes.push((control_index, new_is_overridden));
+                            }
+                        }
+                    });
                     let components = control.as_mut();
                     for (i, component) in components.iter_mut().enumerate().take(component_count) {
+                        let is_enabled = is_overridden != Some(false);
                         ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
-                            ui.add_sized(
-                                [350.0, 0.0],
-                                egui::Slider::new(&mut component.value, 0.0..=1.0)
-                                    .clamping(SliderClamping::Never)
-                                    .max_decimals(3),
-                            );
+                            ui.add_enabled_ui(is_enabled, |ui| {
+                                ui.add_sized(
+                                    [350.0, 0.0],
+                                    egui::Slider::new(&mut component.value, 0.0..=1.0)
+                                        .clamping(SliderClamping::Never)
+                                        .max_decimals(3),
+                                );
 
-                            if ui.button("✏").clicked() {
-                                selected = Some((control_index, i));
-                            }
-                            ui.checkbox(&mut component.use_spline, "")
+                                if ui.button("✏").clicked() {
+                                    selected = Some((control_index, i));
+                                }
+                                ui.checkbox(&mut component.use_spline, "")
+                            });
                         });
                     }
                 }
             })
         });
 
+        // Applied after the loop because overriding copies the components
+        for (control_index, is_overridden) in override_changes {
+            controls.used_controls[control_index].set_overridden(is_overridden);
+        }
+
         selected.map(|(control_index, component_index)| {
             (&controls.used_controls[control_index], component_index)
         })
@@ -0,0 +1,14 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) v_uv: vec2<f32>,
+}
+
+// Draws one large triangle over the clip space, use with a FullscreenTriangle draw item
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32(vertex_index / 2u), f32(vertex_index & 1u)) * 2.0;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.v_uv = uv;
+    return out;
+}